@@ -0,0 +1,108 @@
+//! Timings for the marcher's hot paths on a fixed scene. Run with
+//! `cargo bench`; each benchmark reports the median of several batches so
+//! runs are comparable from one change to the next. Random numbers are
+//! seeded per pixel and frame, so every run renders exactly the same work.
+//!
+//! This is a small timer of its own rather than a criterion benchmark, so
+//! that the crate builds without any dev-dependencies; the median over
+//! batches is the statistic these comparisons need.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use sdf::camera::Camera;
+use sdf::light::PointLight;
+use sdf::*;
+
+/// Batches timed per benchmark, and the time each batch should take.
+const BATCHES: usize = 15;
+const BATCH_TIME: Duration = Duration::from_millis(100);
+
+fn scene() -> Scene {
+    (0..4)
+        .fold(SceneBuilder::new(), |builder, i| {
+            let x = i as FLOAT * 1.5 - 2.25;
+            builder.add(And {
+                t: Cube {
+                    center: Vec3::new(x, 0.0, 5.0),
+                    size: 0.5,
+                },
+                u: Sphere {
+                    center: Vec3::new(x, 0.0, 5.0),
+                    radius: 0.65,
+                },
+            })
+        })
+        .ground(GroundPlane::new(-1.0))
+        .light(PointLight::new(Vec3::new(2.0, 4.0, 1.0), Vec3::splat(20.0)))
+        .build()
+}
+
+/// Times `f` in batches sized to take about [`BATCH_TIME`] and prints the
+/// median time per call. A first, untimed call sizes the batches and warms
+/// up anything built lazily.
+fn bench(name: &str, mut f: impl FnMut()) {
+    f();
+    let start = Instant::now();
+    f();
+    let once = start.elapsed().max(Duration::from_nanos(1));
+    let iterations = (BATCH_TIME.as_nanos() / once.as_nanos()).clamp(1, 1_000_000) as u32;
+    let mut times: Vec<Duration> = (0..BATCHES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                f();
+            }
+            start.elapsed() / iterations
+        })
+        .collect();
+    times.sort();
+    let (low, median, high) = (times[0], times[BATCHES / 2], times[BATCHES - 1]);
+    println!("{name:<16} {median:>12.2?}   [{low:.2?} .. {high:.2?}]");
+}
+
+fn main() {
+    let scene = scene();
+    let points: Vec<Vec3> = (0..64)
+        .map(|i| {
+            let a = i as FLOAT * 0.37;
+            Vec3::new(a.sin() * 3.0, a.cos() * 1.5, 5.0 + (a * 0.5).sin() * 2.0)
+        })
+        .collect();
+    bench("scene_distance", || {
+        for &p in &points {
+            black_box(scene.distance(black_box(p)));
+        }
+    });
+
+    let rays: Vec<Ray> = (0..64)
+        .map(|i| {
+            let a = i as FLOAT / 64.0;
+            Ray::new(
+                Vec3::ZERO,
+                Vec3::new(a - 0.5, 0.3 - a * 0.6, 1.0).normalize(),
+            )
+        })
+        .collect();
+    bench("raycast", || {
+        for ray in &rays {
+            black_box(raycast(black_box(ray), &scene));
+        }
+    });
+
+    let camera = Camera::default();
+    let config = RenderConfig {
+        width: 64,
+        height: 48,
+        samples: 2,
+        threads: Some(1),
+        ..RenderConfig::default()
+    };
+    // The single thread pool is built by the untimed first frame and reused
+    // by every timed one.
+    let mut buffer = Vec::new();
+    bench("render_frame", || {
+        render_frame(&scene, &camera, &config, &mut buffer);
+        black_box(&buffer);
+    });
+}
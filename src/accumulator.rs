@@ -0,0 +1,158 @@
+use crate::color::luminance;
+use crate::{Vec3, FLOAT};
+
+/// Running sum of rendered frames for progressive refinement of a static
+/// view. Anything that changes the image (camera movement, scene edits,
+/// render settings) must call [`Accumulator::mark_dirty`], otherwise stale
+/// frames keep ghosting into the average.
+pub struct Accumulator {
+    pub accum: Vec<Vec3>,
+    pub frame_count: u32,
+    /// Weighted running sum of each frame's squared luminance, for
+    /// [`Accumulator::variance`].
+    accum_sq: Vec<FLOAT>,
+    /// Total weight of the accumulated frames, see [`Accumulator::add_weighted`].
+    weight: FLOAT,
+    dirty: bool,
+}
+
+impl Accumulator {
+    pub fn new(size: usize) -> Accumulator {
+        Accumulator {
+            accum: vec![Vec3::ZERO; size],
+            frame_count: 0,
+            accum_sq: vec![0.0; size],
+            weight: 0.0,
+            dirty: false,
+        }
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Discards the accumulated frames if the view changed since the last
+    /// call. Call at the top of each frame, before [`Accumulator::add`].
+    pub fn begin_frame(&mut self) {
+        if self.dirty {
+            self.accum.fill(Vec3::ZERO);
+            self.frame_count = 0;
+            self.accum_sq.fill(0.0);
+            self.weight = 0.0;
+            self.dirty = false;
+        }
+    }
+
+    pub fn add(&mut self, frame: &[Vec3]) {
+        self.add_weighted(frame, 1.0);
+    }
+
+    /// Adds a frame that counts `weight` times in the average, e.g. its
+    /// sample count when frames are rendered with different sample counts.
+    pub fn add_weighted(&mut self, frame: &[Vec3], weight: FLOAT) {
+        for ((sum, sum_sq), color) in self.accum.iter_mut().zip(&mut self.accum_sq).zip(frame) {
+            *sum += *color * weight;
+            *sum_sq += luminance(*color).powi(2) * weight;
+        }
+        self.frame_count += 1;
+        self.weight += weight;
+    }
+
+    /// Writes the weighted average of all accumulated frames into `buffer`.
+    pub fn resolve(&self, buffer: &mut [Vec3]) {
+        let scale = if self.weight > 0.0 { 1.0 / self.weight } else { 1.0 };
+        for (out, sum) in buffer.iter_mut().zip(&self.accum) {
+            *out = *sum * scale;
+        }
+    }
+
+    /// The largest variance of any pixel's averaged luminance, estimated
+    /// from the spread between frames, or infinity before there are two
+    /// frames to compare. A frame of weight `w` is taken to average `w`
+    /// samples, so the estimate holds for frames of different weights.
+    pub fn variance(&self) -> FLOAT {
+        if self.frame_count < 2 {
+            return FLOAT::INFINITY;
+        }
+        let frames = (self.frame_count - 1) as FLOAT;
+        self.accum
+            .iter()
+            .zip(&self.accum_sq)
+            .map(|(sum, sum_sq)| {
+                let mean = luminance(*sum) / self.weight;
+                let spread = (sum_sq - self.weight * mean * mean).max(0.0);
+                spread / (frames * self.weight)
+            })
+            .fold(0.0, FLOAT::max)
+    }
+}
+
+/// The samples per pixel for the `frame`th frame of a still view: starts at
+/// `min` and doubles every frame up to `max`, so a view that stops moving
+/// quickly refines from a cheap interactive count to a high quality one.
+pub fn sample_ramp(frame: u32, min: usize, max: usize) -> usize {
+    min.max(1).saturating_mul(1 << frame.min(usize::BITS - 1)).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marking_dirty_resets_the_history() {
+        let mut accumulator = Accumulator::new(2);
+        accumulator.begin_frame();
+        accumulator.add(&[Vec3::ONE, Vec3::ZERO]);
+        accumulator.add(&[Vec3::ZERO, Vec3::ONE]);
+        assert_eq!(accumulator.frame_count, 2);
+
+        // Nothing is discarded until the next frame begins.
+        accumulator.mark_dirty();
+        assert!(accumulator.is_dirty());
+        assert_eq!(accumulator.frame_count, 2);
+        accumulator.begin_frame();
+        assert!(!accumulator.is_dirty());
+        assert_eq!(accumulator.frame_count, 0);
+        assert_eq!(accumulator.variance(), FLOAT::INFINITY);
+
+        let frame = [Vec3::new(0.2, 0.4, 0.6), Vec3::splat(3.0)];
+        accumulator.add(&frame);
+        let mut buffer = [Vec3::ZERO; 2];
+        accumulator.resolve(&mut buffer);
+        assert_eq!(buffer, frame);
+    }
+
+    #[test]
+    fn weighted_frames_average_like_repeated_frames() {
+        let a = [Vec3::new(0.2, 0.4, 0.6), Vec3::splat(1.0)];
+        let b = [Vec3::new(1.0, 0.0, 0.5), Vec3::splat(4.0)];
+        let mut weighted = Accumulator::new(2);
+        weighted.add_weighted(&a, 2.0);
+        weighted.add_weighted(&b, 1.0);
+        let mut repeated = Accumulator::new(2);
+        for frame in [&a, &a, &b] {
+            repeated.add(frame);
+        }
+        let (mut left, mut right) = ([Vec3::ZERO; 2], [Vec3::ZERO; 2]);
+        weighted.resolve(&mut left);
+        repeated.resolve(&mut right);
+        for ((l, r), (a, b)) in left.iter().zip(&right).zip(a.iter().zip(&b)) {
+            let mean = (*a * 2.0 + *b) / 3.0;
+            assert!((*l - mean).length() < 1e-6 && (*r - mean).length() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn sample_ramp_rises_to_its_cap() {
+        let ramp: Vec<usize> = (0..12).map(|frame| sample_ramp(frame, 2, 100)).collect();
+        assert_eq!(ramp[0], 2);
+        assert!(ramp.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(*ramp.last().unwrap(), 100);
+        assert_eq!(sample_ramp(u32::MAX, 2, 100), 100);
+        assert_eq!(sample_ramp(0, 0, 100), 1);
+    }
+}
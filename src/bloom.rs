@@ -0,0 +1,98 @@
+use crate::color::luminance;
+use crate::{FLOAT, Vec3};
+
+/// Blur radius of the glow in pixels.
+const RADIUS: usize = 8;
+
+/// Makes bright parts of a linear `width` x `height` buffer glow into their
+/// surroundings. The light above `threshold` luminance is extracted, blurred
+/// with a Gaussian and added back scaled by `intensity`; pixels at or below
+/// the threshold contribute nothing, so dim images are left untouched.
+///
+/// Run it before tone mapping, while highlights are still above 1.
+pub fn apply_bloom(
+    buffer: &mut [Vec3],
+    width: usize,
+    height: usize,
+    threshold: FLOAT,
+    intensity: FLOAT,
+) {
+    let bright: Vec<Vec3> = buffer
+        .iter()
+        .map(|&color| {
+            let l = luminance(color);
+            if l > threshold {
+                color * ((l - threshold) / l)
+            } else {
+                Vec3::ZERO
+            }
+        })
+        .collect();
+    if bright.iter().all(|&c| c == Vec3::ZERO) {
+        return;
+    }
+    let sigma = RADIUS as FLOAT / 3.0;
+    let weights: Vec<FLOAT> = (0..=RADIUS)
+        .map(|i| (-((i * i) as FLOAT) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total = weights[0] + 2.0 * weights[1..].iter().sum::<FLOAT>();
+    let weights: Vec<FLOAT> = weights.iter().map(|w| w / total).collect();
+    let horizontal = blur(&bright, width, height, &weights, 1, 0);
+    let glow = blur(&horizontal, width, height, &weights, 0, 1);
+    for (color, glow) in buffer.iter_mut().zip(glow) {
+        *color += glow * intensity;
+    }
+}
+
+/// One pass of a separable blur along (`dx`, `dy`). Light that would land
+/// outside the image is lost, so edges fade out rather than piling up.
+fn blur(
+    source: &[Vec3],
+    width: usize,
+    height: usize,
+    weights: &[FLOAT],
+    dx: usize,
+    dy: usize,
+) -> Vec<Vec3> {
+    (0..width * height)
+        .map(|pos| {
+            let (x, y) = (pos % width, pos / width);
+            let mut sum = source[pos] * weights[0];
+            for (i, &w) in weights.iter().enumerate().skip(1) {
+                if x + i * dx < width && y + i * dy < height {
+                    sum += source[(y + i * dy) * width + x + i * dx] * w;
+                }
+                if x >= i * dx && y >= i * dy {
+                    sum += source[(y - i * dy) * width + x - i * dx] * w;
+                }
+            }
+            sum
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dim_pixels_are_left_alone() {
+        let (width, height) = (24, 16);
+        let dim: Vec<Vec3> = (0..width * height)
+            .map(|i| Vec3::new(0.3, 0.5, 0.2) * ((i % 7) as FLOAT / 7.0))
+            .collect();
+        let mut buffer = dim.clone();
+        apply_bloom(&mut buffer, width, height, 1.0, 2.0);
+        assert_eq!(buffer, dim);
+
+        // A single bright pixel glows into its dim neighbours and loses none
+        // of its own light.
+        let centre = 8 * width + 12;
+        buffer[centre] = Vec3::splat(10.0);
+        let before = buffer.clone();
+        apply_bloom(&mut buffer, width, height, 1.0, 2.0);
+        assert!(buffer[centre].x > 10.0);
+        assert!(buffer[centre + 2].x > before[centre + 2].x);
+        assert_eq!(buffer[0], before[0]);
+    }
+}
@@ -0,0 +1,577 @@
+use crate::precision::quat_from_mat3;
+use crate::{FLOAT, MIN_DISTANCE, Mat3, Quat, Ray, Scene, Vec3, random_float, raycast};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    pub look_at: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view in radians, for perspective projection.
+    pub fov: FLOAT,
+    /// Casts parallel rays from points spread across the image plane instead
+    /// of from a single eye point.
+    pub ortho: bool,
+    /// Height of the view in world units, for orthographic projection.
+    pub ortho_height: FLOAT,
+    /// Scene times at which the shutter opens and closes. Each ray is taken
+    /// at a random time in between, and motion blur spans the interval; an
+    /// equal open and close time freezes the scene at that instant.
+    pub shutter_open: FLOAT,
+    pub shutter_close: FLOAT,
+    /// Radius of the lens in world units. Zero gives a pinhole camera with
+    /// everything in focus; larger values blur what lies off the focal plane.
+    pub aperture: FLOAT,
+    /// Distance along the view direction of the plane in perfect focus.
+    pub focus_distance: FLOAT,
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera {
+            position: Vec3::ZERO,
+            look_at: Vec3::Z,
+            up: Vec3::Y,
+            fov: crate::consts::FRAC_PI_2,
+            ortho: false,
+            ortho_height: 2.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            aperture: 0.0,
+            focus_distance: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    /// Right, up and forward unit vectors of the view.
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = (self.look_at - self.position).normalize();
+        let right = self.up.cross(forward).normalize();
+        let up = forward.cross(right);
+        (right, up, forward)
+    }
+
+    /// The ray through screen position (`u`, `v`), both in `[-1, 1]` with `v`
+    /// pointing up, for an image of the given aspect ratio. Its
+    /// [`Ray::time`] is drawn uniformly from the shutter interval.
+    pub fn get_ray(&self, u: FLOAT, v: FLOAT, aspect_ratio: FLOAT) -> Ray {
+        let (right, up, forward) = self.basis();
+        let mut ray = if self.ortho {
+            let half_height = self.ortho_height * 0.5;
+            let offset = right * (u * aspect_ratio * half_height) + up * (v * half_height);
+            Ray::new(self.position + offset, forward)
+        } else {
+            let half_height = (self.fov * 0.5).tan();
+            let direction =
+                forward + right * (u * aspect_ratio * half_height) + up * (v * half_height);
+            Ray::new(self.position, direction.normalize())
+        };
+        if self.aperture > 0.0 {
+            // Aim from a random point on the lens at where the pinhole ray
+            // meets the focal plane, so only that plane stays sharp.
+            let focus =
+                ray.position + ray.direction * (self.focus_distance / ray.direction.dot(forward));
+            let angle = random_float() * crate::consts::TAU;
+            let radius = self.aperture * random_float().sqrt();
+            ray.position += (right * angle.cos() + up * angle.sin()) * radius;
+            ray.direction = (focus - ray.position).normalize();
+        }
+        ray.time = if self.shutter_open == self.shutter_close {
+            self.shutter_open
+        } else {
+            self.shutter_time(random_float())
+        };
+        ray
+    }
+
+    /// Focuses on whatever is seen at screen position (`u`, `v`) in `scene`,
+    /// returning the new focus distance, or `None` leaving the focus as it
+    /// was if nothing is there.
+    pub fn focus_at(
+        &mut self,
+        scene: &Scene,
+        u: FLOAT,
+        v: FLOAT,
+        aspect_ratio: FLOAT,
+    ) -> Option<FLOAT> {
+        let pinhole = Camera {
+            aperture: 0.0,
+            ..self.clone()
+        };
+        let hit = raycast(&pinhole.get_ray(u, v, aspect_ratio), scene)?;
+        self.focus_distance = (hit.p - self.position).dot(self.basis().2);
+        Some(self.focus_distance)
+    }
+
+    /// Moves the camera back along its current view direction until the
+    /// whole of `scene`, as given by [`Scene::bounds`], fits in view at the
+    /// given aspect ratio, looking at the centre of the bounds. Returns the
+    /// distance to that centre, or `None` leaving the camera as it was if
+    /// the scene is empty or unbounded.
+    pub fn frame(&mut self, scene: &Scene, aspect_ratio: FLOAT) -> Option<FLOAT> {
+        let bounds = scene.bounds()?;
+        let center = bounds.center();
+        // Fitting the bounding sphere keeps the whole box in view whatever
+        // the direction it is seen from.
+        let radius = (bounds.size().length() * 0.5).max(MIN_DISTANCE);
+        let forward = (self.look_at - self.position).normalize_or(Vec3::Z);
+        let distance = if self.ortho {
+            self.ortho_height = 2.0 * radius * (1.0 / aspect_ratio).max(1.0);
+            2.0 * radius
+        } else {
+            let half_height = self.fov * 0.5;
+            let half_width = (half_height.tan() * aspect_ratio).atan();
+            radius / half_height.min(half_width).sin()
+        };
+        self.look_at = center;
+        self.position = center - forward * distance;
+        Some(distance)
+    }
+
+    /// The time a fraction `u` of the way through the shutter interval.
+    pub fn shutter_time(&self, u: FLOAT) -> FLOAT {
+        self.shutter_open + (self.shutter_close - self.shutter_open) * u
+    }
+
+    /// The radius of the cone covered by a pixel of an image `height` pixels
+    /// tall, as its radius at the camera and its growth per unit distance.
+    pub fn pixel_cone(&self, height: usize) -> (FLOAT, FLOAT) {
+        if self.ortho {
+            (0.5 * self.ortho_height / height as FLOAT, 0.0)
+        } else {
+            (0.0, (self.fov * 0.5).tan() / height as FLOAT)
+        }
+    }
+
+    /// The inverse of [`Camera::get_ray`]: the screen position (`u`, `v`)
+    /// whose ray passes through `point`, or `None` if the point is behind
+    /// the camera. The result may lie outside `[-1, 1]` for points out of view.
+    pub fn project(&self, point: Vec3, aspect_ratio: FLOAT) -> Option<(FLOAT, FLOAT)> {
+        let (right, up, forward) = self.basis();
+        let local = point - self.position;
+        let depth = local.dot(forward);
+        if depth <= 0.0 {
+            return None;
+        }
+        let half_height = if self.ortho {
+            self.ortho_height * 0.5
+        } else {
+            (self.fov * 0.5).tan() * depth
+        };
+        Some((
+            local.dot(right) / (aspect_ratio * half_height),
+            local.dot(up) / half_height,
+        ))
+    }
+
+    /// The camera as a RON struct that can be pasted into a scene file, with
+    /// floats printed so they parse back to exactly the same values.
+    pub fn to_ron_string(&self) -> String {
+        let v = |v: Vec3| format!("({:?}, {:?}, {:?})", v.x, v.y, v.z);
+        format!(
+            "Camera(position: {}, look_at: {}, up: {}, fov: {:?}, ortho: {}, ortho_height: {:?}, \
+             shutter_open: {:?}, shutter_close: {:?}, aperture: {:?}, focus_distance: {:?})",
+            v(self.position),
+            v(self.look_at),
+            v(self.up),
+            self.fov,
+            self.ortho,
+            self.ortho_height,
+            self.shutter_open,
+            self.shutter_close,
+            self.aperture,
+            self.focus_distance,
+        )
+    }
+
+    /// Parses the output of [`Camera::to_ron_string`]. Fields may appear in
+    /// any order and missing ones keep their defaults; returns `None` if the
+    /// text is not a camera.
+    pub fn from_ron_str(text: &str) -> Option<Camera> {
+        let body = text.trim().strip_prefix("Camera(")?.strip_suffix(')')?;
+        let mut camera = Camera::default();
+        let mut rest = body.trim();
+        while !rest.is_empty() {
+            let (name, value) = rest.split_once(':')?;
+            let value = value.trim_start();
+            let end = if value.starts_with('(') {
+                value.find(')')? + 1
+            } else {
+                value.find(',').unwrap_or(value.len())
+            };
+            let (field, tail) = value.split_at(end);
+            let vector = || {
+                let inner = field.strip_prefix('(')?.strip_suffix(')')?;
+                let mut parts = inner
+                    .split(',')
+                    .map(|part| part.trim().parse::<FLOAT>().ok());
+                let v = Vec3::new(parts.next()??, parts.next()??, parts.next()??);
+                parts.next().is_none().then_some(v)
+            };
+            match name.trim() {
+                "position" => camera.position = vector()?,
+                "look_at" => camera.look_at = vector()?,
+                "up" => camera.up = vector()?,
+                "fov" => camera.fov = field.trim().parse().ok()?,
+                "ortho" => camera.ortho = field.trim().parse().ok()?,
+                "ortho_height" => camera.ortho_height = field.trim().parse().ok()?,
+                "shutter_open" => camera.shutter_open = field.trim().parse().ok()?,
+                "shutter_close" => camera.shutter_close = field.trim().parse().ok()?,
+                "aperture" => camera.aperture = field.trim().parse().ok()?,
+                "focus_distance" => camera.focus_distance = field.trim().parse().ok()?,
+                _ => return None,
+            }
+            rest = tail
+                .trim_start()
+                .strip_prefix(',')
+                .unwrap_or(tail)
+                .trim_start();
+        }
+        Some(camera)
+    }
+}
+
+/// A camera pose at a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    pub position: Vec3,
+    pub look_at: Vec3,
+    pub time: FLOAT,
+}
+
+/// A fly-through defined by keyframes in increasing time order. Positions
+/// follow a Catmull-Rom spline through the keyframes and the viewing
+/// direction is slerped between them.
+#[derive(Debug, Clone, Default)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    /// The camera at `time`, clamped to the first and last keyframes. Fields
+    /// other than the pose come from `template`.
+    pub fn sample(&self, time: FLOAT, template: &Camera) -> Camera {
+        let keys = &self.keyframes;
+        let pose = |position: Vec3, look_at: Vec3| Camera {
+            position,
+            look_at,
+            ..template.clone()
+        };
+        match keys.as_slice() {
+            [] => return template.clone(),
+            [only] => return pose(only.position, only.look_at),
+            _ => {}
+        }
+        let last = keys.len() - 1;
+        if time <= keys[0].time {
+            return pose(keys[0].position, keys[0].look_at);
+        }
+        if time >= keys[last].time {
+            return pose(keys[last].position, keys[last].look_at);
+        }
+        let i = keys
+            .windows(2)
+            .position(|w| time < w[1].time)
+            .unwrap_or(last - 1);
+        let (k1, k2) = (&keys[i], &keys[i + 1]);
+        let span = k2.time - k1.time;
+        let u = if span > 0.0 {
+            (time - k1.time) / span
+        } else {
+            0.0
+        };
+
+        let p0 = keys[i.saturating_sub(1)].position;
+        let p3 = keys[(i + 2).min(last)].position;
+        let position = catmull_rom(p0, k1.position, k2.position, p3, u);
+
+        let r1 = orientation(k1.position, k1.look_at);
+        let r2 = orientation(k2.position, k2.look_at);
+        let rotation = r1.slerp(r2, u);
+        let d1 = (k1.look_at - k1.position).length();
+        let d2 = (k2.look_at - k2.position).length();
+        let forward = rotation * Vec3::Z;
+        Camera {
+            up: rotation * Vec3::Y,
+            ..pose(position, position + forward * (d1 + (d2 - d1) * u))
+        }
+    }
+}
+
+/// A turntable orbit for product-style presentation: the camera circles
+/// `center` at a fixed `radius` and `elevation` (radians above the XZ
+/// plane, short of a right angle), always looking at the centre, turning at
+/// `speed` radians per unit of time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turntable {
+    pub center: Vec3,
+    pub radius: FLOAT,
+    pub elevation: FLOAT,
+    pub speed: FLOAT,
+}
+
+impl Turntable {
+    /// The camera position at `angle` radians around the orbit, measured
+    /// about the Y axis from the -Z side of the centre, so angle 0 looks
+    /// along +Z like the default camera.
+    pub fn position(&self, angle: FLOAT) -> Vec3 {
+        let (sin, cos) = angle.sin_cos();
+        let (rise, across) = self.elevation.sin_cos();
+        self.center + Vec3::new(sin * across, rise, -cos * across) * self.radius
+    }
+
+    /// The camera at `time`. Fields other than the pose come from
+    /// `template`.
+    pub fn sample(&self, time: FLOAT, template: &Camera) -> Camera {
+        Camera {
+            position: self.position(time * self.speed),
+            look_at: self.center,
+            up: Vec3::Y,
+            ..template.clone()
+        }
+    }
+}
+
+fn orientation(position: Vec3, look_at: Vec3) -> Quat {
+    let forward = (look_at - position).normalize();
+    let right = Vec3::Y.cross(forward).normalize();
+    let up = forward.cross(right);
+    quat_from_mat3(&Mat3::from_cols(right, up, forward))
+}
+
+/// Uniform Catmull-Rom interpolation between `p1` (at `u = 0`) and `p2` (at
+/// `u = 1`), using `p0` and `p3` as the neighbouring control points.
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, u: FLOAT) -> Vec3 {
+    let u2 = u * u;
+    let u3 = u2 * u;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * u
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turntable() -> Turntable {
+        Turntable {
+            center: Vec3::new(1.0, -0.5, 4.0),
+            radius: 5.0,
+            elevation: 0.3,
+            speed: 0.5,
+        }
+    }
+
+    #[test]
+    fn turntable_keeps_its_radius_and_height() {
+        let orbit = turntable();
+        let height = orbit.center.y + orbit.radius * orbit.elevation.sin();
+        for i in 0..64 {
+            let position = orbit.position(i as FLOAT * 0.37);
+            assert!(((position - orbit.center).length() - orbit.radius).abs() < 1e-4);
+            assert!((position.y - height).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn turntable_always_looks_at_the_center() {
+        let orbit = turntable();
+        let template = Camera {
+            fov: 0.5,
+            ..Camera::default()
+        };
+        for i in 0..16 {
+            let camera = orbit.sample(i as FLOAT * 0.8, &template);
+            assert_eq!(camera.look_at, orbit.center);
+            assert_eq!(camera.fov, template.fov);
+            let (_, _, forward) = camera.basis();
+            let to_center = (orbit.center - camera.position).normalize();
+            assert!((forward - to_center).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn opposite_turntable_angles_are_antipodal() {
+        let orbit = turntable();
+        let start = orbit.position(0.0) - orbit.center;
+        let half = orbit.position(crate::consts::PI) - orbit.center;
+        assert!((start.x + half.x).abs() < 1e-4 && (start.z + half.z).abs() < 1e-4);
+        assert!((start.y - half.y).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ortho_rays_are_parallel_from_spread_origins() {
+        let camera = Camera {
+            ortho: true,
+            ortho_height: 4.0,
+            ..Camera::default()
+        };
+        let a = camera.get_ray(-0.5, 0.25, 1.5);
+        let b = camera.get_ray(0.75, -1.0, 1.5);
+        assert!((a.direction - b.direction).length() < 1e-6);
+        assert!((a.direction - Vec3::Z).length() < 1e-6);
+        assert_ne!(a.position, b.position);
+        let offset = b.position - a.position;
+        assert!((offset - Vec3::new(1.25 * 1.5 * 2.0, -1.25 * 2.0, 0.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn camera_path_passes_through_its_keyframes() {
+        let path = CameraPath {
+            keyframes: vec![
+                Keyframe {
+                    position: Vec3::new(0.0, 0.0, 0.0),
+                    look_at: Vec3::new(0.0, 0.0, 4.0),
+                    time: 0.0,
+                },
+                Keyframe {
+                    position: Vec3::new(4.0, 2.0, 0.0),
+                    look_at: Vec3::new(0.0, 0.0, 4.0),
+                    time: 2.0,
+                },
+            ],
+        };
+        let template = Camera::default();
+        for key in &path.keyframes {
+            let camera = path.sample(key.time, &template);
+            assert_eq!(camera.position, key.position);
+            assert_eq!(camera.look_at, key.look_at);
+        }
+        assert_eq!(path.sample(-1.0, &template).position, Vec3::ZERO);
+        assert_eq!(
+            path.sample(5.0, &template).position,
+            Vec3::new(4.0, 2.0, 0.0)
+        );
+
+        let middle = path.sample(1.0, &template);
+        assert!((middle.position - Vec3::new(2.0, 1.0, 0.0)).length() < 1e-5);
+        // The view turns halfway between the two keyframe directions.
+        let (_, _, forward) = middle.basis();
+        let (_, _, first) = path.sample(0.0, &template).basis();
+        let (_, _, second) = path.sample(2.0, &template).basis();
+        let half = first.angle_between(second) * 0.5;
+        assert!((forward.angle_between(first) - half).abs() < 1e-3);
+        assert!((forward.angle_between(second) - half).abs() < 1e-3);
+    }
+
+    #[test]
+    fn ron_round_trips_every_field() {
+        let camera = Camera {
+            position: Vec3::new(1.25, -0.1, 3.0e-7),
+            look_at: Vec3::new(-2.0, 0.5, 10.0),
+            up: Vec3::new(0.0, 1.0, 0.1),
+            fov: 0.7,
+            ortho: true,
+            ortho_height: 3.3,
+            shutter_open: 0.1,
+            shutter_close: 0.35,
+            aperture: 0.05,
+            focus_distance: 7.5,
+        };
+        let text = camera.to_ron_string();
+        assert_eq!(Camera::from_ron_str(&text), Some(camera));
+        assert_eq!(
+            Camera::from_ron_str("Camera(fov: 0.5, position: (1.0, 2.0, 3.0))"),
+            Some(Camera {
+                fov: 0.5,
+                position: Vec3::new(1.0, 2.0, 3.0),
+                ..Camera::default()
+            })
+        );
+        assert_eq!(Camera::from_ron_str("Sphere(radius: 1.0)"), None);
+        assert_eq!(Camera::from_ron_str("Camera(zoom: 2.0)"), None);
+    }
+
+    #[test]
+    fn zero_length_shutter_freezes_ray_time() {
+        let frozen = Camera {
+            shutter_open: 0.4,
+            shutter_close: 0.4,
+            ..Camera::default()
+        };
+        let open = Camera {
+            shutter_open: 0.2,
+            shutter_close: 0.6,
+            ..Camera::default()
+        };
+        let mut times = Vec::new();
+        for i in 0..64 {
+            let u = i as FLOAT / 32.0 - 1.0;
+            assert_eq!(frozen.get_ray(u, -u, 1.0).time, 0.4);
+            let time = open.get_ray(u, -u, 1.0).time;
+            assert!((0.2..=0.6).contains(&time));
+            times.push(time);
+        }
+        assert!(times.iter().any(|&time| time != times[0]));
+    }
+
+    #[test]
+    fn focus_at_focuses_on_the_surface_seen() {
+        let scene = crate::SceneBuilder::new()
+            .add(crate::Sphere::new(Vec3::new(0.0, 0.0, 6.0), 1.0))
+            .build();
+        let mut camera = Camera {
+            aperture: 0.2,
+            focus_distance: 1.0,
+            ..Camera::default()
+        };
+        assert_eq!(
+            camera.focus_at(&scene, 0.0, 0.0, 1.0),
+            Some(camera.focus_distance)
+        );
+        assert!((camera.focus_distance - 5.0).abs() < 0.01);
+        // Off centre the distance is measured along the view axis.
+        let (u, v) = camera.project(Vec3::new(0.6, 0.0, 5.2), 1.0).unwrap();
+        let depth = camera.focus_at(&scene, u, v, 1.0).unwrap();
+        assert!((depth - 5.2).abs() < 0.01, "{depth}");
+        // Nothing to focus on leaves the focus alone.
+        assert_eq!(camera.focus_at(&scene, 0.9, 0.9, 1.0), None);
+        assert_eq!(camera.focus_distance, depth);
+    }
+
+    #[test]
+    fn framing_brings_every_bounds_corner_into_view() {
+        let scene = crate::SceneBuilder::new()
+            .add(crate::Sphere::new(Vec3::new(3.0, 1.0, 8.0), 1.0))
+            .add(crate::AaBox::new(
+                Vec3::new(-4.0, -1.0, 2.0),
+                Vec3::new(-2.0, 2.5, 5.0),
+            ))
+            .build();
+        let bounds = scene.bounds().unwrap();
+        for (ortho, aspect_ratio) in [(false, 1.0), (false, 2.0), (false, 0.5), (true, 1.5)] {
+            let mut camera = Camera {
+                ortho,
+                position: Vec3::new(1.0, 2.0, -1.0),
+                look_at: Vec3::new(0.5, 1.5, 0.0),
+                ..Camera::default()
+            };
+            let distance = camera.frame(&scene, aspect_ratio).unwrap();
+            assert_eq!(camera.look_at, bounds.center());
+            assert!(((camera.position - bounds.center()).length() - distance).abs() < 1e-4);
+            for i in 0..8 {
+                let corner = Vec3::new(
+                    if i & 1 == 0 {
+                        bounds.min.x
+                    } else {
+                        bounds.max.x
+                    },
+                    if i & 2 == 0 {
+                        bounds.min.y
+                    } else {
+                        bounds.max.y
+                    },
+                    if i & 4 == 0 {
+                        bounds.min.z
+                    } else {
+                        bounds.max.z
+                    },
+                );
+                let (u, v) = camera.project(corner, aspect_ratio).unwrap();
+                assert!(u.abs() <= 1.0 && v.abs() <= 1.0, "{corner} at ({u}, {v})");
+            }
+        }
+        assert_eq!(Camera::default().frame(&crate::Scene::new(), 1.0), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use crate::consts::PI;
+use crate::{FLOAT, Ray, Scene, Vec3, random_float, raycast, seed_rng};
+
+/// Photon paths are abandoned after this many specular bounces.
+const MAX_BOUNCES: usize = 8;
+
+/// A packet of light energy left on a diffuse surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Photon {
+    pub position: Vec3,
+    pub power: Vec3,
+}
+
+/// Caustic photons: light that reached diffuse surfaces only after passing
+/// through or reflecting off specular materials. Path tracing finds such
+/// paths only when a diffuse bounce happens to hit a small light, so
+/// caustics converge very slowly; shooting photons from the lights and
+/// gathering them around each hit estimates them directly, at the cost of
+/// some blur.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    /// Gather radius, also the cell size of the lookup grid.
+    radius: FLOAT,
+    grid: HashMap<(i32, i32, i32), Vec<usize>>,
+}
+
+impl PhotonMap {
+    /// Shoots `count` photons, shared equally among the scene's lights, and
+    /// keeps those that reach a diffuse surface via at least one specular
+    /// bounce. Their energy is later gathered within `radius` of each hit.
+    pub fn build(scene: &Scene, count: usize, radius: FLOAT) -> PhotonMap {
+        // A fixed seed makes the map, and so the caustics, reproducible.
+        seed_rng(0);
+        let mut photons = Vec::new();
+        let per_light = count / scene.lights.len().max(1);
+        for light in &scene.lights {
+            // A point light's intensity spread over the whole sphere.
+            let solid_angle = 4.0 * PI / per_light.max(1) as FLOAT;
+            for _ in 0..per_light {
+                let ray = Ray::new(light.position, uniform_sphere());
+                let power = light.emission(ray.direction) * solid_angle;
+                if let Some(photon) = trace_photon(scene, ray, power) {
+                    photons.push(photon);
+                }
+            }
+        }
+        let mut grid: HashMap<_, Vec<usize>> = HashMap::new();
+        for (index, photon) in photons.iter().enumerate() {
+            grid.entry(cell(photon.position, radius))
+                .or_default()
+                .push(index);
+        }
+        PhotonMap {
+            photons,
+            radius,
+            grid,
+        }
+    }
+
+    pub fn photons(&self) -> &[Photon] {
+        &self.photons
+    }
+
+    /// Caustic light arriving at a surface point `p` facing `normal`,
+    /// estimated from the photon density within the gather radius.
+    pub fn irradiance(&self, p: Vec3, normal: Vec3) -> Vec3 {
+        let (cx, cy, cz) = cell(p, self.radius);
+        let r2 = self.radius * self.radius;
+        let mut power = Vec3::ZERO;
+        for x in cx - 1..=cx + 1 {
+            for y in cy - 1..=cy + 1 {
+                for z in cz - 1..=cz + 1 {
+                    for &index in self.grid.get(&(x, y, z)).into_iter().flatten() {
+                        let photon = &self.photons[index];
+                        let offset = photon.position - p;
+                        // Ignore photons on surfaces that face another way,
+                        // such as the far side of a thin wall.
+                        if offset.length_squared() < r2
+                            && offset.dot(normal).abs() < self.radius * 0.5
+                        {
+                            power += photon.power;
+                        }
+                    }
+                }
+            }
+        }
+        power / (PI * r2)
+    }
+}
+
+fn trace_photon(scene: &Scene, mut ray: Ray, mut power: Vec3) -> Option<Photon> {
+    for bounce in 0..=MAX_BOUNCES {
+        let hit = raycast(&ray, scene)?;
+        let object = &scene.objects[hit.object];
+        let material = object.material.resolve(&hit);
+        if !material.is_specular() {
+            // Direct light is already handled by light sampling.
+            return (bounce > 0 && material.is_diffuse()).then_some(Photon {
+                position: hit.p,
+                power,
+            });
+        }
+        let scatter = material.scatter(&ray, &hit, object)?;
+        power *= scatter.attenuation;
+        ray = scatter.ray;
+    }
+    None
+}
+
+fn cell(p: Vec3, size: FLOAT) -> (i32, i32, i32) {
+    let c = (p / size).floor();
+    (c.x as i32, c.y as i32, c.z as i32)
+}
+
+fn uniform_sphere() -> Vec3 {
+    let z = 1.0 - 2.0 * random_float();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * random_float();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::luminance;
+    use crate::{GroundPlane, Material, PointLight, SceneBuilder, Sphere};
+
+    #[test]
+    fn glass_ball_focuses_photons_beneath_it() {
+        let scene = SceneBuilder::new()
+            .add_with_material(
+                Sphere::new(Vec3::new(0.0, 2.0, 0.0), 1.0),
+                Material::Dielectric {
+                    ior: 1.5,
+                    absorption: Vec3::ZERO,
+                    dispersion: None,
+                },
+            )
+            .ground(GroundPlane::new(-1.0))
+            .light(PointLight::new(Vec3::new(0.0, 6.0, 0.0), Vec3::splat(20.0)))
+            .build();
+        let map = PhotonMap::build(&scene, 20_000, 0.3);
+        assert!(!map.photons().is_empty());
+        // Only light that passed through the ball counts, all of it landing
+        // on the ground.
+        assert!(
+            map.photons()
+                .iter()
+                .all(|photon| (photon.position.y + 1.0).abs() < 1e-2)
+        );
+        let focus = map.irradiance(Vec3::new(0.0, -1.0, 0.0), Vec3::Y);
+        let aside = map.irradiance(Vec3::new(2.5, -1.0, 0.0), Vec3::Y);
+        assert!(
+            luminance(focus) > 10.0 * luminance(aside).max(1e-6),
+            "{focus} vs {aside}"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{FLOAT, MIN_DISTANCE, Sdf, Vec3};
+
+/// How far from 1 a gradient or normal length may be before
+/// [`assert_sdf_consistent`] rejects it. Central differences in `f32` are
+/// accurate to well within this away from creases.
+const TOLERANCE: FLOAT = 0.01;
+
+/// The gradient of `sdf` at `point` by central differences.
+pub fn gradient<T: Sdf + ?Sized>(sdf: &T, point: Vec3) -> Vec3 {
+    let h = MIN_DISTANCE;
+    let d = |offset: Vec3| sdf.distance(point + offset) - sdf.distance(point - offset);
+    Vec3::new(d(Vec3::X * h), d(Vec3::Y * h), d(Vec3::Z * h)) / (2.0 * h)
+}
+
+/// Checks that `sdf` behaves like a true distance field at each of `points`:
+/// its gradient has unit length, as the gradient of an exact distance always
+/// does, and [`Sdf::normal`] is a unit vector. A shape whose distance grows
+/// faster than 1 per unit overshoots when marched, and one that grows slower
+/// marches needlessly slowly, so primitives should pass everywhere but on
+/// creases and centres where the gradient is undefined; keep `points` clear
+/// of those. Panics naming the first point that fails.
+pub fn assert_sdf_consistent<T: Sdf + ?Sized>(sdf: &T, points: &[Vec3]) {
+    for &point in points {
+        let length = gradient(sdf, point).length();
+        assert!(
+            (length - 1.0).abs() <= TOLERANCE,
+            "gradient length {} at {} is not 1",
+            length,
+            point
+        );
+        let normal = sdf.normal(point).length();
+        assert!(
+            (normal - 1.0).abs() <= TOLERANCE,
+            "normal length {} at {} is not 1",
+            normal,
+            point
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sphere;
+
+    /// A sphere whose distance is doubled, so it grows twice as fast as a
+    /// true distance field and overshoots when marched.
+    struct Doubled(Sphere);
+
+    impl Sdf for Doubled {
+        fn distance(&self, point: Vec3) -> FLOAT {
+            self.0.distance(point) * 2.0
+        }
+    }
+
+    fn points() -> Vec<Vec3> {
+        vec![
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.3, -0.4, 0.2),
+            Vec3::new(-1.0, 1.5, 0.5),
+        ]
+    }
+
+    #[test]
+    fn sphere_is_consistent() {
+        assert_sdf_consistent(&Sphere::new(Vec3::ZERO, 1.0), &points());
+    }
+
+    #[test]
+    #[should_panic(expected = "gradient length")]
+    fn doubled_distance_is_rejected() {
+        assert_sdf_consistent(&Doubled(Sphere::new(Vec3::ZERO, 1.0)), &points());
+    }
+}
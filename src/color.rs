@@ -0,0 +1,114 @@
+use crate::consts::TAU;
+use crate::{FLOAT, Mat3, Vec3};
+
+/// Cosine gradient palette, `a + b * cos(2π(c * t + d))`, evaluated per
+/// channel. Useful for mapping a scalar such as depth or step count to a
+/// smooth color ramp; with `a = b = 0.5` the result stays within `[0, 1]`.
+pub fn palette(t: FLOAT, a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> Vec3 {
+    let phase = (c * t + d) * TAU;
+    a + b * Vec3::new(phase.x.cos(), phase.y.cos(), phase.z.cos())
+}
+
+/// A rainbow-like preset of [`palette`].
+pub fn rainbow(t: FLOAT) -> Vec3 {
+    palette(
+        t,
+        Vec3::splat(0.5),
+        Vec3::splat(0.5),
+        Vec3::ONE,
+        Vec3::new(0.0, 0.33, 0.67),
+    )
+}
+
+/// Perceived brightness of a linear Rec. 709 color.
+pub fn luminance(color: Vec3) -> FLOAT {
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// Operator used to bring linear radiance into displayable `[0, 1]` range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMap {
+    /// Passes colors through unchanged; out of range values are clamped on
+    /// output.
+    #[default]
+    Clamp,
+    /// Troy Sobotka's AgX, which desaturates very bright colors towards
+    /// white instead of clipping channels, so saturated highlights keep a
+    /// plausible hue. The result is display encoded.
+    AgX,
+}
+
+pub fn tone_map(color: Vec3, operator: ToneMap) -> Vec3 {
+    match operator {
+        ToneMap::Clamp => color,
+        ToneMap::AgX => agx(color),
+    }
+}
+
+/// Applies `operator` to every pixel of a linear buffer.
+pub fn tone_map_buffer(buffer: &mut [Vec3], operator: ToneMap) {
+    for color in buffer {
+        *color = tone_map(*color, operator);
+    }
+}
+
+fn agx(color: Vec3) -> Vec3 {
+    const MIN_EV: FLOAT = -12.473_931;
+    const MAX_EV: FLOAT = 4.026_069;
+    let inset = Mat3::from_cols(
+        Vec3::new(0.842_479_06, 0.042_328_24, 0.042_375_65),
+        Vec3::new(0.078_433_6, 0.878_468_6, 0.078_433_6),
+        Vec3::new(0.079_223_75, 0.079_166_13, 0.879_143),
+    );
+    let outset = Mat3::from_cols(
+        Vec3::new(1.196_879, -0.052_896_85, -0.052_971_64),
+        Vec3::new(-0.098_020_88, 1.151_903_1, -0.098_043_45),
+        Vec3::new(-0.099_029_74, -0.098_961_18, 1.151_073_7),
+    );
+    let v = inset * color.max(Vec3::splat(1e-10));
+    let v = Vec3::new(v.x.log2(), v.y.log2(), v.z.log2())
+        .clamp(Vec3::splat(MIN_EV), Vec3::splat(MAX_EV));
+    let x = (v - Vec3::splat(MIN_EV)) / (MAX_EV - MIN_EV);
+    // Polynomial fit of the default AgX sigmoid contrast curve.
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    let curve =
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - Vec3::splat(0.002_32);
+    (outset * curve).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_endpoints_hit_their_colors() {
+        let (a, b) = (Vec3::new(0.5, 0.4, 0.3), Vec3::new(0.5, 0.2, 0.1));
+        let half = Vec3::splat(0.5);
+        assert!((palette(0.0, a, b, half, Vec3::ZERO) - (a + b)).length() < 1e-6);
+        assert!((palette(1.0, a, b, half, Vec3::ZERO) - (a - b)).length() < 1e-6);
+        for i in 0..=20 {
+            let color = rainbow(i as FLOAT / 20.0);
+            assert!(color.cmpge(Vec3::ZERO).all() && color.cmple(Vec3::ONE).all());
+        }
+    }
+
+    #[test]
+    fn agx_is_monotonic_within_range_and_keeps_red_red() {
+        assert!(tone_map(Vec3::ZERO, ToneMap::AgX).max_element() < 1e-3);
+        let mut last = Vec3::splat(-1.0);
+        for i in 0..=40 {
+            let exposure = (i as FLOAT * 0.4 - 10.0).exp2();
+            let mapped = tone_map(Vec3::splat(exposure), ToneMap::AgX);
+            assert!(mapped.cmpge(Vec3::ZERO).all() && mapped.cmple(Vec3::ONE).all());
+            assert!(mapped.cmpge(last).all());
+            last = mapped;
+        }
+        for intensity in [0.5, 2.0, 8.0] {
+            let red = tone_map(Vec3::new(intensity, 0.0, 0.0), ToneMap::AgX);
+            assert!(red.x > red.y && red.x > red.z);
+            assert!((red.y - red.z).abs() < 0.05);
+        }
+    }
+}
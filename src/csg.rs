@@ -0,0 +1,242 @@
+//! A tiny constructive solid geometry language for quick experiments, e.g.
+//! `sphere(1) & box(0.75) - sphere(0.5, 0, 0, -1)`.
+//!
+//! Shapes are `sphere(radius)`, `box(half_size)` for a cube or
+//! `box(x, y, z)` for a box of those half extents, and `plane(height)` for
+//! the ground. Sphere and box accept an optional center as three more
+//! arguments after their size. `a & b` intersects, `a - b` subtracts and
+//! `a | b` joins; `&` and `-` bind tighter than `|`, operators of equal
+//! precedence group from the left, and parentheses group explicitly.
+
+use std::fmt;
+
+use crate::{AaBox, And, Cube, Not, Or, Plane, Scene, Sdf, Sphere, Vec3};
+
+/// Why an expression could not be parsed, and the byte offset at which the
+/// problem was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses `text` into a shape.
+pub fn parse(text: &str) -> Result<Box<dyn Sdf>, ParseError> {
+    let mut parser = Parser { text, offset: 0 };
+    let shape = parser.union()?;
+    parser.skip_whitespace();
+    if parser.offset < text.len() {
+        return Err(parser.error("expected an operator"));
+    }
+    Ok(shape)
+}
+
+/// Parses `text` into a scene holding the shape with the default material.
+pub fn parse_scene(text: &str) -> Result<Scene, ParseError> {
+    let mut scene = Scene::new();
+    scene.add(parse(text)?);
+    Ok(scene)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            offset: self.offset,
+            message: message.to_string(),
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.offset..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `symbol` if it comes next.
+    fn eat(&mut self, symbol: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(symbol) {
+            self.offset += symbol.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ParseError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn union(&mut self) -> Result<Box<dyn Sdf>, ParseError> {
+        let mut shape = self.intersection()?;
+        while self.eat('|') {
+            let u = self.intersection()?;
+            shape = Box::new(Or { t: shape, u });
+        }
+        Ok(shape)
+    }
+
+    fn intersection(&mut self) -> Result<Box<dyn Sdf>, ParseError> {
+        let mut shape = self.primary()?;
+        loop {
+            if self.eat('&') {
+                let u = self.primary()?;
+                shape = Box::new(And { t: shape, u });
+            } else if self.eat('-') {
+                let u = self.primary()?;
+                shape = Box::new(Not::new(shape, u));
+            } else {
+                return Ok(shape);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Box<dyn Sdf>, ParseError> {
+        if self.eat('(') {
+            let shape = self.union()?;
+            self.expect(')')?;
+            return Ok(shape);
+        }
+        self.skip_whitespace();
+        let start = self.offset;
+        let length = self
+            .rest()
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(self.rest().len());
+        self.offset += length;
+        let name = &self.text[start..self.offset];
+        if name.is_empty() {
+            return Err(self.error("expected a shape"));
+        }
+        self.expect('(')?;
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.number()?);
+                if self.eat(')') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        // Radii and sizes come first; check them here rather than let the
+        // shape constructors panic.
+        let sizes = match (name, args.len()) {
+            ("box", 3) => 3,
+            ("sphere" | "box", n) => n.min(1),
+            _ => 0,
+        };
+        if args[..sizes].iter().any(|&size| size <= 0.0) {
+            self.offset = start;
+            return Err(self.error(&format!("{} size must be positive", name)));
+        }
+        let center = |i: usize| Vec3::new(args[i], args[i + 1], args[i + 2]);
+        let shape: Box<dyn Sdf> = match (name, args.len()) {
+            ("sphere", 1) => Box::new(Sphere::new(Vec3::ZERO, args[0])),
+            ("sphere", 4) => Box::new(Sphere::new(center(1), args[0])),
+            ("box", 1) => Box::new(Cube::new(Vec3::ZERO, args[0])),
+            ("box", 3) => Box::new(AaBox::new(-center(0), center(0))),
+            ("box", 4) => Box::new(Cube::new(center(1), args[0])),
+            ("plane", 1) => Box::new(Plane::new(Vec3::Y, args[0])),
+            ("sphere" | "box" | "plane", _) => {
+                self.offset = start;
+                return Err(self.error(&format!("wrong number of arguments to {}", name)));
+            }
+            _ => {
+                self.offset = start;
+                return Err(self.error("expected a shape"));
+            }
+        };
+        Ok(shape)
+    }
+
+    fn number(&mut self) -> Result<crate::FLOAT, ParseError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let length = rest
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+            .map_or(rest.len(), |(i, _)| i);
+        let value = rest[..length]
+            .parse()
+            .map_err(|_| self.error("expected a number"))?;
+        self.offset += length;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_points() -> impl Iterator<Item = Vec3> {
+        (0..64).map(|i| {
+            let a = i as crate::FLOAT * 0.61;
+            Vec3::new(a.sin(), (a * 0.8).cos(), (a * 1.7).sin()) * 1.5
+        })
+    }
+
+    #[test]
+    fn intersection_parses_to_and() {
+        let parsed = parse("sphere(1) & box(0.75)").unwrap();
+        let built = And {
+            t: Sphere::new(Vec3::ZERO, 1.0),
+            u: Cube::new(Vec3::ZERO, 0.75),
+        };
+        for p in sample_points() {
+            assert_eq!(parsed.distance(p), built.distance(p));
+        }
+    }
+
+    #[test]
+    fn operators_bind_and_group_as_documented() {
+        let parsed = parse("box(1) - sphere(0.5, 0, 0, -1) | sphere(0.25, 1, 1, 1)").unwrap();
+        let built = Or {
+            t: Not::new(
+                Cube::new(Vec3::ZERO, 1.0),
+                Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5),
+            ),
+            u: Sphere::new(Vec3::ONE, 0.25),
+        };
+        let grouped = parse("box(1) - (sphere(0.5, 0, 0, -1) | sphere(0.25, 1, 1, 1))").unwrap();
+        let mut differ = false;
+        for p in sample_points() {
+            assert_eq!(parsed.distance(p), built.distance(p));
+            differ |= grouped.distance(p) != built.distance(p);
+        }
+        assert!(differ);
+    }
+
+    #[test]
+    fn errors_report_where_parsing_stopped() {
+        let error = |text: &str| parse(text).err().unwrap();
+        assert_eq!(error("sphere(1) &").offset, 11);
+        assert_eq!(error("cone(1)").message, "expected a shape");
+        assert_eq!(
+            error("sphere(1, 2)").message,
+            "wrong number of arguments to sphere"
+        );
+        assert_eq!(error("box(1) box(2)").message, "expected an operator");
+        assert_eq!(error("box(0, 1, 1)").message, "box size must be positive");
+    }
+}
@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use crate::color::luminance;
+use crate::consts::{PI, TAU};
+use crate::{FLOAT, Vec3};
+
+/// An equirectangular (latitude-longitude) image of the light arriving from
+/// every direction, usually loaded from an HDR panorama. The top row looks
+/// straight up and the center column looks down -Z.
+///
+/// Bright regions such as a sun in the map are tiny targets for random
+/// bounces, so the map also holds a 2D distribution over its pixels
+/// proportional to their luminance. Diffuse surfaces draw light directions
+/// from it instead, which keeps small bright sources from turning into
+/// noise.
+pub struct EnvironmentMap {
+    pixels: Vec<Vec3>,
+    width: usize,
+    height: usize,
+    /// Cumulative distribution over rows, normalised so the last entry is 1.
+    marginal: Vec<FLOAT>,
+    /// Per row, the cumulative distribution over that row's columns.
+    conditional: Vec<Vec<FLOAT>>,
+}
+
+/// A direction drawn from an [`EnvironmentMap`] and its probability density
+/// per unit solid angle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvironmentSample {
+    pub direction: Vec3,
+    pub radiance: Vec3,
+    pub pdf: FLOAT,
+}
+
+impl EnvironmentMap {
+    /// `pixels` holds `width` x `height` linear colors in row-major order.
+    pub fn new(pixels: Vec<Vec3>, width: usize, height: usize) -> EnvironmentMap {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height);
+        // Rows near the poles cover less solid angle, so weight by sin(theta).
+        let mut conditional = Vec::with_capacity(height);
+        let mut row_weights = Vec::with_capacity(height);
+        for (y, row) in pixels.chunks(width).enumerate() {
+            let sin_theta = (PI * (y as FLOAT + 0.5) / height as FLOAT).sin();
+            let weights = row.iter().map(|&c| luminance(c).max(0.0) * sin_theta);
+            let (cdf, total) = cumulative(weights);
+            conditional.push(cdf);
+            row_weights.push(total);
+        }
+        let (marginal, _) = cumulative(row_weights.into_iter());
+        EnvironmentMap {
+            pixels,
+            width,
+            height,
+            marginal,
+            conditional,
+        }
+    }
+
+    /// Loads a panorama in any format the `image` crate reads, including
+    /// Radiance HDR and OpenEXR.
+    pub fn load<P: AsRef<Path>>(path: P) -> image::ImageResult<EnvironmentMap> {
+        let (pixels, width, height) = crate::export::load_image(path)?;
+        Ok(EnvironmentMap::new(pixels, width, height))
+    }
+
+    /// The light arriving from `direction`.
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let (x, y) = self.pixel(direction);
+        self.pixels[y * self.width + x]
+    }
+
+    /// Picks a direction with probability proportional to the light arriving
+    /// from it, from two uniform random numbers in `[0, 1)`.
+    pub fn sample(&self, u1: FLOAT, u2: FLOAT) -> EnvironmentSample {
+        let y = search(&self.marginal, u1);
+        let x = search(&self.conditional[y], u2);
+        // Uniform within the chosen pixel.
+        let fx = (x as FLOAT + offset_within(&self.conditional[y], x, u2)) / self.width as FLOAT;
+        let fy = (y as FLOAT + offset_within(&self.marginal, y, u1)) / self.height as FLOAT;
+        let direction = direction(fx, fy);
+        EnvironmentSample {
+            direction,
+            radiance: self.pixels[y * self.width + x],
+            pdf: self.pdf(direction),
+        }
+    }
+
+    /// The density with which [`EnvironmentMap::sample`] returns `direction`,
+    /// per unit solid angle.
+    pub fn pdf(&self, direction: Vec3) -> FLOAT {
+        let (x, y) = self.pixel(direction);
+        let p = probability(&self.marginal, y) * probability(&self.conditional[y], x);
+        let sin_theta = (PI * (y as FLOAT + 0.5) / self.height as FLOAT).sin();
+        let pixel_solid_angle = (TAU / self.width as FLOAT)
+            * (PI / self.height as FLOAT)
+            * sin_theta.max(FLOAT::EPSILON);
+        p / pixel_solid_angle
+    }
+
+    fn pixel(&self, direction: Vec3) -> (usize, usize) {
+        let d = direction.normalize();
+        let u = d.x.atan2(-d.z) / TAU + 0.5;
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = ((u * self.width as FLOAT) as usize).min(self.width - 1);
+        let y = ((v * self.height as FLOAT) as usize).min(self.height - 1);
+        (x, y)
+    }
+}
+
+/// The unit direction at equirectangular coordinates (`u`, `v`) in `[0, 1]`.
+fn direction(u: FLOAT, v: FLOAT) -> Vec3 {
+    let phi = (u - 0.5) * TAU;
+    let theta = v * PI;
+    Vec3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+/// Running sums of `weights` normalised to end at 1, and their total. An
+/// all-zero input gives a uniform distribution.
+fn cumulative(weights: impl Iterator<Item = FLOAT>) -> (Vec<FLOAT>, FLOAT) {
+    let mut sum = 0.0;
+    let mut cdf: Vec<FLOAT> = weights
+        .map(|w| {
+            sum += w;
+            sum
+        })
+        .collect();
+    let n = cdf.len() as FLOAT;
+    for (i, c) in cdf.iter_mut().enumerate() {
+        *c = if sum > 0.0 {
+            *c / sum
+        } else {
+            (i + 1) as FLOAT / n
+        };
+    }
+    (cdf, sum)
+}
+
+/// The first bucket whose cumulative probability exceeds `u`.
+fn search(cdf: &[FLOAT], u: FLOAT) -> usize {
+    cdf.partition_point(|&c| c <= u).min(cdf.len() - 1)
+}
+
+fn probability(cdf: &[FLOAT], i: usize) -> FLOAT {
+    cdf[i] - if i > 0 { cdf[i - 1] } else { 0.0 }
+}
+
+/// Where `u` falls within bucket `i`, rescaled to `[0, 1)`.
+fn offset_within(cdf: &[FLOAT], i: usize, u: FLOAT) -> FLOAT {
+    let low = if i > 0 { cdf[i - 1] } else { 0.0 };
+    let p = cdf[i] - low;
+    if p > 0.0 {
+        ((u - low) / p).clamp(0.0, 0.999_999)
+    } else {
+        0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_favours_the_bright_texel() {
+        let (width, height) = (8, 4);
+        let mut pixels = vec![Vec3::splat(0.1); width * height];
+        let bright = width + 5;
+        pixels[bright] = Vec3::splat(100.0);
+        let map = EnvironmentMap::new(pixels, width, height);
+
+        let n = 32;
+        let mut hits = 0;
+        for i in 0..n * n {
+            let u1 = ((i / n) as FLOAT + 0.5) / n as FLOAT;
+            let u2 = ((i % n) as FLOAT + 0.5) / n as FLOAT;
+            let sample = map.sample(u1, u2);
+            assert_eq!(sample.radiance, map.radiance(sample.direction));
+            assert!((sample.pdf - map.pdf(sample.direction)).abs() < 1e-3 * sample.pdf);
+            if sample.radiance == Vec3::splat(100.0) {
+                hits += 1;
+            }
+        }
+        assert!(
+            hits as FLOAT > 0.9 * (n * n) as FLOAT,
+            "{hits} of {}",
+            n * n
+        );
+
+        let toward_bright = direction(5.5 / width as FLOAT, 1.5 / height as FLOAT);
+        let toward_dim = direction(1.5 / width as FLOAT, 2.5 / height as FLOAT);
+        assert!(map.pdf(toward_bright) > 100.0 * map.pdf(toward_dim));
+    }
+}
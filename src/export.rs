@@ -0,0 +1,155 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{FLOAT, Ray, Scene, Vec3, raycast, to_rgba8_buffer};
+
+/// Samples points on the surface of `scene` by casting `count` rays inwards
+/// from a sphere of `radius` around `center`. Ray origins follow a Fibonacci
+/// spiral so the sphere of directions is covered evenly. Only surfaces visible
+/// from the enclosing sphere are found.
+pub fn surface_points(scene: &Scene, center: Vec3, radius: FLOAT, count: usize) -> Vec<Vec3> {
+    let golden_angle = crate::consts::PI * (3.0 - (5.0 as FLOAT).sqrt());
+    (0..count)
+        .filter_map(|i| {
+            let y = 1.0 - 2.0 * (i as FLOAT + 0.5) / count as FLOAT;
+            let r = (1.0 - y * y).sqrt();
+            let phi = golden_angle * i as FLOAT;
+            let direction = Vec3::new(phi.cos() * r, y, phi.sin() * r);
+            let ray = Ray::new(center + direction * radius, -direction);
+            raycast(&ray, scene).map(|hit| hit.p)
+        })
+        .collect()
+}
+
+/// Writes `points` as an ASCII PLY point cloud.
+pub fn write_ply<W: Write>(mut writer: W, points: &[Vec3]) -> io::Result<()> {
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format ascii 1.0")?;
+    writeln!(writer, "element vertex {}", points.len())?;
+    writeln!(writer, "property float x")?;
+    writeln!(writer, "property float y")?;
+    writeln!(writer, "property float z")?;
+    writeln!(writer, "end_header")?;
+    for p in points {
+        writeln!(writer, "{} {} {}", p.x, p.y, p.z)?;
+    }
+    Ok(())
+}
+
+/// Saves a linear color buffer as an 8-bit PNG, with the same clamping as the
+/// window output.
+pub fn save_png<P: AsRef<Path>>(
+    path: P,
+    buffer: &[Vec3],
+    width: usize,
+    height: usize,
+) -> image::ImageResult<()> {
+    let image = image::RgbaImage::from_raw(width as u32, height as u32, to_rgba8_buffer(buffer))
+        .expect("buffer size does not match the image size");
+    image.save(path)
+}
+
+/// Saves a linear color buffer as an OpenEXR image with 32-bit float
+/// channels, keeping values above 1 so the render can be exposed or graded
+/// elsewhere.
+pub fn save_exr<P: AsRef<Path>>(
+    path: P,
+    buffer: &[Vec3],
+    width: usize,
+    height: usize,
+) -> image::ImageResult<()> {
+    // The casts only do anything with the `f64` feature.
+    #[allow(clippy::unnecessary_cast)]
+    let channels = buffer
+        .iter()
+        .flat_map(|col| [col.x as f32, col.y as f32, col.z as f32])
+        .collect();
+    let image = image::Rgb32FImage::from_raw(width as u32, height as u32, channels)
+        .expect("buffer size does not match the image size");
+    image.save_with_format(path, image::ImageFormat::OpenExr)
+}
+
+/// Loads an image in any format the `image` crate reads as linear colors in
+/// row-major order, with its width and height. Channel values are used as
+/// stored, the same way [`save_png`] and [`save_exr`] write them.
+pub fn load_image<P: AsRef<Path>>(path: P) -> image::ImageResult<(Vec<Vec3>, usize, usize)> {
+    let image = image::open(path)?.into_rgb32f();
+    let (width, height) = image.dimensions();
+    let pixels = image
+        .pixels()
+        .map(|p| Vec3::new(p.0[0] as FLOAT, p.0[1] as FLOAT, p.0[2] as FLOAT))
+        .collect();
+    Ok((pixels, width as usize, height as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exr_round_trips_values_above_one() {
+        let buffer = vec![
+            Vec3::new(0.0, 0.25, 0.5),
+            Vec3::new(1.0, 2.5, 16.0),
+            Vec3::new(0.125, 0.0, 100.0),
+            Vec3::new(0.75, 0.5, 0.25),
+            Vec3::new(3.0, 0.0, 1e-3),
+            Vec3::ONE,
+        ];
+        let path = std::env::temp_dir().join(format!("sdf-round-trip-{}.exr", std::process::id()));
+        save_exr(&path, &buffer, 3, 2).unwrap();
+        let loaded = load_image(&path);
+        std::fs::remove_file(&path).unwrap();
+        let (pixels, width, height) = loaded.unwrap();
+        assert_eq!((width, height), (3, 2));
+        for (loaded, saved) in pixels.iter().zip(&buffer) {
+            assert!((*loaded - *saved).abs().max_element() <= 1e-6 * saved.max_element().max(1.0));
+        }
+    }
+
+    #[test]
+    fn sphere_points_lie_on_its_surface_and_fill_the_ply() {
+        let center = Vec3::new(0.5, -1.0, 3.0);
+        let scene = crate::SceneBuilder::new()
+            .add(crate::Sphere::new(center, 1.0))
+            .build();
+        let points = surface_points(&scene, center, 3.0, 200);
+        assert_eq!(points.len(), 200);
+        for p in &points {
+            assert!(((*p - center).length() - 1.0).abs() <= crate::MIN_DISTANCE);
+        }
+
+        let mut ply = Vec::new();
+        write_ply(&mut ply, &points).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        let (header, body) = ply.split_once("end_header\n").unwrap();
+        assert!(header.contains("element vertex 200\n"));
+        assert_eq!(body.lines().count(), 200);
+    }
+
+    #[test]
+    fn png_bytes_match_the_window_colors() {
+        let buffer = vec![
+            Vec3::new(0.0, 0.5, 1.0),
+            Vec3::new(-0.2, 1.7, 0.999),
+            Vec3::new(0.1, 0.2, 0.3),
+            Vec3::splat(0.75),
+        ];
+        for &color in &buffer {
+            let [r, g, b, a] = crate::to_rgba8(color);
+            let packed = crate::to_color(color);
+            assert_eq!(
+                [r, g, b],
+                [(packed >> 16) as u8, (packed >> 8) as u8, packed as u8]
+            );
+            assert_eq!(a, 255);
+        }
+        let path = std::env::temp_dir().join(format!("sdf-rgba-{}.png", std::process::id()));
+        save_png(&path, &buffer, 2, 2).unwrap();
+        let loaded = image::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap().into_rgba8();
+        assert_eq!(loaded.dimensions(), (2, 2));
+        assert_eq!(loaded.into_raw(), crate::to_rgba8_buffer(&buffer));
+    }
+}
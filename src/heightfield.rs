@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use crate::{FLOAT, Sdf, Vec2, Vec3};
+
+/// Terrain whose surface height is bilinearly interpolated from a grid of
+/// samples. The grid is stretched over `size` world units in x and z,
+/// centered on `center`, and heights are offsets above `center.y`. Beyond
+/// the grid the edge heights are extended outwards.
+///
+/// The distance is the vertical distance to the terrain, which overestimates
+/// the true distance on slopes; [`Sdf::lipschitz`] reports the steepest
+/// slope so the marcher scales its steps down accordingly.
+pub struct HeightField {
+    heights: Vec<FLOAT>,
+    columns: usize,
+    rows: usize,
+    pub center: Vec3,
+    pub size: Vec2,
+    lipschitz: FLOAT,
+}
+
+impl HeightField {
+    /// `heights` are `columns` x `rows` samples in row-major order, with rows
+    /// running along z. There must be at least one sample.
+    pub fn new(
+        heights: Vec<FLOAT>,
+        columns: usize,
+        rows: usize,
+        center: Vec3,
+        size: Vec2,
+    ) -> HeightField {
+        assert!(columns > 0 && rows > 0 && heights.len() == columns * rows);
+        let mut field = HeightField {
+            heights,
+            columns,
+            rows,
+            center,
+            size,
+            lipschitz: 1.0,
+        };
+        field.lipschitz = (1.0 + field.max_slope().powi(2)).sqrt();
+        field
+    }
+
+    /// Builds a height field from a grayscale image, mapping black to a
+    /// height of zero and white to `height`.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        center: Vec3,
+        size: Vec2,
+        height: FLOAT,
+    ) -> image::ImageResult<HeightField> {
+        let image = image::open(path)?.into_luma16();
+        let (columns, rows) = image.dimensions();
+        let heights = image
+            .pixels()
+            .map(|pixel| pixel.0[0] as FLOAT / u16::MAX as FLOAT * height)
+            .collect();
+        Ok(HeightField::new(
+            heights,
+            columns as usize,
+            rows as usize,
+            center,
+            size,
+        ))
+    }
+
+    /// The terrain height above `center.y` at world position (`x`, `z`).
+    pub fn height_at(&self, x: FLOAT, z: FLOAT) -> FLOAT {
+        let (cell_x, cell_z) = self.cell_size();
+        let gx = ((x - self.center.x + self.size.x * 0.5) / cell_x - 0.5)
+            .clamp(0.0, (self.columns - 1) as FLOAT);
+        let gz = ((z - self.center.z + self.size.y * 0.5) / cell_z - 0.5)
+            .clamp(0.0, (self.rows - 1) as FLOAT);
+        let (x0, z0) = (gx as usize, gz as usize);
+        let (x1, z1) = ((x0 + 1).min(self.columns - 1), (z0 + 1).min(self.rows - 1));
+        let (fx, fz) = (gx - x0 as FLOAT, gz - z0 as FLOAT);
+        let h = |x, z| self.heights[z * self.columns + x];
+        let near = h(x0, z0) * (1.0 - fx) + h(x1, z0) * fx;
+        let far = h(x0, z1) * (1.0 - fx) + h(x1, z1) * fx;
+        near * (1.0 - fz) + far * fz
+    }
+
+    /// World size of one grid cell in x and z. Each sample sits at the
+    /// center of its cell.
+    fn cell_size(&self) -> (FLOAT, FLOAT) {
+        (
+            self.size.x / self.columns as FLOAT,
+            self.size.y / self.rows as FLOAT,
+        )
+    }
+
+    /// An upper bound on the steepest gradient of the interpolated surface.
+    /// Within a cell the x slope varies between its values along the two
+    /// rows and the z slope between those along the two columns, so the
+    /// larger of each, combined, bounds the gradient anywhere in the cell.
+    fn max_slope(&self) -> FLOAT {
+        let (cell_x, cell_z) = self.cell_size();
+        let h = |x, z| self.heights[z * self.columns + x];
+        let mut slope: FLOAT = 0.0;
+        for z in 0..self.rows {
+            for x in 0..self.columns {
+                let (x1, z1) = ((x + 1).min(self.columns - 1), (z + 1).min(self.rows - 1));
+                let dx = (h(x1, z) - h(x, z)).abs().max((h(x1, z1) - h(x, z1)).abs()) / cell_x;
+                let dz = (h(x, z1) - h(x, z)).abs().max((h(x1, z1) - h(x1, z)).abs()) / cell_z;
+                slope = slope.max((dx * dx + dz * dz).sqrt());
+            }
+        }
+        slope
+    }
+}
+
+impl Sdf for HeightField {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        point.y - self.center.y - self.height_at(point.x, point.z)
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.lipschitz
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_height_field_is_a_horizontal_plane() {
+        let center = Vec3::new(1.0, -0.5, 2.0);
+        let field = HeightField::new(vec![0.75; 12], 4, 3, center, Vec2::new(8.0, 6.0));
+        assert_eq!(field.lipschitz(), 1.0);
+        let plane = crate::Plane::new(Vec3::Y, center.y + 0.75);
+        for p in [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.5, 3.0, -1.0),
+            Vec3::new(-20.0, -4.0, 15.0),
+        ] {
+            assert!((field.distance(p) - plane.distance(p)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn slope_bound_covers_the_far_corner_of_a_cell() {
+        // Bilinear over one unit cell, h = x * z, whose gradient reaches
+        // (1, 1) at the raised corner.
+        let field = HeightField::new(
+            vec![0.0, 0.0, 0.0, 1.0],
+            2,
+            2,
+            Vec3::ZERO,
+            Vec2::new(2.0, 2.0),
+        );
+        assert!((field.max_slope() - (2.0 as FLOAT).sqrt()).abs() < 1e-6);
+        assert!((field.lipschitz() - (3.0 as FLOAT).sqrt()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::color::luminance;
+use crate::{FLOAT, Vec3};
+
+/// Height in pixels of the overlay drawn by [`draw_histogram`], and the
+/// width of each of its bins.
+const HEIGHT: usize = 48;
+const BIN_WIDTH: usize = 2;
+
+/// Luminance histogram of a frame, with bins spaced evenly in exposure
+/// values (stops) from `min_ev` to `max_ev`, where 0 EV is a luminance of
+/// 1. Pixels outside the range are counted in the first or last bin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub bins: Vec<usize>,
+    pub min_ev: FLOAT,
+    pub max_ev: FLOAT,
+    /// Log-average luminance of the frame in EV, the usual key for
+    /// automatic exposure.
+    pub average_ev: FLOAT,
+}
+
+impl Histogram {
+    /// Bins the luminance of every pixel of a linear color `buffer`.
+    pub fn new(buffer: &[Vec3], bins: usize, min_ev: FLOAT, max_ev: FLOAT) -> Histogram {
+        let bins = bins.max(1);
+        let mut counts = vec![0; bins];
+        let mut ev_sum = 0.0;
+        for color in buffer {
+            let ev = luminance(*color)
+                .max(FLOAT::MIN_POSITIVE)
+                .log2()
+                .max(min_ev);
+            counts[bin_of(ev, bins, min_ev, max_ev)] += 1;
+            ev_sum += ev;
+        }
+        Histogram {
+            bins: counts,
+            min_ev,
+            max_ev,
+            average_ev: ev_sum / buffer.len().max(1) as FLOAT,
+        }
+    }
+
+    /// The bin holding pixels of exposure `ev`.
+    pub fn bin(&self, ev: FLOAT) -> usize {
+        bin_of(ev, self.bins.len(), self.min_ev, self.max_ev)
+    }
+}
+
+fn bin_of(ev: FLOAT, bins: usize, min_ev: FLOAT, max_ev: FLOAT) -> usize {
+    let f = (ev - min_ev) / (max_ev - min_ev);
+    ((f * bins as FLOAT) as isize).clamp(0, bins as isize - 1) as usize
+}
+
+/// Draws `histogram` over the bottom left corner of a window `buffer` that
+/// is `width` pixels wide, as white bars scaled to the fullest bin on a
+/// darkened backdrop, with a red line marking the average exposure. The
+/// overlay is clipped to the buffer.
+pub fn draw_histogram(buffer: &mut [u32], width: usize, histogram: &Histogram) {
+    let height = buffer.len() / width.max(1);
+    let peak = histogram.bins.iter().copied().max().unwrap_or(0).max(1);
+    let average = histogram.bin(histogram.average_ev);
+    let columns = (histogram.bins.len() * BIN_WIDTH).min(width);
+    for row in 0..HEIGHT.min(height) {
+        let y = height - 1 - row;
+        for x in 0..columns {
+            let bin = x / BIN_WIDTH;
+            let bar = histogram.bins[bin] * HEIGHT / peak;
+            let pixel = &mut buffer[y * width + x];
+            *pixel = if bin == average {
+                0xff0000
+            } else if row < bar {
+                0xffffff
+            } else {
+                (*pixel >> 1) & 0x7f7f7f
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_are_counted_in_their_exposure_bins() {
+        let gray = Vec3::splat;
+        let buffer = [
+            gray(1.0),
+            gray(1.0),
+            gray(2.0),
+            gray(0.25),
+            Vec3::ZERO,
+            gray(1000.0),
+        ];
+        let histogram = Histogram::new(&buffer, 8, -4.0, 4.0);
+        // One stop per bin, with black and the very bright pixel clamped to
+        // the ends.
+        assert_eq!(histogram.bins, vec![1, 0, 1, 0, 2, 1, 0, 1]);
+        assert_eq!(histogram.bins.iter().sum::<usize>(), buffer.len());
+        assert_eq!(histogram.bin(0.5), 4);
+    }
+
+    #[test]
+    fn the_average_of_a_flat_frame_is_its_exposure() {
+        let histogram = Histogram::new(&[Vec3::splat(0.5); 16], 8, -4.0, 4.0);
+        assert!((histogram.average_ev + 1.0).abs() < 1e-4);
+        assert_eq!(histogram.bins[3], 16);
+    }
+}
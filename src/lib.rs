@@ -0,0 +1,4288 @@
+use std::cell::RefCell;
+use std::ops::Add;
+
+use minifb::Key;
+use rand::prelude::*;
+use rayon::prelude::*;
+
+use blue_noise::BlueNoise;
+use camera::Camera;
+use environment::EnvironmentMap;
+use light::PointLight;
+use material::Material;
+use preetham::Preetham;
+use reproject::HitCache;
+use texture::{Bump, Checker, FlatShading};
+
+pub mod accumulator;
+pub mod bloom;
+pub mod blue_noise;
+pub mod camera;
+pub mod check;
+#[cfg(feature = "caustics")]
+pub mod caustics;
+pub mod color;
+pub mod csg;
+pub mod environment;
+pub mod export;
+pub mod heightfield;
+pub mod histogram;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod noise;
+pub mod optics;
+pub mod precision;
+pub mod preetham;
+pub mod reproject;
+pub mod ssao;
+pub mod ssr;
+pub mod texture;
+#[cfg(feature = "volumetrics")]
+pub mod volume;
+pub mod watch;
+
+pub use precision::{Affine3, FLOAT, Mat3, Quat, Vec2, Vec3};
+use precision::{BVec3, consts, transform_point};
+
+pub const WIDTH: usize = 400;
+pub const HEIGHT: usize = 300;
+pub const IMAGE_SIZE: usize = WIDTH * HEIGHT;
+/// Default [`Scene::far_clip`].
+pub const MAX_DEPTH: FLOAT = 50.0;
+pub const INV_WIDTH: FLOAT = 1.0 / WIDTH as FLOAT;
+pub const INV_HEIGHT: FLOAT = 1.0 / HEIGHT as FLOAT;
+
+pub const MIN_DISTANCE: FLOAT = 0.001;
+const VEC3_EPSILON_X: Vec3 = Vec3::new(MIN_DISTANCE, 0.0, 0.0);
+const VEC3_EPSILON_Y: Vec3 = Vec3::new(0.0, MIN_DISTANCE, 0.0);
+const VEC3_EPSILON_Z: Vec3 = Vec3::new(0.0, 0.0, MIN_DISTANCE);
+pub const SAMPLES: usize = 10;
+const RANDOM_SAMPLES_X : FLOAT = WIDTH as FLOAT / (100_000) as FLOAT;
+const RANDOM_SAMPLES_Y : FLOAT = HEIGHT as FLOAT / (100_000) as FLOAT;
+
+/// Number of march steps that maps to the hottest color in
+/// [`RenderMode::Steps`].
+const STEP_HEATMAP_SCALE: FLOAT = 64.0;
+
+/// Sub-pixel jitter only pays off when several samples are averaged. With a
+/// single sample every ray goes through the exact pixel center, which keeps
+/// debug renders deterministic and comparable pixel for pixel.
+pub const JITTER: bool = SAMPLES > 1;
+
+thread_local! {
+    /// Reseeded from the pixel coordinates and frame number before each
+    /// pixel is rendered, so the random numbers a pixel sees do not depend
+    /// on which thread renders it or what that thread did before.
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+}
+
+/// A well-mixed seed for pixel (`x`, `y`) of frame `frame`. The pixel and
+/// the frame are hashed in turn rather than packed into one word, so no
+/// frame number, however large, can alias another pixel's seed.
+fn pixel_seed(x: usize, y: usize, frame: u32) -> u64 {
+    splitmix64(splitmix64(x as u64 ^ ((y as u64) << 32)) ^ frame as u64)
+}
+
+/// The SplitMix64 finalizer.
+fn splitmix64(z: u64) -> u64 {
+    let mut z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Restarts this thread's random sequence from `seed`.
+pub(crate) fn seed_rng(seed: u64) {
+    RNG.with_borrow_mut(|rng| *rng = SmallRng::seed_from_u64(seed));
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// The scene time the ray samples, within the camera's shutter interval.
+    pub time: FLOAT,
+    /// Marching starts `t_min` along the ray and only finds surfaces up to
+    /// `t_max`, or the scene's far clip if that is nearer.
+    pub t_min: FLOAT,
+    pub t_max: FLOAT,
+    /// The cone of space the ray stands for, as its radius at the origin
+    /// and its growth per unit distance, see [`Camera::pixel_cone`]. Zero
+    /// for a ray that stands for a single line.
+    pub cone_radius: FLOAT,
+    pub cone_spread: FLOAT,
+}
+
+impl Ray {
+    pub fn new(position: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            position,
+            direction,
+            time: 0.0,
+            t_min: 0.0,
+            t_max: FLOAT::INFINITY,
+            cone_radius: 0.0,
+            cone_spread: 0.0,
+        }
+    }
+
+    /// Limits the ray to distances from `t_min` to `t_max`, e.g. to start a
+    /// secondary ray clear of the surface it leaves, or to stop a shadow ray
+    /// at the light.
+    pub fn with_range(mut self, t_min: FLOAT, t_max: FLOAT) -> Ray {
+        self.t_min = t_min;
+        self.t_max = t_max;
+        self
+    }
+
+    /// Widens the ray to a cone of `radius` at its origin that grows by
+    /// `spread` per unit distance, so textures are filtered over what the
+    /// ray covers.
+    pub fn with_cone(mut self, radius: FLOAT, spread: FLOAT) -> Ray {
+        self.cone_radius = radius;
+        self.cone_spread = spread;
+        self
+    }
+
+    /// The width of the ray's cone at distance `t` along it.
+    pub fn footprint(&self, t: FLOAT) -> FLOAT {
+        2.0 * (self.cone_radius + self.cone_spread * t)
+    }
+}
+
+pub struct HitRecord {
+    pub t: FLOAT,
+    pub p: Vec3,
+    pub normal: Vec3,
+    /// Index into [`Scene::objects`] of the object that was hit.
+    pub object: usize,
+    /// The object's [`Sdf::blend_weight`] at `p`, filled in before shading.
+    pub blend: FLOAT,
+    /// The object's [`Sdf::uv`] texture coordinates at `p`, filled in
+    /// before shading.
+    pub uv: Vec2,
+    /// World-space width of the ray's cone at `p`, over which textures are
+    /// filtered, filled in before shading.
+    pub footprint: FLOAT,
+}
+
+impl HitRecord {
+    pub fn new(t: FLOAT, p: Vec3, normal: Vec3, object: usize) -> HitRecord {
+        HitRecord { t, p, normal, object, blend: 0.0, uv: Vec2::ZERO, footprint: 0.0 }
+    }
+}
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn from_center(center: Vec3, half_size: Vec3) -> Aabb {
+        Aabb::new(center - half_size, center + half_size)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// The overlap of two boxes. Disjoint boxes give an empty box, see
+    /// [`Aabb::is_empty`].
+    pub fn intersection(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.max(other.min), self.max.min(other.max))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    /// The distances along `ray` at which it enters and leaves the box,
+    /// or `None` if it misses. The entry distance is negative when the ray
+    /// starts inside.
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<(FLOAT, FLOAT)> {
+        let inverse = ray.direction.recip();
+        let a = (self.min - ray.position) * inverse;
+        let b = (self.max - ray.position) * inverse;
+        let enter = a.min(b).max_element();
+        let exit = a.max(b).min_element();
+        (enter <= exit && exit >= 0.0).then_some((enter, exit))
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        std::array::from_fn(|i| {
+            Vec3::select(
+                BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                self.max,
+                self.min,
+            )
+        })
+    }
+
+    /// The box enclosing the corners of this box mapped through `f`, which
+    /// is exact for affine maps.
+    pub fn map(&self, f: impl Fn(Vec3) -> Vec3) -> Aabb {
+        let corners = self.corners().map(f);
+        corners[1..]
+            .iter()
+            .fold(Aabb::new(corners[0], corners[0]), |bounds, &c| {
+                bounds.union(&Aabb::new(c, c))
+            })
+    }
+}
+
+/// Object counts for an SDF tree or a whole scene.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SceneStats {
+    pub primitives: usize,
+    pub combinators: usize,
+}
+
+impl SceneStats {
+    pub const PRIMITIVE: SceneStats = SceneStats { primitives: 1, combinators: 0 };
+    pub const COMBINATOR: SceneStats = SceneStats { primitives: 0, combinators: 1 };
+}
+
+impl Add for SceneStats {
+    type Output = SceneStats;
+
+    fn add(self, other: SceneStats) -> SceneStats {
+        SceneStats {
+            primitives: self.primitives + other.primitives,
+            combinators: self.combinators + other.combinators,
+        }
+    }
+}
+
+pub trait Sdf: Sync + Send {
+    fn distance(&self, point: Vec3) -> FLOAT;
+    /// Leaves count as a single primitive; combinators override this to add
+    /// themselves to the stats of their children.
+    fn stats(&self) -> SceneStats {
+        SceneStats::PRIMITIVE
+    }
+    /// A box enclosing the surface and interior of the shape, or `None` for
+    /// unbounded shapes. Used for early outs and scene summaries, so it only
+    /// has to be conservative, not tight.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+    /// An upper bound on how fast `distance` can change per unit of movement.
+    /// Exact distance fields have a Lipschitz constant of 1; deformations
+    /// like [`Twist`] stretch space and overestimate the distance, so the
+    /// marcher divides their steps by this value to avoid overshooting.
+    fn lipschitz(&self) -> FLOAT {
+        1.0
+    }
+    /// For combinators that blend two shapes, how far `point` is towards the
+    /// second shape, from 0 to 1. [`Material::Blend`] mixes its two
+    /// materials by this weight.
+    fn blend_weight(&self, _point: Vec3) -> FLOAT {
+        0.0
+    }
+    /// Texture coordinates of the surface point nearest `point`. Shapes
+    /// without a natural parameterisation project onto the XZ plane, the
+    /// same way [`Checker`] does.
+    fn uv(&self, point: Vec3) -> Vec2 {
+        Vec2::new(point.x, point.z)
+    }
+    fn normal(&self, point: Vec3) -> Vec3 {
+        let normal = Vec3::new(
+            self.distance(point + VEC3_EPSILON_X) - self.distance(point - VEC3_EPSILON_X),
+            self.distance(point + VEC3_EPSILON_Y) - self.distance(point - VEC3_EPSILON_Y),
+            self.distance(point + VEC3_EPSILON_Z) - self.distance(point - VEC3_EPSILON_Z),
+        );
+        normal.normalize()
+    }
+}
+
+impl Sdf for Box<dyn Sdf> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.as_ref().distance(point)
+    }
+
+    fn stats(&self) -> SceneStats {
+        self.as_ref().stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.as_ref().bounds()
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.as_ref().lipschitz()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.as_ref().blend_weight(point)
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.as_ref().uv(point)
+    }
+
+    fn normal(&self, point: Vec3) -> Vec3 {
+        self.as_ref().normal(point)
+    }
+}
+
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: FLOAT,
+}
+
+impl Sphere {
+    /// Panics if `radius` is not positive.
+    pub fn new(center: Vec3, radius: FLOAT) -> Sphere {
+        assert!(radius > 0.0, "sphere radius must be positive, got {}", radius);
+        Sphere { center, radius }
+    }
+}
+
+impl Sdf for Sphere {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        (point - self.center).length() - self.radius
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_center(self.center, Vec3::splat(self.radius)))
+    }
+
+    /// Longitude and latitude, both in `[0, 1]`: `u` runs around the Y axis
+    /// starting behind the sphere (+Z) and `v` from the top pole down, the
+    /// same layout as an [`EnvironmentMap`].
+    fn uv(&self, point: Vec3) -> Vec2 {
+        let d = (point - self.center).normalize_or(Vec3::Y);
+        Vec2::new(
+            d.x.atan2(-d.z) / consts::TAU + 0.5,
+            d.y.clamp(-1.0, 1.0).acos() / consts::PI,
+        )
+    }
+}
+
+pub struct Cube {
+    pub center: Vec3,
+    pub size: FLOAT,
+}
+
+impl Cube {
+    /// Panics if `size`, the half width, is not positive.
+    pub fn new(center: Vec3, size: FLOAT) -> Cube {
+        assert!(size > 0.0, "cube size must be positive, got {}", size);
+        Cube { center, size }
+    }
+}
+
+impl Sdf for Cube {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        let q = (point - self.center).abs() - Vec3::splat(self.size);
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_center(self.center, Vec3::splat(self.size)))
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        box_uv((point - self.center) / self.size)
+    }
+}
+
+/// A box spanning arbitrary extents from `min` to `max`, unlike the
+/// uniformly sized [`Cube`].
+pub struct AaBox {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl AaBox {
+    /// Panics if `min` exceeds `max` on any axis.
+    pub fn new(min: Vec3, max: Vec3) -> AaBox {
+        assert!(min.cmple(max).all(), "box min {} exceeds max {}", min, max);
+        AaBox { min, max }
+    }
+}
+
+impl From<Aabb> for AaBox {
+    fn from(bounds: Aabb) -> AaBox {
+        AaBox {
+            min: bounds.min,
+            max: bounds.max,
+        }
+    }
+}
+
+impl Sdf for AaBox {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        let center = (self.min + self.max) * 0.5;
+        let half_size = (self.max - self.min) * 0.5;
+        let q = (point - center).abs() - half_size;
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        let center = (self.min + self.max) * 0.5;
+        box_uv((point - center) / ((self.max - self.min) * 0.5))
+    }
+}
+
+/// Planar texture coordinates on the face of the box `[-1, 1]³` that
+/// `local` is nearest, each in `[0, 1]` across the face.
+fn box_uv(local: Vec3) -> Vec2 {
+    let a = local.abs();
+    let (u, v) = if a.x >= a.y && a.x >= a.z {
+        (local.z, local.y)
+    } else if a.y >= a.z {
+        (local.x, local.z)
+    } else {
+        (local.x, local.y)
+    };
+    Vec2::new(u, v) * 0.5 + 0.5
+}
+
+/// A spherical sector: the part of a sphere of `radius` within `angle`
+/// radians of the +Y axis, with its apex at `center`.
+pub struct SolidAngle {
+    pub center: Vec3,
+    pub angle: FLOAT,
+    pub radius: FLOAT,
+}
+
+impl SolidAngle {
+    /// Panics unless `radius` is positive and `angle` lies in `(0, π]`.
+    pub fn new(center: Vec3, angle: FLOAT, radius: FLOAT) -> SolidAngle {
+        assert!(radius > 0.0, "solid angle radius must be positive, got {}", radius);
+        assert!(
+            angle > 0.0 && angle <= consts::PI,
+            "solid angle must be in (0, π], got {}",
+            angle
+        );
+        SolidAngle { center, angle, radius }
+    }
+}
+
+impl Sdf for SolidAngle {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        let p = point - self.center;
+        let c = Vec2::new(self.angle.sin(), self.angle.cos());
+        let q = Vec2::new(Vec2::new(p.x, p.z).length(), p.y);
+        let l = q.length() - self.radius;
+        let m = (q - c * q.dot(c).clamp(0.0, self.radius)).length();
+        l.max(m * (c.y * q.x - c.x * q.y).signum())
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_center(self.center, Vec3::splat(self.radius)))
+    }
+}
+
+/// An infinite plane through `normal * offset`, with `normal` unit length.
+/// Space behind the plane counts as inside.
+pub struct Plane {
+    pub normal: Vec3,
+    pub offset: FLOAT,
+}
+
+impl Plane {
+    /// Panics if `normal` is not unit length.
+    pub fn new(normal: Vec3, offset: FLOAT) -> Plane {
+        assert!(normal.is_normalized(), "plane normal {} is not unit length", normal);
+        Plane { normal, offset }
+    }
+}
+
+impl Sdf for Plane {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        point.dot(self.normal) - self.offset
+    }
+
+    /// World units along two tangent axes, so textures tile across the
+    /// plane; a +Y normal gives the X and Z coordinates.
+    fn uv(&self, point: Vec3) -> Vec2 {
+        let axis = if self.normal.x.abs() > 0.9 { Vec3::Z } else { Vec3::X };
+        let tangent = (axis - self.normal * axis.dot(self.normal)).normalize();
+        let bitangent = tangent.cross(self.normal);
+        Vec2::new(point.dot(tangent), point.dot(bitangent))
+    }
+}
+
+/// The Mandelbulb fractal of the given `power` centred on the origin, with
+/// the usual escape time distance estimate. More `iterations` resolve finer
+/// detail, which deep zooms need, and where `f32` rounding shows up first.
+pub struct Mandelbulb {
+    pub power: FLOAT,
+    pub iterations: usize,
+}
+
+impl Mandelbulb {
+    /// Points further out than the escape radius are never in the set, so
+    /// well beyond it the distance to this sphere stands in for the estimate.
+    const ESCAPE: FLOAT = 2.0;
+
+    /// Panics unless `power` exceeds 1 and `iterations` is positive.
+    pub fn new(power: FLOAT, iterations: usize) -> Mandelbulb {
+        assert!(power > 1.0, "mandelbulb power must exceed 1, got {}", power);
+        assert!(iterations > 0, "mandelbulb needs at least one iteration");
+        Mandelbulb { power, iterations }
+    }
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        let length = point.length();
+        if length > 2.0 * Mandelbulb::ESCAPE {
+            return length - Mandelbulb::ESCAPE;
+        }
+        let mut z = point;
+        let mut dr: FLOAT = 1.0;
+        let mut r = length;
+        for _ in 0..self.iterations {
+            if r > Mandelbulb::ESCAPE || r == 0.0 {
+                break;
+            }
+            let theta = (z.z / r).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            let zr = r.powf(self.power);
+            z = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * zr + point;
+            r = z.length();
+        }
+        if r == 0.0 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_center(Vec3::ZERO, Vec3::splat(Mandelbulb::ESCAPE)))
+    }
+}
+
+/// The intersection of two shapes. `max(t, u)` has exactly the right sign
+/// everywhere, since a point is inside only when it is inside both. Outside,
+/// near edges where the two surfaces meet, it underestimates the distance,
+/// so the marcher takes shorter steps there but never steps over the
+/// surface, even for thin shapes such as a lens cut from two spheres.
+pub struct And<T: Sdf, U: Sdf> {
+    pub t: T,
+    pub u: U,
+}
+
+impl<T: Sdf, U: Sdf> Sdf for And<T, U> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.t.distance(point).max(self.u.distance(point))
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        if self.t.distance(point) >= self.u.distance(point) {
+            self.t.blend_weight(point)
+        } else {
+            self.u.blend_weight(point)
+        }
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        if self.t.distance(point) >= self.u.distance(point) {
+            self.t.uv(point)
+        } else {
+            self.u.uv(point)
+        }
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.t.stats() + self.u.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        match (self.t.bounds(), self.u.bounds()) {
+            (Some(t), Some(u)) => Some(t.intersection(&u)),
+            (t, u) => t.or(u),
+        }
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.t.lipschitz().max(self.u.lipschitz())
+    }
+}
+
+/// The union of two shapes.
+pub struct Or<T: Sdf, U: Sdf> {
+    pub t: T,
+    pub u: U,
+}
+
+impl<T: Sdf, U: Sdf> Sdf for Or<T, U> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.t.distance(point).min(self.u.distance(point))
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        if self.t.distance(point) <= self.u.distance(point) {
+            self.t.blend_weight(point)
+        } else {
+            self.u.blend_weight(point)
+        }
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        if self.t.distance(point) <= self.u.distance(point) {
+            self.t.uv(point)
+        } else {
+            self.u.uv(point)
+        }
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.t.stats() + self.u.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.t.bounds()?.union(&self.u.bounds()?))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.t.lipschitz().max(self.u.lipschitz())
+    }
+}
+
+/// The union of any number of shapes, evaluated in a single pass instead
+/// of through nested [`Or`]s. An empty union contains nothing.
+pub struct UnionAll(pub Vec<Box<dyn Sdf>>);
+
+impl UnionAll {
+    /// The shape whose surface governs the union at `point`.
+    fn nearest(&self, point: Vec3) -> Option<&dyn Sdf> {
+        self.0
+            .iter()
+            .min_by(|a, b| a.distance(point).total_cmp(&b.distance(point)))
+            .map(|shape| shape.as_ref())
+    }
+}
+
+impl Sdf for UnionAll {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.0
+            .iter()
+            .map(|shape| shape.distance(point))
+            .fold(FLOAT::MAX, FLOAT::min)
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.nearest(point).map_or(0.0, |shape| shape.blend_weight(point))
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.nearest(point).map_or(Vec2::ZERO, |shape| shape.uv(point))
+    }
+
+    fn stats(&self) -> SceneStats {
+        self.0
+            .iter()
+            .fold(SceneStats::COMBINATOR, |stats, shape| stats + shape.stats())
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let mut shapes = self.0.iter();
+        let first = shapes.next()?.bounds()?;
+        shapes.try_fold(first, |bounds, shape| Some(bounds.union(&shape.bounds()?)))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.0.iter().map(|shape| shape.lipschitz()).fold(1.0, FLOAT::max)
+    }
+}
+
+/// The intersection of any number of shapes, the n-ary form of [`And`].
+/// An empty intersection is all of space.
+pub struct IntersectAll(pub Vec<Box<dyn Sdf>>);
+
+impl IntersectAll {
+    /// The shape whose surface governs the intersection at `point`.
+    fn farthest(&self, point: Vec3) -> Option<&dyn Sdf> {
+        self.0
+            .iter()
+            .max_by(|a, b| a.distance(point).total_cmp(&b.distance(point)))
+            .map(|shape| shape.as_ref())
+    }
+}
+
+impl Sdf for IntersectAll {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.0
+            .iter()
+            .map(|shape| shape.distance(point))
+            .fold(-FLOAT::MAX, FLOAT::max)
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.farthest(point).map_or(0.0, |shape| shape.blend_weight(point))
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.farthest(point).map_or(Vec2::ZERO, |shape| shape.uv(point))
+    }
+
+    fn stats(&self) -> SceneStats {
+        self.0
+            .iter()
+            .fold(SceneStats::COMBINATOR, |stats, shape| stats + shape.stats())
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.0
+            .iter()
+            .filter_map(|shape| shape.bounds())
+            .reduce(|a, b| a.intersection(&b))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.0.iter().map(|shape| shape.lipschitz()).fold(1.0, FLOAT::max)
+    }
+}
+
+/// Joins two shapes like a union, but melts them together with a fillet
+/// of about `k` world units where they meet (polynomial smooth minimum).
+/// Its [`Sdf::blend_weight`] rises smoothly from 0 on `t` to 1 on `u`
+/// across the fillet, so a [`Material::Blend`] fades between their
+/// materials instead of switching abruptly.
+pub struct SmoothUnion<T: Sdf, U: Sdf> {
+    pub t: T,
+    pub u: U,
+    pub k: FLOAT,
+}
+
+impl<T: Sdf, U: Sdf> SmoothUnion<T, U> {
+    /// Both distances and the weight of `u` at `point`.
+    fn mix(&self, point: Vec3) -> (FLOAT, FLOAT, FLOAT) {
+        let a = self.t.distance(point);
+        let b = self.u.distance(point);
+        let h = if self.k > 0.0 {
+            (0.5 + 0.5 * (a - b) / self.k).clamp(0.0, 1.0)
+        } else if a <= b {
+            0.0
+        } else {
+            1.0
+        };
+        (a, b, h)
+    }
+}
+
+impl<T: Sdf, U: Sdf> Sdf for SmoothUnion<T, U> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        let (a, b, h) = self.mix(point);
+        a + (b - a) * h - self.k * h * (1.0 - h)
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.t.stats() + self.u.stats()
+    }
+
+    /// The blend bulges out by at most `k / 4` beyond the plain union.
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.t.bounds()?.union(&self.u.bounds()?);
+        let grow = Vec3::splat(self.k * 0.25);
+        Some(Aabb::new(bounds.min - grow, bounds.max + grow))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.t.lipschitz().max(self.u.lipschitz())
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.mix(point).2
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        if self.mix(point).2 < 0.5 {
+            self.t.uv(point)
+        } else {
+            self.u.uv(point)
+        }
+    }
+}
+
+/// Subtracts `u` from `t`, as the intersection of `t` with the inside-out
+/// `u`. Like [`And`], the sign is exact and the distance is at worst an
+/// underestimate, so marching it is safe.
+pub struct Not<T: Sdf, U: Sdf> {
+    pub t: T,
+    pub u: U,
+    /// Tags the cut wall, where `u` governs the distance, with a
+    /// [`Sdf::blend_weight`] of 1 and everything else with 0, so that a
+    /// [`Material::Blend`] of two materials gives the cavity the second.
+    pub tag_cut: bool,
+}
+
+impl<T: Sdf, U: Sdf> Not<T, U> {
+    pub fn new(t: T, u: U) -> Not<T, U> {
+        Not { t, u, tag_cut: false }
+    }
+
+    /// Whether the cut surface of `u`, rather than `t`, is nearest `point`.
+    fn on_cut(&self, point: Vec3) -> bool {
+        self.t.distance(point) < -self.u.distance(point)
+    }
+}
+
+impl<T: Sdf, U: Sdf> Sdf for Not<T, U> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.t.distance(point).max(-self.u.distance(point))
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        if self.tag_cut && self.on_cut(point) { 1.0 } else { 0.0 }
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        if self.on_cut(point) {
+            self.u.uv(point)
+        } else {
+            self.t.uv(point)
+        }
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.t.stats() + self.u.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.t.bounds()
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.t.lipschitz().max(self.u.lipschitz())
+    }
+}
+
+/// Subtracts `u` from `t` like [`Not`], but rounds the edge where the cut
+/// meets the base into a circular arc of `radius`, like a machined fillet.
+/// Away from that edge both surfaces are unchanged, so the cut stays crisp.
+pub struct RoundSubtraction<T: Sdf, U: Sdf> {
+    pub t: T,
+    pub u: U,
+    pub radius: FLOAT,
+}
+
+impl<T: Sdf, U: Sdf> Sdf for RoundSubtraction<T, U> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        let a = self.t.distance(point);
+        let b = -self.u.distance(point);
+        let r = self.radius;
+        let u = Vec2::new(r + a, r + b).max(Vec2::ZERO);
+        (-r).min(a.max(b)) + u.length()
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.t.stats() + self.u.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.t.bounds()
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.t.lipschitz().max(self.u.lipschitz())
+    }
+}
+
+/// Blends between two shapes by interpolating their distances,
+/// `(1 - t) * a + t * b`, so `t = 0` gives `a` and `t = 1` gives `b`. The
+/// in-between field is not an exact distance to the blended surface, but
+/// for `t` in `[0, 1]` it changes no faster than its inputs, so marching it
+/// never overshoots.
+pub struct Morph<T: Sdf, U: Sdf> {
+    pub a: T,
+    pub b: U,
+    pub t: FLOAT,
+}
+
+impl<T: Sdf, U: Sdf> Sdf for Morph<T, U> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        let a = self.a.distance(point);
+        let b = self.b.distance(point);
+        // Weighting both ends gives exactly `b` at `t = 1`, which
+        // `a + (b - a) * t` can miss by a rounding error.
+        a * (1.0 - self.t) + b * self.t
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.a.stats() + self.b.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.a.bounds()?.union(&self.b.bounds()?))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        (1.0 - self.t).abs() * self.a.lipschitz() + self.t.abs() * self.b.lipschitz()
+    }
+
+    /// Taken from whichever shape the morph is closer to.
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        if self.t < 0.5 {
+            self.a.blend_weight(point)
+        } else {
+            self.b.blend_weight(point)
+        }
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        if self.t < 0.5 {
+            self.a.uv(point)
+        } else {
+            self.b.uv(point)
+        }
+    }
+}
+
+/// Cuts `inner` with a plane, keeping the half-space where
+/// `point.dot(normal) >= offset`. Equivalent to an [`And`] with a plane but
+/// without building one. `normal` must be unit length.
+pub struct Clip<T: Sdf> {
+    pub inner: T,
+    pub normal: Vec3,
+    pub offset: FLOAT,
+}
+
+impl<T: Sdf> Sdf for Clip<T> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.inner.distance(point).max(self.offset - point.dot(self.normal))
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.inner.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.inner.bounds()
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.inner.lipschitz()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(point)
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(point)
+    }
+}
+
+/// Repeats `inner` on a grid of cells `spacing` apart, keeping only the
+/// copies whose cell indices lie between `min` and `max` inclusive on each
+/// axis. The copy in cell `(i, j, k)` is centred on `spacing * (i, j, k)`,
+/// so setting `min` and `max` to zero on an axis leaves it unrepeated; every
+/// component of `spacing` must still be non-zero. Each point is measured
+/// against the copy in its nearest cell only, so the field is exact while
+/// `inner` fits within half a cell of its centre.
+pub struct RepeatLimited<T: Sdf> {
+    pub inner: T,
+    pub spacing: Vec3,
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl<T: Sdf> RepeatLimited<T> {
+    /// `point` relative to the centre of the copy nearest it.
+    fn local(&self, point: Vec3) -> Vec3 {
+        point - self.spacing * (point / self.spacing).round().clamp(self.min, self.max)
+    }
+}
+
+impl<T: Sdf> Sdf for RepeatLimited<T> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.inner.distance(self.local(point))
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.inner.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let inner = self.inner.bounds()?;
+        let (a, b) = (self.spacing * self.min, self.spacing * self.max);
+        Some(Aabb::new(inner.min + a.min(b), inner.max + a.max(b)))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.inner.lipschitz()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(self.local(point))
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(self.local(point))
+    }
+}
+
+/// Tiles `tile`, modelled centred on the Y axis, across the rectangle of the
+/// XZ plane from `min` to `max` (`x` and `z` in a [`Vec2`]), with copies
+/// `spacing` apart. As many whole cells as fit are laid out, centred in the
+/// rectangle, and anything reaching past its edges is cut off, so a grid of
+/// pillars on a ground needs only a single pillar.
+pub fn tiled_floor<T: Sdf>(
+    tile: T,
+    min: Vec2,
+    max: Vec2,
+    spacing: FLOAT,
+) -> And<Translate<RepeatLimited<T>>, AaBox> {
+    let size = max - min;
+    let cells = (size / spacing).floor().max(Vec2::ONE);
+    let (low, high) = tile
+        .bounds()
+        .map_or((-MAX_DEPTH, MAX_DEPTH), |bounds| (bounds.min.y, bounds.max.y));
+    let corner = min + (size - (cells - 1.0) * spacing) * 0.5;
+    And {
+        t: Translate {
+            inner: RepeatLimited {
+                inner: tile,
+                spacing: Vec3::splat(spacing),
+                min: Vec3::ZERO,
+                max: Vec3::new(cells.x - 1.0, 0.0, cells.y - 1.0),
+            },
+            offset: Vec3::new(corner.x, 0.0, corner.y),
+        },
+        u: AaBox::new(Vec3::new(min.x, low, min.y), Vec3::new(max.x, high, max.y)),
+    }
+}
+
+/// Repeats `inner` `count` times around the Y axis by folding the angle of the
+/// sample point into a single sector centred on +X, so `inner` should be
+/// modelled around the +X axis. With `mirror` every other copy is reflected,
+/// which keeps shapes continuous across the sector boundaries.
+pub struct RepeatRadial<T: Sdf> {
+    pub inner: T,
+    pub count: usize,
+    pub mirror: bool,
+}
+
+impl<T: Sdf> RepeatRadial<T> {
+    /// Panics if `count` is zero, which leaves no sector to fold into.
+    pub fn new(inner: T, count: usize, mirror: bool) -> RepeatRadial<T> {
+        assert!(count > 0, "radial repeat count must be positive");
+        RepeatRadial { inner, count, mirror }
+    }
+
+    /// `point` folded into the sector around +X.
+    fn local(&self, point: Vec3) -> Vec3 {
+        let sector = consts::TAU / self.count as FLOAT;
+        let angle = point.z.atan2(point.x);
+        let mut folded = (angle + sector * 0.5).rem_euclid(sector) - sector * 0.5;
+        if self.mirror {
+            folded = folded.abs();
+        }
+        let r = Vec3::new(point.x, 0.0, point.z).length();
+        Vec3::new(r * folded.cos(), point.y, r * folded.sin())
+    }
+}
+
+impl<T: Sdf> Sdf for RepeatRadial<T> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.inner.distance(self.local(point))
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.inner.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let inner = self.inner.bounds()?;
+        let extent = inner.min.abs().max(inner.max.abs());
+        let radius = Vec3::new(extent.x, 0.0, extent.z).length();
+        Some(Aabb::new(
+            Vec3::new(-radius, inner.min.y, -radius),
+            Vec3::new(radius, inner.max.y, radius),
+        ))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.inner.lipschitz()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(self.local(point))
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(self.local(point))
+    }
+}
+
+/// Twists `inner` around the Y axis by `rate` radians per unit of height.
+/// The twist stretches the field by up to `sqrt(1 + (rate * r)^2)` at radius
+/// `r` from the axis, which is reported through [`Sdf::lipschitz`] using the
+/// inner shape's bounds (or a unit radius if it is unbounded).
+pub struct Twist<T: Sdf> {
+    pub inner: T,
+    pub rate: FLOAT,
+}
+
+impl<T: Sdf> Twist<T> {
+    /// `point` untwisted into the space of `inner`.
+    fn local(&self, point: Vec3) -> Vec3 {
+        let (sin, cos) = (self.rate * point.y).sin_cos();
+        Vec3::new(cos * point.x - sin * point.z, point.y, sin * point.x + cos * point.z)
+    }
+}
+
+impl<T: Sdf> Sdf for Twist<T> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.inner.distance(self.local(point))
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.inner.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let inner = self.inner.bounds()?;
+        let extent = inner.min.abs().max(inner.max.abs());
+        let radius = Vec3::new(extent.x, 0.0, extent.z).length();
+        Some(Aabb::new(
+            Vec3::new(-radius, inner.min.y, -radius),
+            Vec3::new(radius, inner.max.y, radius),
+        ))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        let radius = self.bounds().map_or(1.0, |bounds| bounds.max.x);
+        self.inner.lipschitz() * (1.0 + (self.rate * radius).powi(2)).sqrt()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(self.local(point))
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(self.local(point))
+    }
+}
+
+/// Adds `displacement(point)` to the distance of `inner`, but only where
+/// `mask` reaches, so bumps can cover part of a surface and leave the rest
+/// smooth. The displacement is applied in full inside the mask and fades
+/// out smoothly over `falloff` (which must be positive) beyond its surface;
+/// further out the field is exactly that of `inner`. `amplitude` and
+/// `slope` must bound the magnitude of the displacement and of its gradient,
+/// and are used for the bounds and the Lipschitz constant.
+pub struct DisplaceMasked<T: Sdf, F: Fn(Vec3) -> FLOAT + Sync + Send, M: Sdf> {
+    pub inner: T,
+    pub displacement: F,
+    pub mask: M,
+    pub falloff: FLOAT,
+    pub amplitude: FLOAT,
+    pub slope: FLOAT,
+}
+
+impl<T: Sdf, F: Fn(Vec3) -> FLOAT + Sync + Send, M: Sdf> DisplaceMasked<T, F, M> {
+    /// How much of the displacement applies at `point`, from 0 to 1.
+    pub fn weight(&self, point: Vec3) -> FLOAT {
+        let t = (1.0 - self.mask.distance(point) / self.falloff).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl<T: Sdf, F: Fn(Vec3) -> FLOAT + Sync + Send, M: Sdf> Sdf for DisplaceMasked<T, F, M> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        let d = self.inner.distance(point);
+        let weight = self.weight(point);
+        // Skips the displacement, which may be costly, where it is masked off.
+        if weight == 0.0 {
+            d
+        } else {
+            d + weight * (self.displacement)(point)
+        }
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.inner.stats() + self.mask.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let inner = self.inner.bounds()?;
+        let pad = Vec3::splat(self.amplitude.abs());
+        Some(Aabb::new(inner.min - pad, inner.max + pad))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        // The weight is a smoothstep of the mask distance, whose steepest
+        // slope is 1.5 per falloff.
+        let weight_slope = 1.5 * self.mask.lipschitz() / self.falloff;
+        self.inner.lipschitz() + self.slope.abs() + self.amplitude.abs() * weight_slope
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(point)
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(point)
+    }
+}
+
+/// Moves `inner` by `offset`.
+pub struct Translate<T: Sdf> {
+    pub inner: T,
+    pub offset: Vec3,
+}
+
+impl<T: Sdf> Sdf for Translate<T> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.inner.distance(point - self.offset)
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.inner.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.inner.bounds()?.map(|c| c + self.offset))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.inner.lipschitz()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(point - self.offset)
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(point - self.offset)
+    }
+}
+
+/// Rotates `inner` about the origin.
+pub struct Rotate<T: Sdf> {
+    pub inner: T,
+    pub rotation: Quat,
+}
+
+impl<T: Sdf> Sdf for Rotate<T> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.inner.distance(self.rotation.inverse() * point)
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.inner.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.inner.bounds()?.map(|c| self.rotation * c))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.inner.lipschitz()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(self.rotation.inverse() * point)
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(self.rotation.inverse() * point)
+    }
+}
+
+/// Scales `inner` uniformly about the origin. The inner distance is measured
+/// in the shape's unscaled space, so it is multiplied back by `scale`; every
+/// nested `UniformScale` applies its own factor, so chains of transforms
+/// remain a correctly scaled distance field.
+pub struct UniformScale<T: Sdf> {
+    pub inner: T,
+    pub scale: FLOAT,
+}
+
+impl<T: Sdf> Sdf for UniformScale<T> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.inner.distance(point / self.scale) * self.scale
+    }
+
+    fn stats(&self) -> SceneStats {
+        SceneStats::COMBINATOR + self.inner.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.inner.bounds()?.map(|c| c * self.scale))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.inner.lipschitz()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(point / self.scale)
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(point / self.scale)
+    }
+}
+
+/// Wraps `inner` and counts how many times its distance is evaluated, to
+/// find out which shapes dominate render cost. Take a handle to the counter
+/// with [`Instrumented::calls`] before adding the shape to a scene and read
+/// it after rendering.
+pub struct Instrumented<T: Sdf> {
+    pub inner: T,
+    calls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl<T: Sdf> Instrumented<T> {
+    pub fn new(inner: T) -> Instrumented<T> {
+        Instrumented {
+            inner,
+            calls: Default::default(),
+        }
+    }
+
+    /// The evaluation counter, shared with the shape.
+    pub fn calls(&self) -> std::sync::Arc<std::sync::atomic::AtomicUsize> {
+        self.calls.clone()
+    }
+}
+
+impl<T: Sdf> Sdf for Instrumented<T> {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.inner.distance(point)
+    }
+
+    fn stats(&self) -> SceneStats {
+        self.inner.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.inner.bounds()
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.inner.lipschitz()
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.inner.blend_weight(point)
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.inner.uv(point)
+    }
+}
+
+/// A ready-made floor: an infinite horizontal plane at `height` with a
+/// checkerboard material. The checker is filtered by pixel footprint, so the
+/// floor fades smoothly to an even tone towards the horizon.
+pub struct GroundPlane {
+    pub height: FLOAT,
+    pub checker: Checker,
+}
+
+impl GroundPlane {
+    /// A floor at `height` with light and dark gray tiles one unit wide.
+    pub fn new(height: FLOAT) -> GroundPlane {
+        GroundPlane {
+            height,
+            checker: Checker {
+                a: Vec3::splat(0.8),
+                b: Vec3::splat(0.3),
+                scale: 1.0,
+            },
+        }
+    }
+}
+
+impl From<GroundPlane> for Object {
+    fn from(ground: GroundPlane) -> Object {
+        let plane = Plane {
+            normal: Vec3::Y,
+            offset: ground.height,
+        };
+        Object::new(plane, Material::Checker(ground.checker))
+    }
+}
+
+/// Background seen by rays that leave the scene: a vertical gradient plus a
+/// sun disc. The sun is much brighter than the gradient so diffuse bounces
+/// that escape towards it pick up direct sunlight.
+pub struct Sky {
+    /// Color stops spaced evenly from straight down (first) to straight up
+    /// (last), linearly interpolated by the ray's y component.
+    pub gradient: Vec<Vec3>,
+    pub sun_direction: Vec3,
+    /// Angular radius of the sun disc in radians.
+    pub sun_radius: FLOAT,
+    pub sun_color: Vec3,
+    /// Replaces the gradient with a physically based daylight model when
+    /// set. The sun disc is still drawn on top.
+    pub preetham: Option<Preetham>,
+    /// Replaces the gradient and sun when set. Diffuse surfaces then sample
+    /// it directly towards its bright regions.
+    pub environment: Option<EnvironmentMap>,
+}
+
+impl Default for Sky {
+    fn default() -> Sky {
+        Sky {
+            gradient: vec![Vec3::ONE, Vec3::new(0.5, 0.7, 1.0)],
+            sun_direction: Vec3::new(-0.4, 0.6, -0.7).normalize(),
+            sun_radius: 0.05,
+            sun_color: Vec3::new(20.0, 18.0, 15.0),
+            preetham: None,
+            environment: None,
+        }
+    }
+}
+
+impl Sky {
+    pub fn color(&self, direction: Vec3) -> Vec3 {
+        if let Some(environment) = &self.environment {
+            return environment.radiance(direction);
+        }
+        let unit_direction = direction.normalize();
+        let gradient = match &self.preetham {
+            Some(preetham) => preetham.radiance(unit_direction),
+            None => self.gradient_color(0.5 * (unit_direction.y + 1.0)),
+        };
+        if unit_direction.dot(self.sun_direction) > self.sun_radius.cos() {
+            gradient + self.sun_color
+        } else {
+            gradient
+        }
+    }
+
+    /// Samples the gradient at `t` in `[0, 1]`.
+    pub fn gradient_color(&self, t: FLOAT) -> Vec3 {
+        match self.gradient.as_slice() {
+            [] => Vec3::ZERO,
+            [only] => *only,
+            stops => {
+                let x = t.clamp(0.0, 1.0) * (stops.len() - 1) as FLOAT;
+                let i = (x as usize).min(stops.len() - 2);
+                let f = x - i as FLOAT;
+                stops[i] * (1.0 - f) + stops[i + 1] * f
+            }
+        }
+    }
+}
+
+/// A shape in the scene together with how its surface is shaded and where
+/// it is placed. The object's transform maps the shape's local space into
+/// the world, so primitives can be modelled around the origin.
+pub struct Object {
+    pub sdf: Box<dyn Sdf>,
+    pub material: Material,
+    /// Perturbs the shading normal before the material is applied.
+    pub bump: Option<Bump>,
+    /// Snaps the shading normal to facets, after any bump mapping.
+    pub flat_shading: Option<FlatShading>,
+    /// Shades both sides of the surface alike by turning the normal to face
+    /// the incoming ray. Meant for planes and open or thin surfaces seen from
+    /// behind; dielectrics rely on the outward normal and should not use it.
+    pub two_sided: bool,
+    /// Disabled objects are skipped by marching and scene queries, as if
+    /// they had been removed.
+    pub enabled: bool,
+    transform: Affine3,
+    inverse: Affine3,
+    scale: FLOAT,
+}
+
+impl Object {
+    pub fn new<T: Sdf + 'static>(sdf: T, material: Material) -> Object {
+        Object {
+            sdf: Box::new(sdf),
+            material,
+            bump: None,
+            flat_shading: None,
+            two_sided: false,
+            enabled: true,
+            transform: Affine3::IDENTITY,
+            inverse: Affine3::IDENTITY,
+            scale: 1.0,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Affine3) -> Object {
+        self.set_transform(transform);
+        self
+    }
+
+    pub fn transform(&self) -> Affine3 {
+        self.transform
+    }
+
+    /// Places the object. Distances are rescaled by the smallest axis scale,
+    /// which is exact for rotations, translations and uniform scales; the
+    /// transform should not contain shear.
+    pub fn set_transform(&mut self, transform: Affine3) {
+        let m = transform.matrix3;
+        self.transform = transform;
+        self.inverse = transform.inverse();
+        self.scale = m.x_axis.length().min(m.y_axis.length()).min(m.z_axis.length());
+    }
+}
+
+impl Sdf for Object {
+    fn distance(&self, point: Vec3) -> FLOAT {
+        self.sdf.distance(transform_point(&self.inverse, point)) * self.scale
+    }
+
+    fn stats(&self) -> SceneStats {
+        self.sdf.stats()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.sdf.bounds()?.map(|c| transform_point(&self.transform, c)))
+    }
+
+    fn blend_weight(&self, point: Vec3) -> FLOAT {
+        self.sdf.blend_weight(transform_point(&self.inverse, point))
+    }
+
+    fn uv(&self, point: Vec3) -> Vec2 {
+        self.sdf.uv(transform_point(&self.inverse, point))
+    }
+
+    fn lipschitz(&self) -> FLOAT {
+        self.sdf.lipschitz()
+    }
+}
+
+pub struct Scene {
+    pub objects: Vec<Object>,
+    pub lights: Vec<PointLight>,
+    pub sky: Sky,
+    /// Distance along a ray beyond which the march gives up and reports a
+    /// miss. Large scenes need it raised to avoid clipping far geometry;
+    /// small ones can lower it to stop escaping rays sooner.
+    pub far_clip: FLOAT,
+    /// Isolevel subtracted from every object's distance, so surfaces are
+    /// found where the field equals `iso` instead of zero. Positive values
+    /// inflate all geometry by that many world units and negative values
+    /// shrink it, which previews wall thickness or offsets a mesh export.
+    pub iso: FLOAT,
+    /// Caustics gathered at diffuse hits, built with
+    /// [`PhotonMap::build`](caustics::PhotonMap::build) once the scene is
+    /// complete.
+    #[cfg(feature = "caustics")]
+    pub photons: Option<caustics::PhotonMap>,
+    /// Participating media that rays are traced through.
+    #[cfg(feature = "volumetrics")]
+    pub volumes: Vec<volume::Volume>,
+}
+
+impl Default for Scene {
+    fn default() -> Scene {
+        Scene {
+            objects: Vec::new(),
+            lights: Vec::new(),
+            sky: Sky::default(),
+            far_clip: MAX_DEPTH,
+            iso: 0.0,
+            #[cfg(feature = "caustics")]
+            photons: None,
+            #[cfg(feature = "volumetrics")]
+            volumes: Vec::new(),
+        }
+    }
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    pub fn add<T: Sdf + 'static>(&mut self, sdf: T) {
+        self.add_with_material(sdf, Material::default());
+    }
+
+    pub fn add_with_material<T: Sdf + 'static>(&mut self, sdf: T, material: Material) {
+        self.add_object(Object::new(sdf, material));
+    }
+
+    pub fn add_object(&mut self, object: Object) {
+        self.objects.push(object);
+    }
+
+    pub fn add_light(&mut self, light: PointLight) {
+        self.lights.push(light);
+    }
+
+    #[cfg(feature = "volumetrics")]
+    pub fn add_volume(&mut self, volume: volume::Volume) {
+        self.volumes.push(volume);
+    }
+
+    pub fn stats(&self) -> SceneStats {
+        self.objects
+            .iter()
+            .fold(SceneStats::default(), |stats, object| stats + object.stats())
+    }
+
+    /// The objects that are currently enabled.
+    pub fn enabled_objects(&self) -> impl Iterator<Item = &Object> {
+        self.objects.iter().filter(|object| object.enabled)
+    }
+
+    /// Enables or disables the object at `index`, returning whether it is
+    /// now enabled, or `None` if there is no such object.
+    pub fn toggle(&mut self, index: usize) -> Option<bool> {
+        let object = self.objects.get_mut(index)?;
+        object.enabled = !object.enabled;
+        Some(object.enabled)
+    }
+
+    /// The union of the bounds of the enabled objects, grown by a positive
+    /// [`Scene::iso`], or `None` if there are none or one is unbounded.
+    pub fn bounds(&self) -> Option<Aabb> {
+        let mut objects = self.enabled_objects();
+        let first = objects.next()?.bounds()?;
+        let bounds =
+            objects.try_fold(first, |bounds, object| Some(bounds.union(&object.bounds()?)))?;
+        let grow = Vec3::splat(self.iso.max(0.0));
+        Some(Aabb::new(bounds.min - grow, bounds.max + grow))
+    }
+
+    /// The signed distance from `point` to the nearest enabled surface in the
+    /// scene, offset by [`Scene::iso`], or `FLOAT::MAX` if there is none.
+    /// Independent of rendering, so it can drive collision queries or
+    /// particle systems.
+    pub fn distance(&self, point: Vec3) -> FLOAT {
+        self.enabled_objects()
+            .map(|object| object.distance(point) - self.iso)
+            .fold(FLOAT::MAX, FLOAT::min)
+    }
+
+    /// Up to `k` enabled objects nearest to `point`, as indices into
+    /// [`Scene::objects`] with their signed distances, nearest first. Objects
+    /// containing the point have negative distances and so come first.
+    pub fn closest_objects(&self, point: Vec3, k: usize) -> Vec<(usize, FLOAT)> {
+        let mut distances: Vec<(usize, FLOAT)> = self
+            .objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| object.enabled)
+            .map(|(index, object)| (index, object.distance(point) - self.iso))
+            .collect();
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+        distances.truncate(k);
+        distances
+    }
+
+    /// The gradient of [`Scene::distance`] at `point`, pointing away from the
+    /// nearest surface.
+    pub fn normal(&self, point: Vec3) -> Vec3 {
+        let normal = Vec3::new(
+            self.distance(point + VEC3_EPSILON_X) - self.distance(point - VEC3_EPSILON_X),
+            self.distance(point + VEC3_EPSILON_Y) - self.distance(point - VEC3_EPSILON_Y),
+            self.distance(point + VEC3_EPSILON_Z) - self.distance(point - VEC3_EPSILON_Z),
+        );
+        normal.normalize()
+    }
+
+    /// Walks `point` onto the nearest surface by repeatedly stepping along the
+    /// normal by the signed distance, stopping early once it is within
+    /// `MIN_DISTANCE`. Handy for placing decorations on surfaces.
+    pub fn project_to_surface(&self, mut point: Vec3, iterations: usize) -> Vec3 {
+        for _ in 0..iterations {
+            let d = self.distance(point);
+            if d.abs() < MIN_DISTANCE {
+                break;
+            }
+            point -= self.normal(point) * d;
+        }
+        point
+    }
+
+    /// Whether `ray` hits any enabled object before the far clip. See
+    /// [`Scene::ray_intersects_within`].
+    pub fn ray_intersects(&self, ray: &Ray) -> bool {
+        self.ray_intersects_within(ray, self.far_clip)
+    }
+
+    /// Whether `ray` hits any enabled object within `max_distance` of its
+    /// origin, and within the ray's own range. The march gives up as soon
+    /// as it comes within `MIN_DISTANCE` of a surface, without working out
+    /// the hit point, normal or object, so it is the cheaper choice for
+    /// shadow and visibility tests.
+    pub fn ray_intersects_within(&self, ray: &Ray, max_distance: FLOAT) -> bool {
+        let mut t = ray.t_min;
+        let end = max_distance.min(ray.t_max);
+        while t < end {
+            let (step, _) = nearest_step(self, ray.position + ray.direction * t);
+            if step < MIN_DISTANCE {
+                return true;
+            }
+            t += step;
+        }
+        false
+    }
+}
+
+/// Fluent construction of a [`Scene`], e.g.
+/// `SceneBuilder::new().add(sphere).light(light).build()`.
+#[derive(Default)]
+pub struct SceneBuilder {
+    scene: Scene,
+}
+
+impl SceneBuilder {
+    pub fn new() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<T: Sdf + 'static>(mut self, sdf: T) -> SceneBuilder {
+        self.scene.add(sdf);
+        self
+    }
+
+    pub fn add_with_material<T: Sdf + 'static>(mut self, sdf: T, material: Material) -> SceneBuilder {
+        self.scene.add_with_material(sdf, material);
+        self
+    }
+
+    pub fn add_object(mut self, object: Object) -> SceneBuilder {
+        self.scene.add_object(object);
+        self
+    }
+
+    pub fn ground(mut self, ground: GroundPlane) -> SceneBuilder {
+        self.scene.add_object(ground.into());
+        self
+    }
+
+    pub fn light(mut self, light: PointLight) -> SceneBuilder {
+        self.scene.add_light(light);
+        self
+    }
+
+    #[cfg(feature = "volumetrics")]
+    pub fn volume(mut self, volume: volume::Volume) -> SceneBuilder {
+        self.scene.add_volume(volume);
+        self
+    }
+
+    pub fn sky(mut self, sky: Sky) -> SceneBuilder {
+        self.scene.sky = sky;
+        self
+    }
+
+    pub fn far_clip(mut self, far_clip: FLOAT) -> SceneBuilder {
+        self.scene.far_clip = far_clip;
+        self
+    }
+
+    pub fn iso(mut self, iso: FLOAT) -> SceneBuilder {
+        self.scene.iso = iso;
+        self
+    }
+
+    pub fn build(self) -> Scene {
+        self.scene
+    }
+}
+
+pub fn to_color(col: Vec3) -> u32 {
+    let col = col.clamp(Vec3::ZERO, Vec3::ONE);
+    let ir = (255.99 * col.x) as u32;
+    let ig = (255.99 * col.y) as u32;
+    let ib = (255.99 * col.z) as u32;
+
+    (ir << 16) | (ig << 8) | ib
+}
+
+/// Like [`to_color`], but as the `[r, g, b, a]` bytes that image encoders
+/// expect, with alpha fully opaque.
+pub fn to_rgba8(col: Vec3) -> [u8; 4] {
+    let col = col.clamp(Vec3::ZERO, Vec3::ONE);
+    [
+        (255.99 * col.x) as u8,
+        (255.99 * col.y) as u8,
+        (255.99 * col.z) as u8,
+        255,
+    ]
+}
+
+/// [`to_rgba8`] over a whole buffer, giving four bytes per pixel.
+pub fn to_rgba8_buffer(buffer: &[Vec3]) -> Vec<u8> {
+    buffer.iter().flat_map(|&col| to_rgba8(col)).collect()
+}
+
+/// Tone maps a linear buffer with `operator` and packs it into window pixels
+/// with [`to_color`].
+pub fn to_color_buffer(buffer: &[Vec3], operator: color::ToneMap) -> Vec<u32> {
+    buffer.iter().map(|&col| to_color(color::tone_map(col, operator))).collect()
+}
+
+pub(crate) fn random_float() -> FLOAT {
+    RNG.with_borrow_mut(|rng| rng.random())
+}
+
+pub(crate) fn random_in_unit_sphere() -> Vec3 {
+    RNG.with_borrow_mut(|rng| loop {
+        let p = Vec3::new(rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0));
+        if p.length_squared() < 1.0 {
+            return p;
+        }
+    })
+}
+
+/// Builds the ray from `camera` through pixel (`x`, `y`) of a `width` x
+/// `height` image. `jitter` is a sample position in `[0, 1)²` that offsets
+/// the ray around the pixel center; with `None` the ray passes through the
+/// center.
+pub fn primary_ray(
+    camera: &Camera,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    jitter: Option<(FLOAT, FLOAT)>,
+) -> Ray {
+    let aspect_ratio = width as FLOAT / height as FLOAT;
+    let (u, v) = pixel_center(x, y, width, height);
+    let (u, v) = match jitter {
+        Some((jx, jy)) => (
+            u + (jx * 2.0 - 1.0) * RANDOM_SAMPLES_X / aspect_ratio,
+            v + (jy * 2.0 - 1.0) * RANDOM_SAMPLES_Y,
+        ),
+        None => (u, v),
+    };
+    let (radius, spread) = camera.pixel_cone(height);
+    camera.get_ray(u, v, aspect_ratio).with_cone(radius, spread)
+}
+
+/// The screen position of the center of pixel (`x`, `y`), as passed to
+/// [`Camera::get_ray`].
+fn pixel_center(x: usize, y: usize, width: usize, height: usize) -> (FLOAT, FLOAT) {
+    let u = (x as FLOAT + 0.5) * (2.0 / width as FLOAT) - 1.0;
+    let v = 1.0 - (y as FLOAT + 0.5) * (2.0 / height as FLOAT);
+    (u, v)
+}
+
+/// Focuses `camera` on the surface seen through pixel (`x`, `y`) of a
+/// `width` x `height` image, for click-to-focus. Returns the new focus
+/// distance, or `None` if the pixel shows only sky.
+pub fn focus_on_pixel(
+    camera: &mut Camera,
+    scene: &Scene,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> Option<FLOAT> {
+    let (u, v) = pixel_center(x, y, width, height);
+    camera.focus_at(scene, u, v, width as FLOAT / height as FLOAT)
+}
+
+/// Picks the sub-pixel position of sample `sample` of pixel (`x`, `y`).
+fn sample_jitter(config: &RenderConfig, x: usize, y: usize, sample: usize) -> Option<(FLOAT, FLOAT)> {
+    if !config.jitter {
+        None
+    } else if config.blue_noise {
+        let index = config.frame as usize * config.samples + sample;
+        Some(BlueNoise::shared().sample_2d(x, y, index))
+    } else {
+        Some(RNG.with_borrow_mut(|rng| (rng.random(), rng.random())))
+    }
+}
+
+/// A rectangle of pixels, in pixel coordinates from the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// The rectangle grown by `margin` pixels on every side, clipped to a
+    /// `width` x `height` image.
+    pub fn expand(&self, margin: usize, width: usize, height: usize) -> Rect {
+        let x = self.x.saturating_sub(margin);
+        let y = self.y.saturating_sub(margin);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width + margin).min(width) - x,
+            height: (self.y + self.height + margin).min(height) - y,
+        }
+    }
+
+    /// Splits the rectangle into `size` x `size` tiles in row-major order,
+    /// with narrower tiles along the right and bottom edges.
+    pub fn tiles(&self, size: usize) -> Vec<Rect> {
+        let mut tiles = Vec::new();
+        for y in (self.y..self.y + self.height).step_by(size) {
+            for x in (self.x..self.x + self.width).step_by(size) {
+                tiles.push(Rect {
+                    x,
+                    y,
+                    width: size.min(self.x + self.width - x),
+                    height: size.min(self.y + self.height - y),
+                });
+            }
+        }
+        tiles
+    }
+}
+
+/// Edge length of the tiles reported by [`render_frame_with_progress`].
+pub const TILE_SIZE: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub jitter: bool,
+    /// Takes jittered sample positions from a blue-noise tile rather than
+    /// white noise, which looks much smoother at low sample counts.
+    pub blue_noise: bool,
+    /// Index of the frame being rendered, used to vary deterministic
+    /// sample patterns from one frame to the next.
+    pub frame: u32,
+    pub mode: RenderMode,
+    /// Renders on a dedicated rayon pool of this many threads instead of the
+    /// global pool, to cap CPU usage or pin benchmark thread counts. Each
+    /// pool is built the first time its thread count is asked for.
+    pub threads: Option<usize>,
+    /// Only renders the pixels inside this region, leaving the rest of the
+    /// buffer untouched.
+    pub crop: Option<Rect>,
+    /// Applied when converting the linear frame for display or export.
+    pub tone_map: color::ToneMap,
+    /// Darkens shaded frames with screen-space ambient occlusion computed
+    /// from an extra depth pass.
+    pub ssao: Option<ssao::Ssao>,
+    /// Adds screen-space reflections to metal surfaces in shaded frames,
+    /// from an extra pass over the primary hits.
+    pub ssr: Option<ssr::Ssr>,
+    /// Marches primary rays as cones one pixel wide, see [`march_cone`].
+    /// Faster on distant geometry and anti-aliases silhouettes, at the cost
+    /// of surfaces being found slightly in front of where they are.
+    pub cone_march: bool,
+    /// Over-relaxation factor for primary rays, see [`march_relaxed`]. 1
+    /// marches plain sphere-tracing steps.
+    pub relaxation: FLOAT,
+}
+
+impl Default for RenderConfig {
+    fn default() -> RenderConfig {
+        RenderConfig {
+            width: WIDTH,
+            height: HEIGHT,
+            samples: SAMPLES,
+            jitter: JITTER,
+            blue_noise: true,
+            frame: 0,
+            mode: RenderMode::Shaded,
+            threads: None,
+            crop: None,
+            tone_map: color::ToneMap::default(),
+            ssao: None,
+            ssr: None,
+            cone_march: false,
+            relaxation: 1.0,
+        }
+    }
+}
+
+/// Renders a frame of linear colors into `buffer`, one entry per pixel in
+/// row-major order. The buffer is resized to the configured resolution if
+/// needed; with a crop region only the pixels inside it are written.
+pub fn render_frame(scene: &Scene, camera: &Camera, config: &RenderConfig, buffer: &mut Vec<Vec3>) {
+    in_pool(config, || render_pixels(scene, camera, config, buffer));
+    apply_ssao(scene, camera, config, buffer);
+    apply_ssr(scene, camera, config, buffer);
+}
+
+/// A frame both as rendered and as displayed.
+pub struct Frame {
+    /// Linear colors, unclamped, for saving in a high dynamic range format.
+    pub hdr: Vec<Vec3>,
+    /// The colors tone mapped with [`RenderConfig::tone_map`] and clamped
+    /// into window pixels.
+    pub ldr: Vec<u32>,
+}
+
+/// Renders a frame like [`render_frame`], returning the linear colors along
+/// with their display encoding so capture and display can be handled apart.
+pub fn render_frame_display(scene: &Scene, camera: &Camera, config: &RenderConfig) -> Frame {
+    let mut hdr = Vec::new();
+    render_frame(scene, camera, config, &mut hdr);
+    let ldr = to_color_buffer(&hdr, config.tone_map);
+    Frame { hdr, ldr }
+}
+
+/// Renders the left half of the frame from `left` and the right half from
+/// `right`, each a scene with its own config, for comparing materials or
+/// settings on one screen. Both halves are seen through `camera` at the
+/// resolution of the left config, so each shows its own half of the same
+/// view; the crop regions of the configs are ignored.
+pub fn render_frame_side_by_side(
+    left: (&Scene, &RenderConfig),
+    right: (&Scene, &RenderConfig),
+    camera: &Camera,
+    buffer: &mut Vec<Vec3>,
+) {
+    let (width, height) = (left.1.width, left.1.height);
+    let split = width / 2;
+    buffer.resize(width * height, Vec3::ZERO);
+    let mut half = Vec::new();
+    for ((scene, config), x, half_width) in [(left, 0, split), (right, split, width - split)] {
+        let half_config = RenderConfig {
+            width,
+            height,
+            crop: Some(Rect {
+                x,
+                y: 0,
+                width: half_width,
+                height,
+            }),
+            ..config.clone()
+        };
+        // Each half renders into a frame of its own, so screen-space effects
+        // of one scene never darken or reflect into the other.
+        half.clone_from(buffer);
+        render_frame(scene, camera, &half_config, &mut half);
+        for (row, source) in buffer.chunks_mut(width).zip(half.chunks(width)) {
+            row[x..x + half_width].copy_from_slice(&source[x..x + half_width]);
+        }
+    }
+}
+
+/// Renders like [`render_frame`], but stops taking further samples once
+/// `budget` of wall-clock time has passed, so the frame arrives in about
+/// that time however expensive the scene. Samples are taken in passes of
+/// one per pixel over the whole frame, which keeps the noise even across
+/// the image. The budget is only checked between passes and the first pass
+/// always completes, so a frame can overrun it by up to one pass. As with
+/// [`render_frame`], only the pixels inside the crop region are written.
+/// Returns the number of samples per pixel taken, at most `config.samples`,
+/// to weight the frame by when accumulating it.
+pub fn render_frame_within(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    budget: std::time::Duration,
+    buffer: &mut Vec<Vec3>,
+) -> usize {
+    let start = std::time::Instant::now();
+    let max_samples = config.samples.max(1);
+    let mut pass_config = RenderConfig {
+        samples: 1,
+        ..config.clone()
+    };
+    let mut pass = Vec::new();
+    let crop = config.crop.unwrap_or(Rect {
+        x: 0,
+        y: 0,
+        width: config.width,
+        height: config.height,
+    });
+    let cropped_rows = || {
+        (crop.y..crop.y + crop.height).map(|y| {
+            let start = y * config.width + crop.x;
+            start..start + crop.width
+        })
+    };
+    buffer.resize(config.width * config.height, Vec3::ZERO);
+    for row in cropped_rows() {
+        buffer[row].fill(Vec3::ZERO);
+    }
+    let mut samples = 0;
+    in_pool(config, || {
+        while samples < max_samples && (samples == 0 || start.elapsed() < budget) {
+            // Number the passes the way a full frame numbers its samples, so
+            // every pass of every frame gets its own sample pattern.
+            pass_config.frame = config
+                .frame
+                .wrapping_mul(max_samples as u32)
+                .wrapping_add(samples as u32);
+            render_pixels(scene, camera, &pass_config, &mut pass);
+            for row in cropped_rows() {
+                for (sum, color) in buffer[row.clone()].iter_mut().zip(&pass[row]) {
+                    *sum += *color;
+                }
+            }
+            samples += 1;
+        }
+    });
+    for row in cropped_rows() {
+        for color in &mut buffer[row] {
+            *color /= samples as FLOAT;
+        }
+    }
+    apply_ssao(scene, camera, config, buffer);
+    apply_ssr(scene, camera, config, buffer);
+    samples
+}
+
+/// Renders like [`render_frame`], but in [`TILE_SIZE`] tiles, calling
+/// `on_tile` with each tile's rectangle and row-major pixels as soon as it is
+/// done so a host application can show progress. Tiles finish in no
+/// particular order and the callback runs on the render threads. Screen
+/// space ambient occlusion needs the whole frame, so it is only applied to
+/// `buffer` after the last tile.
+pub fn render_frame_with_progress<F: Fn(Rect, &[Vec3]) + Sync>(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    buffer: &mut Vec<Vec3>,
+    on_tile: F,
+) {
+    let region = config.crop.unwrap_or(Rect {
+        x: 0,
+        y: 0,
+        width: config.width,
+        height: config.height,
+    });
+    let tiles: Vec<(Rect, Vec<Vec3>)> = in_pool(config, || {
+        region
+            .tiles(TILE_SIZE)
+            .into_par_iter()
+            .map(|tile| {
+                let pixels: Vec<Vec3> = (0..tile.width * tile.height)
+                    .map(|i| {
+                        let (x, y) = (tile.x + i % tile.width, tile.y + i / tile.width);
+                        render_pixel(scene, camera, config, x, y)
+                    })
+                    .collect();
+                on_tile(tile, &pixels);
+                (tile, pixels)
+            })
+            .collect()
+    });
+    buffer.resize(config.width * config.height, Vec3::ZERO);
+    for (tile, pixels) in tiles {
+        for (row, source) in pixels.chunks(tile.width).enumerate() {
+            let start = (tile.y + row) * config.width + tile.x;
+            buffer[start..start + tile.width].copy_from_slice(source);
+        }
+    }
+    apply_ssao(scene, camera, config, buffer);
+    apply_ssr(scene, camera, config, buffer);
+}
+
+fn apply_ssao(scene: &Scene, camera: &Camera, config: &RenderConfig, buffer: &mut [Vec3]) {
+    if let (Some(settings), RenderMode::Shaded) = (&config.ssao, config.mode) {
+        let crop = config.crop.unwrap_or(Rect {
+            x: 0,
+            y: 0,
+            width: config.width,
+            height: config.height,
+        });
+        // Pixels near the edge of the crop region compare depths with their
+        // neighbours outside it, so those are rendered too.
+        let depth_config = RenderConfig {
+            samples: 1,
+            jitter: false,
+            mode: RenderMode::Depth,
+            crop: Some(crop.expand(settings.radius, config.width, config.height)),
+            ssao: None,
+            ..config.clone()
+        };
+        let mut depth = Vec::new();
+        in_pool(config, || render_pixels(scene, camera, &depth_config, &mut depth));
+        let occlusion = ssao::occlusion(&depth, config.width, config.height, scene.far_clip, settings);
+        for (i, (pixel, visibility)) in buffer.iter_mut().zip(occlusion).enumerate() {
+            if crop.contains(i % config.width, i / config.width) {
+                *pixel *= visibility;
+            }
+        }
+    }
+}
+
+/// The entry of [`render_object_ids`] for pixels that see no object.
+pub const NO_OBJECT: u32 = u32::MAX;
+
+/// Renders an object ID pass into `ids`, parallel to the color buffer of
+/// [`render_frame`]: each pixel holds the index into [`Scene::objects`] of
+/// the object seen through its center, or [`NO_OBJECT`] for the sky. The
+/// buffer is resized and cropped the same way as the color buffer.
+pub fn render_object_ids(scene: &Scene, camera: &Camera, config: &RenderConfig, ids: &mut Vec<u32>) {
+    render_primary_hits(scene, camera, config, ids, NO_OBJECT, |hit| hit.object as u32);
+}
+
+/// Renders a G-buffer of the world-space unit normals of the surfaces seen
+/// through each pixel center into `normals`, with zero for the sky. The
+/// buffer is resized and cropped the same way as the color buffer.
+pub fn render_normals(scene: &Scene, camera: &Camera, config: &RenderConfig, normals: &mut Vec<Vec3>) {
+    render_primary_hits(scene, camera, config, normals, Vec3::ZERO, |hit| hit.normal);
+}
+
+/// Fills `buffer` with `pass` of the hit seen through each pixel center, or
+/// `background` where the primary ray misses.
+fn render_primary_hits<T: Copy + Send + Sync>(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    buffer: &mut Vec<T>,
+    background: T,
+    pass: impl Fn(&HitRecord) -> T + Sync,
+) {
+    buffer.resize(config.width * config.height, background);
+    let crop = config.crop.unwrap_or(Rect {
+        x: 0,
+        y: 0,
+        width: config.width,
+        height: config.height,
+    });
+    in_pool(config, || {
+        buffer
+            .par_chunks_mut(config.width)
+            .enumerate()
+            .skip(crop.y)
+            .take(crop.height)
+            .for_each(|(y, row)| {
+                for (x, value) in row.iter_mut().enumerate().skip(crop.x).take(crop.width) {
+                    let ray = primary_ray(camera, x, y, config.width, config.height, None);
+                    let march = march_relaxed(&ray, scene, 0.0, config.relaxation);
+                    *value = march.hit.as_ref().map_or(background, &pass);
+                }
+            });
+    });
+}
+
+fn apply_ssr(scene: &Scene, camera: &Camera, config: &RenderConfig, buffer: &mut [Vec3]) {
+    if let (Some(settings), RenderMode::Shaded) = (&config.ssr, config.mode) {
+        let mut surfaces = Vec::new();
+        render_primary_hits(scene, camera, config, &mut surfaces, None, |hit| {
+            let material = scene.objects[hit.object].material.resolve(hit);
+            Some(ssr::Surface {
+                position: hit.p,
+                normal: hit.normal,
+                reflective: matches!(material, Material::Metal { .. }),
+            })
+        });
+        let crop = config.crop.unwrap_or(Rect {
+            x: 0,
+            y: 0,
+            width: config.width,
+            height: config.height,
+        });
+        ssr::apply(buffer, &surfaces, camera, config.width, config.height, crop, settings);
+    }
+}
+
+/// Renders like [`render_frame`], but starts each pixel's primary march
+/// from the previous frame's hit reprojected through `cache`, and then
+/// records this frame's hits in it. Pixels whose seed misses are marched
+/// from the camera as usual. The whole frame is rendered regardless of the
+/// crop region.
+pub fn render_frame_reprojected(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    cache: &mut HitCache,
+    buffer: &mut Vec<Vec3>,
+) {
+    let seeds = cache.seeds(camera, config.width, config.height);
+    let mut points = vec![None; config.width * config.height];
+    buffer.resize(config.width * config.height, Vec3::ZERO);
+    in_pool(config, || {
+        buffer
+            .par_chunks_mut(config.width)
+            .zip(points.par_chunks_mut(config.width))
+            .enumerate()
+            .for_each(|(y, (row, hits))| {
+                for x in 0..config.width {
+                    let seed = seeds[y * config.width + x];
+                    (row[x], hits[x]) = render_pixel_seeded(scene, camera, config, x, y, seed);
+                }
+            });
+    });
+    cache.store(camera, config.width, config.height, points);
+}
+
+/// Runs `render` on the thread pool requested by `config`.
+fn in_pool<T: Send>(config: &RenderConfig, render: impl FnOnce() -> T + Send) -> T {
+    match config.threads {
+        Some(threads) => thread_pool(threads).install(render),
+        None => render(),
+    }
+}
+
+/// The pool of `threads` render threads, built on first use and kept for
+/// every later frame that asks for the same count.
+fn thread_pool(threads: usize) -> &'static rayon::ThreadPool {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock};
+    static POOLS: OnceLock<Mutex<HashMap<usize, &'static rayon::ThreadPool>>> = OnceLock::new();
+    let mut pools = POOLS.get_or_init(Default::default).lock().unwrap();
+    pools.entry(threads).or_insert_with(|| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("failed to build render thread pool");
+        Box::leak(Box::new(pool))
+    })
+}
+
+/// Renders a frame with motion blur over the camera's shutter interval.
+/// Each of the configured samples per pixel is rendered as a separate pass
+/// of the scene that `build_scene` returns for a time spread evenly across
+/// the interval, and the passes are averaged. The times are offset from
+/// frame to frame, so accumulating frames fills in the motion smoothly.
+pub fn render_frame_motion_blur<F: Fn(FLOAT) -> Scene>(
+    build_scene: F,
+    camera: &Camera,
+    config: &RenderConfig,
+    buffer: &mut Vec<Vec3>,
+) {
+    let passes = config.samples.max(1);
+    let offset = (config.frame as FLOAT * 0.618_034).fract();
+    let mut sum = vec![Vec3::ZERO; config.width * config.height];
+    let mut pass = Vec::new();
+    for i in 0..passes {
+        let time = camera.shutter_time((i as FLOAT + offset) / passes as FLOAT);
+        let scene = build_scene(time);
+        // Rays of this pass all sample the pass's time.
+        let camera = Camera {
+            shutter_open: time,
+            shutter_close: time,
+            ..camera.clone()
+        };
+        let pass_config = RenderConfig {
+            samples: 1,
+            frame: config.frame.wrapping_mul(passes as u32).wrapping_add(i as u32),
+            ..config.clone()
+        };
+        pass.clone_from(buffer);
+        render_frame(&scene, &camera, &pass_config, &mut pass);
+        for (total, color) in sum.iter_mut().zip(&pass) {
+            *total += *color;
+        }
+    }
+    // Each pass starts from a copy of `buffer`, so pixels outside the crop
+    // region average back to their previous values.
+    let scale = 1.0 / passes as FLOAT;
+    buffer.clear();
+    buffer.extend(sum.into_iter().map(|total| total * scale));
+}
+
+/// Renders at `1 / factor` of the configured resolution and upscales the
+/// result into `buffer`, for responsive previews while the view is moving.
+/// The whole frame is rendered regardless of the crop region.
+pub fn render_frame_coarse(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    factor: usize,
+    buffer: &mut Vec<Vec3>,
+) {
+    let coarse_config = RenderConfig {
+        width: config.width.div_ceil(factor),
+        height: config.height.div_ceil(factor),
+        crop: None,
+        ..config.clone()
+    };
+    let mut coarse = Vec::new();
+    render_frame(scene, camera, &coarse_config, &mut coarse);
+    *buffer = upscale(&coarse, factor, config.width, config.height);
+}
+
+/// Averages each `factor` x `factor` block of a `width` x `height` buffer
+/// into one pixel. `width` and `height` must be multiples of `factor`.
+pub fn downsample(buffer: &[Vec3], width: usize, height: usize, factor: usize) -> Vec<Vec3> {
+    let small_width = width / factor;
+    let small_height = height / factor;
+    let scale = 1.0 / (factor * factor) as FLOAT;
+    (0..small_width * small_height)
+        .map(|pos| {
+            let x = (pos % small_width) * factor;
+            let y = (pos / small_width) * factor;
+            let mut sum = Vec3::ZERO;
+            for row in buffer[y * width..].chunks(width).take(factor) {
+                sum += row[x..x + factor].iter().sum::<Vec3>();
+            }
+            sum * scale
+        })
+        .collect()
+}
+
+/// Renders a high quality still independent of the interactive settings:
+/// `factor` times the configured resolution with `samples` samples per
+/// pixel, box-downsampled back to the configured resolution. The crop
+/// region is ignored.
+pub fn render_screenshot(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    factor: usize,
+    samples: usize,
+) -> Vec<Vec3> {
+    let large_config = RenderConfig {
+        width: config.width * factor,
+        height: config.height * factor,
+        samples,
+        jitter: true,
+        crop: None,
+        ..config.clone()
+    };
+    let mut large = Vec::new();
+    render_frame(scene, camera, &large_config, &mut large);
+    downsample(&large, large_config.width, large_config.height, factor)
+}
+
+/// Renders a still by accumulating frames of `config.samples` samples each
+/// until the variance of every pixel's luminance drops to `target_variance`
+/// or `max_samples` samples per pixel have been taken, leaving the average
+/// in `buffer`. Returns the number of samples per pixel taken.
+pub fn render_converged(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    target_variance: FLOAT,
+    max_samples: usize,
+    buffer: &mut Vec<Vec3>,
+) -> usize {
+    let mut config = config.clone();
+    config.samples = config.samples.max(1);
+    buffer.resize(config.width * config.height, Vec3::ZERO);
+    let mut accumulator = accumulator::Accumulator::new(buffer.len());
+    let mut samples = 0;
+    while samples < max_samples && accumulator.variance() > target_variance {
+        render_frame(scene, camera, &config, buffer);
+        accumulator.add_weighted(buffer, config.samples as FLOAT);
+        samples += config.samples;
+        config.frame = config.frame.wrapping_add(1);
+    }
+    accumulator.resolve(buffer);
+    samples
+}
+
+/// Expands a buffer rendered at `1 / factor` resolution to `width` x
+/// `height` by repeating each coarse pixel over a `factor` x `factor` block.
+pub fn upscale(coarse: &[Vec3], factor: usize, width: usize, height: usize) -> Vec<Vec3> {
+    let coarse_width = width.div_ceil(factor);
+    (0..width * height)
+        .map(|pos| {
+            let x = (pos % width) / factor;
+            let y = (pos / width) / factor;
+            coarse[y * coarse_width + x]
+        })
+        .collect()
+}
+
+fn render_pixels(scene: &Scene, camera: &Camera, config: &RenderConfig, buffer: &mut Vec<Vec3>) {
+    buffer.resize(config.width * config.height, Vec3::ZERO);
+    let crop = config.crop.unwrap_or(Rect {
+        x: 0,
+        y: 0,
+        width: config.width,
+        height: config.height,
+    });
+    buffer
+        .par_chunks_mut(config.width)
+        .enumerate()
+        .skip(crop.y)
+        .take(crop.height)
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate().skip(crop.x).take(crop.width) {
+                *pixel = render_pixel(scene, camera, config, x, y);
+            }
+        });
+}
+
+fn render_pixel(scene: &Scene, camera: &Camera, config: &RenderConfig, x: usize, y: usize) -> Vec3 {
+    render_pixel_seeded(scene, camera, config, x, y, None).0
+}
+
+/// Renders a pixel whose primary marches start `seed` along the ray when
+/// given, returning its color and where its first sample hit the scene.
+fn render_pixel_seeded(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    x: usize,
+    y: usize,
+    seed: Option<FLOAT>,
+) -> (Vec3, Option<Vec3>) {
+    seed_rng(pixel_seed(x, y, config.frame));
+    let mut first_hit = None;
+    let color = (0..config.samples).fold(Vec3::ZERO, |c, sample| {
+        let jitter = sample_jitter(config, x, y, sample);
+        let ray = primary_ray(camera, x, y, config.width, config.height, jitter);
+        if config.cone_march {
+            let (radius, spread) = camera.pixel_cone(config.height);
+            let cone = march_cone(&ray, scene, seed.unwrap_or(0.0), radius, spread);
+            let cone = match cone.hit {
+                None if seed.is_some() => march_cone(&ray, scene, 0.0, radius, spread),
+                _ => cone,
+            };
+            if sample == 0 && cone.coverage == 1.0 {
+                first_hit = cone.hit.as_ref().map(|hit| hit.p);
+            }
+            return render_cone(ray, cone, scene, config.mode) + c;
+        }
+        let march = seed
+            .map(|start| march_relaxed(&ray, scene, start, config.relaxation))
+            .filter(|march| march.hit.is_some())
+            .unwrap_or_else(|| march_relaxed(&ray, scene, 0.0, config.relaxation));
+        if sample == 0 {
+            first_hit = march.hit.as_ref().map(|hit| hit.p);
+        }
+        render_march(ray, march, scene, config.mode) + c
+    });
+
+    (color / config.samples as FLOAT, first_hit)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    Shaded,
+    Normals,
+    Depth,
+    Steps,
+}
+
+/// Number keys 1-4 select the render mode in the interactive window.
+pub fn render_mode_for_key(key: Key) -> Option<RenderMode> {
+    match key {
+        Key::Key1 => Some(RenderMode::Shaded),
+        Key::Key2 => Some(RenderMode::Normals),
+        Key::Key3 => Some(RenderMode::Depth),
+        Key::Key4 => Some(RenderMode::Steps),
+        _ => None,
+    }
+}
+
+/// The outcome of sphere tracing a single ray.
+pub struct March {
+    pub hit: Option<HitRecord>,
+    pub steps: usize,
+}
+
+/// Sphere traces `ray` through the scene. Distances are taken as absolute
+/// values, so a ray travelling inside a transmissive object marches to the
+/// surface it exits through, and each object's step is divided by its
+/// [`Sdf::lipschitz`] bound so warped fields are not overshot. Only
+/// surfaces within the ray's [`Ray::t_min`] to [`Ray::t_max`] range and
+/// before [`Scene::far_clip`] are found.
+pub fn march(ray: &Ray, scene: &Scene) -> March {
+    march_from(ray, scene, 0.0)
+}
+
+/// Like [`march`], but starts `start` along the ray, or at [`Ray::t_min`]
+/// if that is further, skipping whatever lies before it.
+pub fn march_from(ray: &Ray, scene: &Scene, start: FLOAT) -> March {
+    march_relaxed(ray, scene, start, 1.0)
+}
+
+/// Like [`march_from`], but over-relaxed: each step is the safe distance
+/// multiplied by `relaxation`, which takes fewer steps through smooth
+/// fields. When the safe spheres around the last two points fail to
+/// overlap, the step may have jumped a surface, so the march returns to the
+/// previous point and carries on with plain steps. A `relaxation` of 1 is
+/// plain sphere tracing; values much above 1.5 backtrack too often to pay.
+pub fn march_relaxed(ray: &Ray, scene: &Scene, start: FLOAT, relaxation: FLOAT) -> March {
+    let start = start.max(ray.t_min);
+    let far = ray.t_max.min(scene.far_clip);
+    let mut p = ray.position + ray.direction * start;
+    let mut t = start;
+    let mut steps = 0;
+    let mut omega = relaxation.max(1.0);
+    let mut last_radius = 0.0;
+    let mut last_step = 0.0;
+    loop {
+        steps += 1;
+        let (min_distance, nearest) = nearest_step(scene, p);
+        if omega > 1.0 && min_distance + last_radius < last_step {
+            p -= ray.direction * (last_step - last_radius);
+            t -= last_step - last_radius;
+            last_step = last_radius;
+            omega = 1.0;
+            continue;
+        }
+        if t + min_distance > far {
+            return March { hit: None, steps };
+        }
+        if min_distance < MIN_DISTANCE {
+            p += ray.direction * min_distance;
+            t += min_distance;
+            let normal = scene.objects[nearest].normal(p);
+            let hit = HitRecord::new(t, p, normal, nearest);
+            return March { hit: Some(hit), steps };
+        }
+        let step = min_distance * omega;
+        p += ray.direction * step;
+        t += step;
+        last_radius = min_distance;
+        last_step = step;
+    }
+}
+
+/// The safe step from `p` and the index of the enabled object that limits
+/// it.
+fn nearest_step(scene: &Scene, p: Vec3) -> (FLOAT, usize) {
+    let mut min_distance = FLOAT::MAX;
+    let mut nearest = 0;
+    for (index, object) in scene.objects.iter().enumerate() {
+        if !object.enabled {
+            continue;
+        }
+        let d = (object.distance(p) - scene.iso).abs() / object.lipschitz();
+        if d < min_distance {
+            min_distance = d;
+            nearest = index;
+        }
+    }
+    (min_distance, nearest)
+}
+
+/// A march is accepted as a hit once the surface is this fraction of the
+/// cone radius away.
+const CONE_HIT_RATIO: FLOAT = 0.25;
+
+/// The outcome of [`march_cone`].
+pub struct ConeMarch {
+    /// The accepted hit, or for a near miss the point where the ray passed
+    /// closest to a surface relative to the cone's width.
+    pub hit: Option<HitRecord>,
+    /// The fraction of the cone covered by the `hit` surface: 1 for a hit
+    /// and less for a near miss, so silhouettes blend into what is behind.
+    pub coverage: FLOAT,
+    pub steps: usize,
+}
+
+/// Marches a cone around `ray` rather than a line: its radius is `radius`
+/// at the origin and grows by `spread` per unit along the ray, see
+/// [`Camera::pixel_cone`]. The march stops as soon as a surface is well
+/// within the cone, so distant geometry is not resolved finer than a
+/// pixel. Rays that pass a surface within the cone report it as partially
+/// covered.
+pub fn march_cone(ray: &Ray, scene: &Scene, start: FLOAT, radius: FLOAT, spread: FLOAT) -> ConeMarch {
+    let start = start.max(ray.t_min);
+    let far = ray.t_max.min(scene.far_clip);
+    let mut p = ray.position + ray.direction * start;
+    let mut t = start;
+    let mut steps = 0;
+    let mut closest: Option<(FLOAT, FLOAT, Vec3, usize)> = None;
+    loop {
+        steps += 1;
+        let (min_distance, nearest) = nearest_step(scene, p);
+        if t + min_distance > far {
+            break;
+        }
+        let cone = (radius + spread * t).max(MIN_DISTANCE);
+        let ratio = min_distance / cone;
+        if ratio < CONE_HIT_RATIO {
+            let normal = scene.objects[nearest].normal(p);
+            let hit = HitRecord::new(t, p, normal, nearest);
+            return ConeMarch { hit: Some(hit), coverage: 1.0, steps };
+        }
+        if closest.is_none_or(|(best, ..)| ratio < best) {
+            closest = Some((ratio, t, p, nearest));
+        }
+        p += ray.direction * min_distance;
+        t += min_distance;
+    }
+    match closest.filter(|&(ratio, ..)| ratio < 1.0) {
+        // Treat the nearby surface as a straight edge beside the cone's axis,
+        // which covers half the cone at the axis and none at the rim.
+        Some((ratio, t, p, nearest)) => {
+            let normal = scene.objects[nearest].normal(p);
+            ConeMarch {
+                hit: Some(HitRecord::new(t, p, normal, nearest)),
+                coverage: 0.5 * (1.0 - ratio),
+                steps,
+            }
+        }
+        None => ConeMarch { hit: None, coverage: 0.0, steps },
+    }
+}
+
+/// A slow brute-force oracle for [`march`]: samples the scene distance at
+/// fixed `step` intervals along `ray` up to the far clip, and returns the
+/// distance of the first sign change, refined by bisection. Far too slow
+/// for rendering, but it cannot step over a surface thicker than `step`.
+pub fn march_reference(ray: &Ray, scene: &Scene, step: FLOAT) -> Option<FLOAT> {
+    let at = |t: FLOAT| scene.distance(ray.position + ray.direction * t);
+    let far = ray.t_max.min(scene.far_clip);
+    let mut t = ray.t_min;
+    let mut d = at(t);
+    while t < far {
+        let next = t + step;
+        let next_d = at(next);
+        if (d > 0.0) != (next_d > 0.0) {
+            let (mut low, mut high) = (t, next);
+            for _ in 0..32 {
+                let mid = 0.5 * (low + high);
+                if (at(mid) > 0.0) == (d > 0.0) {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            return Some(0.5 * (low + high));
+        }
+        t = next;
+        d = next_d;
+    }
+    None
+}
+
+pub fn raycast(ray: &Ray, scene: &Scene) -> Option<HitRecord> {
+    march(ray, scene).hit
+}
+
+/// Colors a single camera ray according to `mode`.
+pub fn render_ray(ray: Ray, scene: &Scene, mode: RenderMode) -> Vec3 {
+    let march = march(&ray, scene);
+    render_march(ray, march, scene, mode)
+}
+
+/// Colors a camera ray whose primary march has already been done.
+fn render_march(ray: Ray, march: March, scene: &Scene, mode: RenderMode) -> Vec3 {
+    match mode {
+        RenderMode::Shaded => {
+            let t = march.hit.as_ref().map_or(scene.far_clip, |hit| hit.t);
+            let color = match march.hit {
+                Some(hit) => shade(ray, hit, scene, 0, None),
+                None => scene.sky.color(ray.direction),
+            };
+            through_volumes(scene, &ray, t, color)
+        }
+        RenderMode::Normals => march.hit
+            .map_or(Vec3::ZERO, |hit| hit.normal * 0.5 + Vec3::splat(0.5)),
+        RenderMode::Depth => march.hit
+            .map_or(Vec3::ZERO, |hit| Vec3::splat(1.0 - (hit.t / scene.far_clip).min(1.0))),
+        RenderMode::Steps => {
+            let heat = (march.steps as FLOAT / STEP_HEATMAP_SCALE).min(1.0);
+            Vec3::new(heat, 0.0, 1.0 - heat)
+        }
+    }
+}
+
+/// Colors a camera ray from a cone march, blending partially covered
+/// surfaces over the background in shaded mode.
+fn render_cone(ray: Ray, cone: ConeMarch, scene: &Scene, mode: RenderMode) -> Vec3 {
+    let coverage = cone.coverage;
+    match (mode, cone.hit) {
+        (RenderMode::Shaded, Some(hit)) if coverage < 1.0 => {
+            let t = hit.t;
+            let background = scene.sky.color(ray.direction);
+            let surface = shade(ray, hit, scene, 0, None);
+            through_volumes(scene, &ray, t, surface * coverage + background * (1.0 - coverage))
+        }
+        (_, hit) => {
+            let hit = hit.filter(|_| coverage == 1.0);
+            render_march(ray, March { hit, steps: cone.steps }, scene, mode)
+        }
+    }
+}
+
+pub fn trace_ray(ray: Ray, scene: &Scene, depth : usize) -> Vec3 {
+    trace(ray, scene, depth, None, false)
+}
+
+/// Traces `ray`, following only `channel` once it has been split by a
+/// dispersive material. Components other than `channel` are meaningless in
+/// the result of a split path. `diffuse` rays left a diffuse surface that
+/// already sampled the environment map, so they gather nothing from it.
+fn trace(ray: Ray, scene: &Scene, depth: usize, channel: Option<usize>, diffuse: bool) -> Vec3 {
+    if depth > 5 {
+        return Vec3::ZERO;
+    }
+    let hit = raycast(&ray, scene);
+    let t = hit.as_ref().map_or(scene.far_clip, |hit| hit.t);
+    let color = match hit {
+        Some(hit) => shade(ray, hit, scene, depth, channel),
+        None if diffuse && scene.sky.environment.is_some() => Vec3::ZERO,
+        None => scene.sky.color(ray.direction),
+    };
+    through_volumes(scene, &ray, t, color)
+}
+
+/// `color` seen at distance `t` along `ray`, after passing through the
+/// scene's volumes.
+#[cfg(feature = "volumetrics")]
+fn through_volumes(scene: &Scene, ray: &Ray, t: FLOAT, color: Vec3) -> Vec3 {
+    volume::composite(scene, ray, t, color)
+}
+
+#[cfg(not(feature = "volumetrics"))]
+fn through_volumes(_scene: &Scene, _ray: &Ray, _t: FLOAT, color: Vec3) -> Vec3 {
+    color
+}
+
+/// Light from the environment map reaching a surface at `p` facing
+/// `normal`, estimated from one direction drawn towards bright regions of
+/// the map. Zero without an environment map.
+fn environment_light(scene: &Scene, p: Vec3, normal: Vec3) -> Vec3 {
+    let Some(environment) = &scene.sky.environment else {
+        return Vec3::ZERO;
+    };
+    let sample = environment.sample(random_float(), random_float());
+    let cos_theta = normal.dot(sample.direction);
+    if cos_theta <= 0.0 || sample.pdf <= 0.0 {
+        return Vec3::ZERO;
+    }
+    let shadow = Ray::new(p + normal * (MIN_DISTANCE * 4.0), sample.direction);
+    if scene.ray_intersects(&shadow) {
+        return Vec3::ZERO;
+    }
+    sample.radiance * (cos_theta / sample.pdf)
+}
+
+/// The light leaving `hit` back along `ray`.
+fn shade(ray: Ray, mut hit: HitRecord, scene: &Scene, depth: usize, channel: Option<usize>) -> Vec3 {
+    let object = &scene.objects[hit.object];
+    if object.two_sided {
+        hit.normal = optics::face_forward(hit.normal, ray.direction);
+    }
+    if let Some(bump) = &object.bump {
+        bump.apply(&mut hit);
+    }
+    if let Some(flat) = &object.flat_shading {
+        flat.apply(&mut hit);
+    }
+    hit.blend = object.blend_weight(hit.p);
+    hit.uv = object.uv(hit.p);
+    hit.footprint = ray.footprint(hit.t);
+    // Bounced rays carry on the cone from where it meets the surface.
+    let carry_cone = |scattered: Ray| scattered.with_cone(hit.footprint * 0.5, ray.cone_spread);
+    let material = object.material.resolve(&hit);
+    if channel.is_none() && material.is_dispersive() {
+        let mut color = Vec3::ZERO;
+        for c in 0..3 {
+            if let Some(scatter) = material.scatter_channel(&ray, &hit, object, c) {
+                let scattered = carry_cone(scatter.ray);
+                let light = trace(scattered, scene, depth + 1, Some(c), scatter.diffuse);
+                color[c] = scatter.attenuation[c] * light[c];
+            }
+        }
+        return color;
+    }
+    let scatter = match channel {
+        Some(c) => material.scatter_channel(&ray, &hit, object, c),
+        None => material.scatter(&ray, &hit, object),
+    };
+    let direct = if material.is_diffuse() {
+        #[allow(unused_mut)]
+        let mut irradiance = light::direct_light(scene, hit.p, hit.normal)
+            + environment_light(scene, hit.p, hit.normal);
+        #[cfg(feature = "caustics")]
+        if let Some(photons) = &scene.photons {
+            irradiance += photons.irradiance(hit.p, hit.normal);
+        }
+        // Light reflected by the clear coat never reaches the diffuse base.
+        let base = 1.0 - material::coat_reflectance(ray.direction, hit.normal);
+        material.albedo(&hit) * irradiance * (consts::FRAC_1_PI * base)
+    } else {
+        Vec3::ZERO
+    };
+    match scatter {
+        Some(scatter) => {
+            let scattered = carry_cone(scatter.ray);
+            let light = trace(scattered, scene, depth + 1, channel, scatter.diffuse);
+            direct + scatter.attenuation * light
+        }
+        None => direct,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centered_ray_points_down_the_view_axis() {
+        let camera = Camera::default();
+        let ray = primary_ray(&camera, 1, 1, 3, 3, None);
+        assert_eq!(ray.position, camera.position);
+        assert!((ray.direction - Vec3::Z).length() < 1e-6);
+    }
+
+    #[test]
+    fn single_unjittered_sample_is_centered() {
+        let config = RenderConfig {
+            samples: 1,
+            jitter: false,
+            ..RenderConfig::default()
+        };
+        assert_eq!(sample_jitter(&config, 10, 20, 0), None);
+        let corner = primary_ray(&Camera::default(), 0, 0, 2, 2, None);
+        let expected = Vec3::new(-0.5, 0.5, 1.0).normalize();
+        assert!((corner.direction - expected).length() < 1e-6);
+    }
+
+    #[test]
+    fn scene_stats_count_primitives_and_combinators() {
+        let scene = (3..6)
+            .fold(SceneBuilder::new(), |builder, z| {
+                let center = Vec3::new(0.0, 0.0, z as FLOAT);
+                builder.add(And {
+                    t: Cube::new(center, 0.75),
+                    u: Sphere::new(center, 1.0),
+                })
+            })
+            .build();
+        let stats = scene.stats();
+        assert_eq!(stats.combinators, 3);
+        assert_eq!(stats.primitives, 6);
+        let bounds = scene.bounds().unwrap();
+        assert_eq!(bounds.min, Vec3::new(-0.75, -0.75, 2.25));
+        assert_eq!(bounds.max, Vec3::new(0.75, 0.75, 5.75));
+    }
+
+    #[test]
+    fn solid_angle_is_negative_only_inside_the_sector() {
+        let sector = SolidAngle::new(Vec3::new(1.0, -1.0, 2.0), 0.5, 2.0);
+        let at = |p: Vec3| sector.distance(sector.center + p);
+        assert!(at(Vec3::new(0.0, 1.0, 0.0)) < 0.0);
+        assert!(at(Vec3::new(0.2, 1.5, -0.2)) < 0.0);
+        assert!(at(Vec3::new(1.0, 0.1, 0.0)) > 0.0);
+        assert!(at(Vec3::new(0.0, -1.0, 0.0)) > 0.0);
+        assert!(at(Vec3::new(0.0, 2.5, 0.0)) > 0.0);
+    }
+
+    fn test_scene() -> Scene {
+        SceneBuilder::new()
+            .add(And {
+                t: Cube::new(Vec3::new(0.0, 0.0, 4.0), 0.75),
+                u: Sphere::new(Vec3::new(0.0, 0.0, 4.0), 1.0),
+            })
+            .ground(GroundPlane::new(-1.0))
+            .light(PointLight::new(Vec3::new(2.0, 4.0, 1.0), Vec3::splat(20.0)))
+            .build()
+    }
+
+    #[test]
+    fn single_thread_pool_renders_like_the_global_pool() {
+        let scene = test_scene();
+        let camera = Camera::default();
+        let config = RenderConfig {
+            width: 32,
+            height: 24,
+            samples: 2,
+            ..RenderConfig::default()
+        };
+        let pinned = RenderConfig {
+            threads: Some(1),
+            ..config.clone()
+        };
+        let (mut global, mut single) = (Vec::new(), Vec::new());
+        render_frame(&scene, &camera, &config, &mut global);
+        render_frame(&scene, &camera, &pinned, &mut single);
+        assert_eq!(global, single);
+        assert!(std::ptr::eq(thread_pool(1), thread_pool(1)));
+        assert_eq!(thread_pool(1).current_num_threads(), 1);
+    }
+
+    #[test]
+    fn intersection_bounds_are_the_overlap_of_its_inputs() {
+        let shape = And {
+            t: AaBox::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)),
+            u: AaBox::new(Vec3::new(0.0, -2.0, 0.5), Vec3::new(3.0, 0.5, 2.0)),
+        };
+        let bounds = shape.bounds().unwrap();
+        assert_eq!(bounds.min, Vec3::new(0.0, -1.0, 0.5));
+        assert_eq!(bounds.max, Vec3::new(1.0, 0.5, 1.0));
+        let union = Or { t: shape.t, u: shape.u };
+        assert_eq!(
+            union.bounds(),
+            Some(Aabb::new(Vec3::new(-1.0, -2.0, -1.0), Vec3::new(3.0, 1.0, 2.0)))
+        );
+        let unbounded = And {
+            t: Plane::new(Vec3::Y, 0.0),
+            u: Sphere::new(Vec3::ZERO, 1.0),
+        };
+        assert_eq!(unbounded.bounds(), Sphere::new(Vec3::ZERO, 1.0).bounds());
+    }
+
+    #[test]
+    fn radial_repeat_is_symmetric_under_quarter_turns() {
+        for mirror in [false, true] {
+            let petals = RepeatRadial::new(Sphere::new(Vec3::new(2.0, 0.0, 0.3), 0.5), 4, mirror);
+            for i in 0..32 {
+                let a = i as FLOAT * 0.37;
+                let p = Vec3::new(a.cos() * 2.5, (a * 2.0).sin(), a.sin() * 1.5);
+                let turned = Vec3::new(-p.z, p.y, p.x);
+                assert!((petals.distance(p) - petals.distance(turned)).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "count must be positive")]
+    fn radial_repeat_rejects_a_zero_count() {
+        RepeatRadial::new(Sphere::new(Vec3::X, 0.5), 0, false);
+    }
+
+    /// Rays from in front of the origin fanning across a 3 x 3 square.
+    fn fan_of_rays() -> Vec<Ray> {
+        (0..49)
+            .map(|i| {
+                let target = Vec3::new((i % 7) as FLOAT - 3.0, (i / 7) as FLOAT - 3.0, 0.0) * 0.5;
+                let origin = Vec3::new(0.3, 0.2, -4.0);
+                Ray::new(origin, (target - origin).normalize())
+            })
+            .collect()
+    }
+
+    /// Checks that sphere tracing `scene` finds the same first surface along
+    /// each ray as a fine fixed-step march.
+    fn assert_marches_like_reference(scene: &Scene, rays: &[Ray]) {
+        for ray in rays {
+            let traced = raycast(ray, scene).map(|hit| hit.t);
+            let reference = march_reference(ray, scene, 0.002);
+            match (traced, reference) {
+                (Some(t), Some(r)) => assert!((t - r).abs() < 0.01, "hit at {} not {}", t, r),
+                (None, None) => {}
+                // The tracer accepts rays passing within `MIN_DISTANCE` of a
+                // surface as hits, where the reference sees no crossing.
+                (Some(t), None) => {
+                    let p = ray.position + ray.direction * t;
+                    assert!(scene.distance(p) >= 0.0, "hit at {} is inside", t);
+                }
+                _ => panic!("{:?} traced {:?}, reference {:?}", ray.direction, traced, reference),
+            }
+        }
+    }
+
+    #[test]
+    fn twisted_shape_is_not_overshot() {
+        let mut scene = Scene::new();
+        scene.add(Twist {
+            inner: AaBox::new(Vec3::new(-1.2, -1.5, -0.15), Vec3::new(1.2, 1.5, 0.15)),
+            rate: 2.0,
+        });
+        assert!(scene.objects[0].lipschitz() > 2.0);
+        assert_marches_like_reference(&scene, &fan_of_rays());
+    }
+
+    #[test]
+    fn nested_transforms_match_a_single_affine_transform() {
+        let rotation = Quat::from_rotation_y(0.8) * Quat::from_rotation_x(0.3);
+        let nested = Translate {
+            inner: Rotate {
+                inner: UniformScale {
+                    inner: Translate {
+                        inner: UniformScale {
+                            inner: Cube::new(Vec3::ZERO, 1.0),
+                            scale: 0.5,
+                        },
+                        offset: Vec3::new(0.25, 0.0, -0.5),
+                    },
+                    scale: 3.0,
+                },
+                rotation,
+            },
+            offset: Vec3::new(1.0, -2.0, 4.0),
+        };
+        let scale = |s: FLOAT| Affine3 {
+            matrix3: Mat3::IDENTITY * s,
+            translation: Vec3::ZERO,
+        };
+        let translate = |offset: Vec3| Affine3 {
+            matrix3: Mat3::IDENTITY,
+            translation: offset,
+        };
+        let rotate = Affine3 {
+            matrix3: Mat3::from_quat(rotation),
+            translation: Vec3::ZERO,
+        };
+        let composed = translate(Vec3::new(1.0, -2.0, 4.0))
+            * rotate
+            * scale(3.0)
+            * translate(Vec3::new(0.25, 0.0, -0.5))
+            * scale(0.5);
+        let object = Object::new(Cube::new(Vec3::ZERO, 1.0), Material::default())
+            .with_transform(composed);
+        for i in 0..64 {
+            let a = i as FLOAT * 0.53;
+            let offset = Vec3::new(a.sin(), (a * 0.7).cos(), (a * 1.9).sin()) * 3.0;
+            let p = Vec3::new(1.0, -2.0, 4.0) + offset;
+            assert!((nested.distance(p) - object.distance(p)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn sphere_tracing_agrees_with_the_reference_march() {
+        let shapes: Vec<Box<dyn Sdf>> = vec![
+            Box::new(Sphere::new(Vec3::new(0.2, 0.0, 0.5), 1.0)),
+            Box::new(Cube::new(Vec3::ZERO, 0.75)),
+            Box::new(AaBox::new(Vec3::new(-1.5, -0.2, -0.3), Vec3::new(0.5, 1.0, 0.3))),
+            Box::new(SolidAngle::new(Vec3::new(0.0, -1.0, 0.0), 0.6, 2.0)),
+            Box::new(Plane::new(Vec3::Y, -0.8)),
+        ];
+        for shape in shapes {
+            let mut scene = Scene::new();
+            scene.far_clip = 12.0;
+            scene.add(shape);
+            assert_marches_like_reference(&scene, &fan_of_rays());
+        }
+    }
+
+    #[test]
+    fn renders_do_not_depend_on_the_thread_count() {
+        let scene = test_scene();
+        let camera = Camera::default();
+        let config = RenderConfig {
+            width: 48,
+            height: 32,
+            samples: 3,
+            blue_noise: false,
+            frame: 70_000,
+            threads: Some(1),
+            ..RenderConfig::default()
+        };
+        let eight = RenderConfig {
+            threads: Some(8),
+            ..config.clone()
+        };
+        let (mut one, mut many) = (Vec::new(), Vec::new());
+        render_frame(&scene, &camera, &config, &mut one);
+        render_frame(&scene, &camera, &eight, &mut many);
+        assert_eq!(one, many);
+    }
+
+    #[test]
+    fn pixel_seeds_do_not_alias_across_rows_and_frames() {
+        assert_ne!(pixel_seed(0, 1, 0), pixel_seed(0, 0, 1 << 16));
+        assert_ne!(pixel_seed(3, 0, 0), pixel_seed(0, 0, 3));
+        let mut seeds: Vec<u64> = (0..64)
+            .flat_map(|y| (0..1024).map(move |frame| pixel_seed(5, y, frame * 4096)))
+            .collect();
+        seeds.sort();
+        seeds.dedup();
+        assert_eq!(seeds.len(), 64 * 1024);
+    }
+
+    #[test]
+    fn thin_intersections_and_subtractions_are_not_overshot() {
+        let lens = And {
+            t: Sphere::new(Vec3::new(0.0, 0.0, 1.9), 2.0),
+            u: Sphere::new(Vec3::new(0.0, 0.0, -1.9), 2.0),
+        };
+        let crescent = Not::new(
+            Sphere::new(Vec3::ZERO, 1.2),
+            Sphere::new(Vec3::new(0.0, 0.0, 0.05), 1.2),
+        );
+        let shell = Not::new(Sphere::new(Vec3::ZERO, 1.0), Sphere::new(Vec3::ZERO, 0.97));
+        let shapes: Vec<Box<dyn Sdf>> = vec![Box::new(lens), Box::new(crescent), Box::new(shell)];
+        for shape in shapes {
+            let mut scene = Scene::new();
+            scene.far_clip = 12.0;
+            scene.add(shape);
+            assert_marches_like_reference(&scene, &fan_of_rays());
+        }
+    }
+
+    #[test]
+    fn iso_offset_grows_the_surface_and_its_bounds() {
+        let mut scene = Scene::new();
+        scene.add(Sphere::new(Vec3::new(0.0, 0.0, 4.0), 1.0));
+        let ray = Ray::new(Vec3::ZERO, Vec3::Z);
+        let plain = raycast(&ray, &scene).unwrap().t;
+        let plain_bounds = scene.bounds().unwrap();
+        scene.iso = 0.25;
+        let inflated = raycast(&ray, &scene).unwrap().t;
+        assert!((plain - inflated - 0.25).abs() < 2.0 * MIN_DISTANCE);
+        let bounds = scene.bounds().unwrap();
+        assert_eq!(bounds.min, plain_bounds.min - 0.25);
+        assert_eq!(bounds.max, plain_bounds.max + 0.25);
+        scene.iso = -0.25;
+        assert_eq!(scene.bounds(), Some(plain_bounds));
+    }
+
+    #[test]
+    fn screen_space_passes_leave_pixels_outside_the_crop_alone() {
+        let mut scene = test_scene();
+        scene.objects[1].material = Material::Metal {
+            albedo: Vec3::splat(0.9),
+            roughness: 0.0,
+        };
+        let camera = Camera::default();
+        let config = RenderConfig {
+            width: 32,
+            height: 24,
+            samples: 1,
+            ssao: Some(ssao::Ssao::default()),
+            ssr: Some(ssr::Ssr::default()),
+            ..RenderConfig::default()
+        };
+        let crop = Rect {
+            x: 8,
+            y: 10,
+            width: 12,
+            height: 8,
+        };
+        let cropped_config = RenderConfig {
+            crop: Some(crop),
+            ..config.clone()
+        };
+        let mut full = Vec::new();
+        render_frame(&scene, &camera, &config, &mut full);
+        let mut cropped = vec![Vec3::splat(-1.0); 32 * 24];
+        render_frame(&scene, &camera, &cropped_config, &mut cropped);
+        for (i, color) in cropped.iter().enumerate() {
+            if !crop.contains(i % 32, i / 32) {
+                assert_eq!(*color, Vec3::splat(-1.0));
+            }
+        }
+        // Without reflections the cropped pixels match the full frame,
+        // occlusion included, even along the edges of the crop.
+        let no_ssr = |config: &RenderConfig| RenderConfig {
+            ssr: None,
+            ..config.clone()
+        };
+        render_frame(&scene, &camera, &no_ssr(&config), &mut full);
+        render_frame(&scene, &camera, &no_ssr(&cropped_config), &mut cropped);
+        for (i, color) in cropped.iter().enumerate() {
+            if crop.contains(i % 32, i / 32) {
+                assert_eq!(*color, full[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn instrumented_counts_one_call_per_march_step() {
+        use std::sync::atomic::Ordering;
+        let sphere = Instrumented::new(Sphere::new(Vec3::new(0.0, 0.0, 4.0), 1.0));
+        let calls = sphere.calls();
+        let mut scene = Scene::new();
+        scene.add(sphere);
+
+        let miss = march(&Ray::new(Vec3::ZERO, -Vec3::Z), &scene);
+        assert!(miss.hit.is_none());
+        assert_eq!(calls.swap(0, Ordering::Relaxed), miss.steps);
+        // A hit also takes six evaluations for the central-difference normal.
+        let hit = march(&Ray::new(Vec3::ZERO, Vec3::Z), &scene);
+        assert!(hit.hit.is_some());
+        assert_eq!(calls.load(Ordering::Relaxed), hit.steps + 6);
+    }
+
+    /// A Mandelbulb seen through a field of view so narrow that one row of
+    /// 32 pixels spans a few `f32` ulps of the surface, with the distance to
+    /// the surface point at its center.
+    fn deep_zoom() -> (Scene, Camera, FLOAT) {
+        let scene = SceneBuilder::new().add(Mandelbulb::new(8.0, 12)).build();
+        let position = Vec3::new(0.3, 0.4, -3.0);
+        let ray = Ray::new(position, (Vec3::new(0.0, 0.1, 0.0) - position).normalize());
+        let surface = march(&ray, &scene).hit.expect("zoom ray misses the bulb").p;
+        let camera = Camera { position, look_at: surface, fov: 2e-8, ..Camera::default() };
+        (scene, camera, (surface - position).length())
+    }
+
+    /// The number of runs of equal distances across the row, one per pixel
+    /// when precision resolves every step and fewer as it bands.
+    fn zoomed_bands() -> usize {
+        let (scene, camera, depth) = deep_zoom();
+        let mut distances: Vec<FLOAT> = (0..32)
+            .map(|i| {
+                let ray = camera.get_ray(i as FLOAT / 16.0 - 1.0, 0.0, 1.0);
+                scene.distance(ray.position + ray.direction * depth)
+            })
+            .collect();
+        distances.dedup();
+        distances.len()
+    }
+
+    #[cfg(not(feature = "f64"))]
+    #[test]
+    fn deep_fractal_zoom_bands_in_single_precision() {
+        assert!(zoomed_bands() <= 8);
+    }
+
+    #[cfg(feature = "f64")]
+    #[test]
+    fn deep_fractal_zoom_resolves_every_pixel_in_double_precision() {
+        assert_eq!(zoomed_bands(), 32);
+    }
+
+    #[test]
+    fn valid_constructors_keep_their_arguments() {
+        let sphere = Sphere::new(Vec3::Z, 0.5);
+        assert_eq!((sphere.center, sphere.radius), (Vec3::Z, 0.5));
+        assert_eq!(Cube::new(Vec3::ZERO, 1.0).distance(Vec3::new(2.0, 0.0, 0.0)), 1.0);
+        assert_eq!(Plane::new(Vec3::Y, -1.0).distance(Vec3::ZERO), 1.0);
+        AaBox::new(Vec3::ZERO, Vec3::ONE);
+        SolidAngle::new(Vec3::ZERO, consts::PI, 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "sphere radius must be positive")]
+    fn sphere_rejects_a_negative_radius() {
+        Sphere::new(Vec3::ZERO, -1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "cube size must be positive")]
+    fn cube_rejects_a_zero_size() {
+        Cube::new(Vec3::ZERO, 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not unit length")]
+    fn plane_rejects_an_unnormalized_normal() {
+        Plane::new(Vec3::new(0.0, 2.0, 0.0), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds max")]
+    fn box_rejects_inverted_extents() {
+        AaBox::new(Vec3::ONE, Vec3::ZERO);
+    }
+
+    #[test]
+    fn sky_is_much_brighter_looking_at_the_sun() {
+        let sky = Sky::default();
+        let away = Vec3::new(sky.sun_direction.x, -sky.sun_direction.y, -sky.sun_direction.z);
+        let at_sun = sky.color(sky.sun_direction).length();
+        assert!(at_sun > 10.0 * sky.color(away).length());
+        let edge = Quat::from_rotation_y(sky.sun_radius * 0.9) * sky.sun_direction;
+        assert!(sky.color(edge).length() > 10.0 * sky.color(away).length());
+    }
+
+    #[test]
+    fn upscale_expands_coarse_pixels_to_the_full_size() {
+        let coarse: Vec<Vec3> = (0..4).map(|i| Vec3::splat(i as FLOAT)).collect();
+        let full = upscale(&coarse, 4, 7, 6);
+        assert_eq!(full.len(), 7 * 6);
+        assert_eq!(full[0], coarse[0]);
+        assert_eq!(full[3], coarse[0]);
+        assert_eq!(full[4], coarse[1]);
+        assert_eq!(full[4 * 7], coarse[2]);
+        assert_eq!(full[7 * 6 - 1], coarse[3]);
+
+        let config = RenderConfig { width: 10, height: 6, ..RenderConfig::default() };
+        let mut buffer = Vec::new();
+        render_frame_coarse(&test_scene(), &Camera::default(), &config, 4, &mut buffer);
+        assert_eq!(buffer.len(), 10 * 6);
+    }
+
+    /// Unit spheres at x = -2, 0 and 2, five units down the Z axis.
+    fn row_of_spheres() -> Scene {
+        (0..3)
+            .fold(SceneBuilder::new(), |builder, i| {
+                builder.add(Sphere::new(Vec3::new(i as FLOAT * 2.0 - 2.0, 0.0, 5.0), 1.0))
+            })
+            .build()
+    }
+
+    #[test]
+    fn hit_record_names_the_object_hit() {
+        let scene = row_of_spheres();
+        for (object, x) in [(0, -2.0), (1, 0.0), (2, 2.0)] {
+            let ray = Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::Z);
+            let hit = raycast(&ray, &scene).unwrap();
+            assert_eq!(hit.object, object);
+            assert!((hit.t - 4.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn clipped_sphere_is_a_hemisphere_capped_at_zero() {
+        let hemisphere = Clip {
+            inner: Sphere::new(Vec3::ZERO, 1.0),
+            normal: Vec3::Y,
+            offset: 0.0,
+        };
+        // The dome is untouched and the cap is flat at y = 0.
+        assert!((hemisphere.distance(Vec3::new(0.0, 2.0, 0.0)) - 1.0).abs() < 1e-6);
+        for (x, z) in [(0.0, 0.0), (0.5, 0.0), (-0.3, 0.6)] {
+            assert!(hemisphere.distance(Vec3::new(x, 0.0, z)).abs() < 1e-6);
+            assert!(hemisphere.distance(Vec3::new(x, -0.25, z)) > 0.2);
+            assert!(hemisphere.distance(Vec3::new(x, 0.1, z)) < 0.0);
+        }
+        let scene = SceneBuilder::new().add(hemisphere).build();
+        let hit = raycast(&Ray::new(Vec3::new(0.2, -3.0, 0.1), Vec3::Y), &scene).unwrap();
+        assert!(hit.p.y.abs() < 0.01);
+        assert!((hit.normal - -Vec3::Y).length() < 0.01);
+    }
+
+    #[test]
+    fn downsampling_averages_each_block_into_one_pixel() {
+        let buffer: Vec<Vec3> = (0..8 * 4)
+            .map(|i| Vec3::new((i % 8) as FLOAT, (i / 8) as FLOAT, 1.0))
+            .collect();
+        let small = downsample(&buffer, 8, 4, 4);
+        assert_eq!(small, vec![Vec3::new(1.5, 1.5, 1.0), Vec3::new(5.5, 1.5, 1.0)]);
+    }
+
+    #[test]
+    fn sky_gradient_interpolates_between_its_stops() {
+        let (bottom, top) = (Vec3::new(1.0, 0.5, 0.0), Vec3::new(0.0, 0.5, 1.0));
+        let two = Sky {
+            gradient: vec![bottom, top],
+            ..Sky::default()
+        };
+        for t in [0.0, 0.25, 0.5, 1.0] {
+            assert!((two.gradient_color(t) - bottom.lerp(top, t)).length() < 1e-6);
+        }
+        let middle = Vec3::new(0.2, 0.9, 0.2);
+        let three = Sky {
+            gradient: vec![bottom, middle, top],
+            ..Sky::default()
+        };
+        assert_eq!(three.gradient_color(0.5), middle);
+        assert!((three.gradient_color(0.25) - bottom.lerp(middle, 0.5)).length() < 1e-6);
+        assert!((three.gradient_color(0.75) - middle.lerp(top, 0.5)).length() < 1e-6);
+        // Straight up and down reach the end stops, away from the sun.
+        assert!((three.color(-Vec3::Y) - bottom).length() < 1e-6);
+        assert!((three.color(Vec3::Y) - top).length() < 1e-6);
+    }
+
+    #[test]
+    fn morph_endpoints_reproduce_their_shapes() {
+        let sphere = || Sphere::new(Vec3::new(0.5, 0.0, 0.0), 1.0);
+        let cube = || Cube::new(Vec3::new(0.0, 0.2, 0.0), 0.8);
+        let morph = |t| Morph { a: sphere(), b: cube(), t };
+        let (start, halfway, end) = (morph(0.0), morph(0.5), morph(1.0));
+        for p in fan_of_rays().iter().map(|ray| ray.position + ray.direction * 3.0) {
+            assert_eq!(start.distance(p), sphere().distance(p));
+            assert_eq!(end.distance(p), cube().distance(p));
+            let mean = 0.5 * (sphere().distance(p) + cube().distance(p));
+            assert!((halfway.distance(p) - mean).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn translated_object_renders_like_a_placed_primitive() {
+        let center = Vec3::new(0.5, -0.25, 4.0);
+        let placed = SceneBuilder::new()
+            .add(Sphere::new(center, 1.0))
+            .light(PointLight::new(Vec3::new(2.0, 4.0, 1.0), Vec3::splat(20.0)))
+            .build();
+        let moved = SceneBuilder::new()
+            .add_object(
+                Object::new(Sphere::new(Vec3::ZERO, 1.0), Material::default())
+                    .with_transform(Affine3 {
+                        matrix3: Mat3::IDENTITY,
+                        translation: center,
+                    }),
+            )
+            .light(PointLight::new(Vec3::new(2.0, 4.0, 1.0), Vec3::splat(20.0)))
+            .build();
+        let config = RenderConfig {
+            width: 24,
+            height: 16,
+            samples: 2,
+            ..RenderConfig::default()
+        };
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        render_frame(&placed, &Camera::default(), &config, &mut a);
+        render_frame(&moved, &Camera::default(), &config, &mut b);
+        let worst = a
+            .iter()
+            .zip(&b)
+            .map(|(a, b)| (*a - *b).abs().max_element())
+            .fold(0.0, FLOAT::max);
+        assert!(worst < 1e-3, "pixels differ by up to {}", worst);
+    }
+
+    #[test]
+    fn cropped_render_only_writes_inside_the_region() {
+        let crop = Rect { x: 5, y: 3, width: 8, height: 6 };
+        let config = RenderConfig {
+            width: 20,
+            height: 12,
+            samples: 1,
+            crop: Some(crop),
+            ..RenderConfig::default()
+        };
+        let sentinel = Vec3::splat(-1.0);
+        let mut buffer = vec![sentinel; 20 * 12];
+        render_frame(&test_scene(), &Camera::default(), &config, &mut buffer);
+        for (i, pixel) in buffer.iter().enumerate() {
+            assert_eq!(*pixel == sentinel, !crop.contains(i % 20, i / 20), "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn two_sided_surfaces_face_the_viewer_from_behind() {
+        let ray = Ray::new(Vec3::new(0.0, -2.0, 0.0), Vec3::Y);
+        assert_eq!(optics::face_forward(Vec3::Y, ray.direction), -Vec3::Y);
+        assert_eq!(optics::face_forward(-Vec3::Y, ray.direction), -Vec3::Y);
+
+        // Seen from below with a light below, a plane facing up is only lit
+        // when it is two-sided.
+        let brightness = |two_sided: bool| {
+            let mut plane = Object::new(Plane::new(Vec3::Y, 0.0), Material::default());
+            plane.two_sided = two_sided;
+            let scene = SceneBuilder::new()
+                .add_object(plane)
+                .light(PointLight::new(Vec3::new(0.0, -1.0, 0.0), Vec3::splat(50.0)))
+                .build();
+            let camera = Camera {
+                position: ray.position,
+                look_at: Vec3::ZERO,
+                up: Vec3::Z,
+                ..Camera::default()
+            };
+            let config = RenderConfig {
+                width: 4,
+                height: 4,
+                samples: 4,
+                ..RenderConfig::default()
+            };
+            let mut buffer = Vec::new();
+            render_frame(&scene, &camera, &config, &mut buffer);
+            buffer.iter().map(|c| color::luminance(*c)).sum::<FLOAT>()
+        };
+        assert!(brightness(true) > 2.0 * brightness(false));
+    }
+
+    #[test]
+    fn scene_distance_is_the_nearest_object_distance() {
+        let shapes = || -> Vec<Box<dyn Sdf>> {
+            vec![
+                Box::new(Sphere::new(Vec3::new(-2.0, 0.0, 5.0), 1.0)),
+                Box::new(Cube::new(Vec3::new(1.5, 0.5, 4.0), 0.5)),
+                Box::new(Plane::new(Vec3::Y, -1.5)),
+            ]
+        };
+        let scene = shapes()
+            .into_iter()
+            .fold(SceneBuilder::new(), |builder, shape| builder.add(shape))
+            .build();
+        let shapes = shapes();
+        for ray in fan_of_rays() {
+            let p = ray.position + ray.direction * 4.0;
+            let nearest = shapes.iter().map(|shape| shape.distance(p)).fold(FLOAT::MAX, FLOAT::min);
+            assert_eq!(scene.distance(p), nearest);
+        }
+        assert!((scene.normal(Vec3::new(-2.0, 0.0, 3.5)) - -Vec3::Z).length() < 1e-3);
+    }
+
+    #[test]
+    fn projection_lands_points_on_a_sphere() {
+        let center = Vec3::new(0.5, 1.0, 4.0);
+        let scene = SceneBuilder::new().add(Sphere::new(center, 1.5)).build();
+        for start in [Vec3::new(0.5, 1.0, 1.0), Vec3::new(3.0, 2.0, 5.0), Vec3::new(0.7, 1.2, 4.1)] {
+            let projected = scene.project_to_surface(start, 16);
+            assert!(((projected - center).length() - 1.5).abs() < MIN_DISTANCE);
+            // It moves along the normal, so the direction from the centre is kept.
+            let direction = (start - center).normalize();
+            assert!(((projected - center).normalize() - direction).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn builder_collects_objects_lights_and_bounds() {
+        let scene = SceneBuilder::new()
+            .add(Sphere::new(Vec3::new(-2.0, 0.0, 5.0), 1.0))
+            .add_with_material(
+                Cube::new(Vec3::new(1.0, 1.0, 3.0), 0.5),
+                Material::Metal {
+                    albedo: Vec3::ONE,
+                    roughness: 0.0,
+                },
+            )
+            .light(PointLight::new(Vec3::Y, Vec3::ONE))
+            .build();
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(scene.lights.len(), 1);
+        assert!(matches!(scene.objects[1].material, Material::Metal { .. }));
+        assert_eq!(
+            scene.bounds(),
+            Some(Aabb::new(Vec3::new(-3.0, -1.0, 2.5), Vec3::new(1.5, 1.5, 6.0)))
+        );
+    }
+
+    #[test]
+    fn far_clip_hides_distant_objects_until_raised() {
+        let scene = |far_clip: FLOAT| {
+            SceneBuilder::new()
+                .add(Sphere::new(Vec3::new(0.0, 0.0, 30.0), 2.0))
+                .far_clip(far_clip)
+                .build()
+        };
+        let ray = Ray::new(Vec3::ZERO, Vec3::Z);
+        assert!(raycast(&ray, &scene(20.0)).is_none());
+        assert!(!scene(20.0).ray_intersects(&ray));
+        let hit = raycast(&ray, &scene(50.0)).unwrap();
+        assert!((hit.t - 28.0).abs() < 0.01);
+
+        let config = RenderConfig {
+            width: 1,
+            height: 1,
+            samples: 1,
+            jitter: false,
+            ..RenderConfig::default()
+        };
+        let (mut clipped, mut revealed) = (Vec::new(), Vec::new());
+        render_frame(&scene(20.0), &Camera::default(), &config, &mut clipped);
+        render_frame(&scene(50.0), &Camera::default(), &config, &mut revealed);
+        assert!((clipped[0] - scene(20.0).sky.color(Vec3::Z)).length() < 1e-6);
+        assert_ne!(revealed[0], clipped[0]);
+    }
+
+    #[test]
+    fn symmetric_aa_box_is_a_cube() {
+        let center = Vec3::new(0.5, -1.0, 2.0);
+        let cube = Cube::new(center, 0.75);
+        let aa_box = AaBox::new(center - Vec3::splat(0.75), center + Vec3::splat(0.75));
+        for ray in fan_of_rays() {
+            for i in 0..16 {
+                let p = ray.position + ray.direction * (i as FLOAT * 0.5);
+                assert!((aa_box.distance(p) - cube.distance(p)).abs() < 1e-5);
+            }
+        }
+        assert_eq!(aa_box.bounds(), cube.bounds());
+    }
+
+    #[test]
+    fn progress_reports_each_tile_once_covering_the_image() {
+        let config = RenderConfig {
+            width: TILE_SIZE + 5,
+            height: 2 * TILE_SIZE - 3,
+            samples: 1,
+            jitter: false,
+            ..RenderConfig::default()
+        };
+        let camera = Camera::default();
+        let reported = std::sync::Mutex::new(Vec::new());
+        let mut buffer = Vec::new();
+        render_frame_with_progress(&test_scene(), &camera, &config, &mut buffer, |tile, pixels| {
+            assert_eq!(pixels.len(), tile.width * tile.height);
+            reported.lock().unwrap().push((tile, pixels.to_vec()));
+        });
+        let reported = reported.into_inner().unwrap();
+        assert_eq!(reported.len(), 4);
+        let mut covered = vec![0; config.width * config.height];
+        for (tile, pixels) in &reported {
+            for (i, pixel) in pixels.iter().enumerate() {
+                let pos = (tile.y + i / tile.width) * config.width + tile.x + i % tile.width;
+                covered[pos] += 1;
+                assert_eq!(buffer[pos], *pixel);
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn round_subtraction_fillets_the_cut_edge_with_its_radius() {
+        // The floor y <= 0 with x < 0 cut away leaves a square edge along
+        // the z axis, which the fillet replaces with an arc centred on (r, -r).
+        let radius = 0.5;
+        let filleted = RoundSubtraction {
+            t: Plane::new(Vec3::Y, 0.0),
+            u: Plane::new(Vec3::X, 0.0),
+            radius,
+        };
+        let center = Vec3::new(radius, -radius, 0.0);
+        for i in 0..=8 {
+            let angle = crate::consts::FRAC_PI_2 * i as FLOAT / 8.0;
+            let on_arc = center + Vec3::new(-angle.cos(), angle.sin(), 0.0) * radius;
+            assert!(filleted.distance(on_arc).abs() < 1e-5);
+        }
+        assert!((filleted.distance(Vec3::ZERO) - (crate::consts::SQRT_2 - 1.0) * radius).abs() < 1e-5);
+        // Away from the edge both faces are untouched.
+        assert!((filleted.distance(Vec3::new(3.0, 1.0, 2.0)) - 1.0).abs() < 1e-6);
+        assert!((filleted.distance(Vec3::new(-1.0, -3.0, 0.0)) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn disabled_objects_do_not_contribute_to_distance() {
+        let mut scene = row_of_spheres();
+        let middle = Vec3::new(0.0, 0.0, 3.0);
+        assert!((scene.distance(middle) - 1.0).abs() < 1e-6);
+        assert_eq!(scene.toggle(1), Some(false));
+        let without_middle = scene.distance(middle);
+        assert!((without_middle - (Vec3::new(2.0, 0.0, 2.0).length() - 1.0)).abs() < 1e-6);
+        assert_eq!(scene.enabled_objects().count(), 2);
+        let ray = Ray::new(Vec3::ZERO, Vec3::Z);
+        assert!(raycast(&ray, &scene).is_none());
+        assert_eq!(scene.toggle(1), Some(true));
+        assert_eq!(raycast(&ray, &scene).unwrap().object, 1);
+        assert_eq!(scene.toggle(7), None);
+    }
+
+    #[test]
+    fn cone_march_partly_covers_a_distant_edge() {
+        let scene = SceneBuilder::new()
+            .add(Sphere::new(Vec3::new(0.0, 0.0, 50.0), 1.0))
+            .build();
+        // At the sphere the cone is half a unit wide.
+        let spread = 0.01;
+        let toward = |x: FLOAT| Ray::new(Vec3::ZERO, Vec3::new(x, 0.0, 50.0).normalize());
+
+        let centre = march_cone(&toward(0.0), &scene, 0.0, 0.0, spread);
+        assert_eq!(centre.coverage, 1.0);
+
+        let edge = toward(1.2);
+        assert!(raycast(&edge, &scene).is_none());
+        let grazing = march_cone(&edge, &scene, 0.0, 0.0, spread);
+        assert!(grazing.coverage > 0.0 && grazing.coverage < 1.0, "{}", grazing.coverage);
+        assert_eq!(grazing.hit.unwrap().object, 0);
+
+        let clear = march_cone(&toward(3.0), &scene, 0.0, 0.0, spread);
+        assert!(clear.hit.is_none());
+        assert_eq!(clear.coverage, 0.0);
+    }
+
+    #[test]
+    fn ground_plane_is_a_checkered_floor_at_its_height() {
+        let object: Object = GroundPlane::new(-1.5).into();
+        for p in [
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(-30.0, -1.5, 12.0),
+            Vec3::new(4.0, -3.0, -7.0),
+        ] {
+            assert!((object.distance(p) - (p.y + 1.5)).abs() < 1e-6);
+            assert!((object.normal(p) - Vec3::Y).length() < 1e-3);
+        }
+        let Material::Checker(checker) = &object.material else {
+            panic!("ground is not checkered");
+        };
+        assert_eq!(
+            (checker.a, checker.b, checker.scale),
+            (Vec3::splat(0.8), Vec3::splat(0.3), 1.0)
+        );
+    }
+
+    #[test]
+    fn wrappers_forward_the_blend_of_a_smooth_union() {
+        let (red, blue) = (Vec3::X, Vec3::Z);
+        let blended = || SmoothUnion {
+            t: Sphere::new(Vec3::new(-1.5, 0.0, 0.0), 1.0),
+            u: Sphere::new(Vec3::new(1.5, 0.0, 0.0), 1.0),
+            k: 1.0,
+        };
+        let far = || Sphere::new(Vec3::new(0.0, 0.0, 20.0), 1.0);
+        let wrapped: Vec<Box<dyn Sdf>> = vec![
+            Box::new(blended()),
+            Box::new(Clip {
+                inner: blended(),
+                normal: Vec3::Y,
+                offset: -5.0,
+            }),
+            Box::new(And {
+                t: blended(),
+                u: Sphere::new(Vec3::ZERO, 5.0),
+            }),
+            Box::new(Or {
+                t: blended(),
+                u: far(),
+            }),
+            Box::new(UnionAll(vec![Box::new(blended()), Box::new(far())])),
+            Box::new(Morph {
+                a: blended(),
+                b: far(),
+                t: 0.0,
+            }),
+            Box::new(RepeatRadial::new(blended(), 1, false)),
+            Box::new(Twist {
+                inner: blended(),
+                rate: 0.3,
+            }),
+        ];
+        // Midway between the spheres both count equally.
+        let midway = Vec3::new(0.0, 0.2, 0.0);
+        for shape in wrapped {
+            let object = Object::new(
+                shape,
+                Material::Blend(
+                    Box::new(Material::Diffuse { albedo: red }),
+                    Box::new(Material::Diffuse { albedo: blue }),
+                ),
+            );
+            let mut hit = HitRecord::new(1.0, midway, Vec3::Y, 0);
+            hit.blend = object.blend_weight(midway);
+            assert!((object.material.albedo(&hit) - (red + blue) * 0.5).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn n_ary_combinators_match_nested_binary_ones() {
+        let a = || Sphere::new(Vec3::new(-1.0, 0.0, 4.0), 1.0);
+        let b = || Sphere::new(Vec3::new(0.5, 0.5, 4.5), 1.2);
+        let c = || Cube::new(Vec3::new(0.0, -0.5, 4.0), 0.8);
+        let union = UnionAll(vec![Box::new(a()), Box::new(b()), Box::new(c())]);
+        let nested_union = Or {
+            t: Or { t: a(), u: b() },
+            u: c(),
+        };
+        let intersection = IntersectAll(vec![Box::new(a()), Box::new(b()), Box::new(c())]);
+        let nested_intersection = And {
+            t: And { t: a(), u: b() },
+            u: c(),
+        };
+        for ray in fan_of_rays() {
+            for i in 0..16 {
+                let p = ray.position + ray.direction * (i as FLOAT * 0.5);
+                assert_eq!(union.distance(p), nested_union.distance(p));
+                assert_eq!(intersection.distance(p), nested_intersection.distance(p));
+            }
+        }
+        assert_eq!(union.bounds(), nested_union.bounds());
+        assert_eq!(intersection.bounds(), nested_intersection.bounds());
+        assert_eq!(UnionAll(Vec::new()).distance(Vec3::ZERO), FLOAT::MAX);
+        assert_eq!(IntersectAll(Vec::new()).distance(Vec3::ZERO), -FLOAT::MAX);
+    }
+
+    #[test]
+    fn motion_blur_spreads_a_moving_object() {
+        let config = RenderConfig {
+            width: 64,
+            height: 32,
+            samples: 8,
+            jitter: false,
+            mode: RenderMode::Depth,
+            ..RenderConfig::default()
+        };
+        // A small sphere sweeping from x = -1 to x = 1 while the shutter is open.
+        let at = |time: FLOAT| {
+            SceneBuilder::new()
+                .add(Sphere::new(Vec3::new(2.0 * time - 1.0, 0.0, 5.0), 0.5))
+                .build()
+        };
+        let profile = |camera: &Camera| {
+            let mut buffer = Vec::new();
+            render_frame_motion_blur(at, camera, &config, &mut buffer);
+            let row = config.height / 2 * config.width;
+            buffer[row..row + config.width].iter().map(|pixel| pixel.x).collect::<Vec<_>>()
+        };
+        let still = profile(&Camera {
+            shutter_open: 0.5,
+            shutter_close: 0.5,
+            fov: 0.4,
+            ..Camera::default()
+        });
+        let blurred = profile(&Camera {
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            fov: 0.4,
+            ..Camera::default()
+        });
+        let covered = |row: &[FLOAT]| row.iter().filter(|&&depth| depth > 0.0).count();
+        let peak = |row: &[FLOAT]| row.iter().copied().fold(0.0, FLOAT::max);
+        assert!(covered(&blurred) > 2 * covered(&still), "{still:?} vs {blurred:?}");
+        assert!(peak(&blurred) < 0.75 * peak(&still));
+    }
+
+    #[test]
+    fn sphere_uv_follows_longitude_and_latitude() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let sphere = Sphere::new(center, 2.0);
+        for (direction, expected) in [
+            (Vec3::NEG_Z, Vec2::new(0.5, 0.5)),
+            (Vec3::X, Vec2::new(0.75, 0.5)),
+            (Vec3::NEG_X, Vec2::new(0.25, 0.5)),
+        ] {
+            let uv = sphere.uv(center + direction * 2.0);
+            assert!((uv - expected).length() < 1e-5, "{direction}: {uv}");
+        }
+        // Longitude is arbitrary at the poles.
+        assert!(sphere.uv(center + Vec3::Y * 2.0).y.abs() < 1e-5);
+        assert!((sphere.uv(center - Vec3::Y * 2.0).y - 1.0).abs() < 1e-5);
+        // Behind the sphere is the seam, where u wraps from 1 back to 0.
+        let seam = sphere.uv(center + Vec3::Z * 2.0);
+        assert!((seam.x - 1.0).abs() < 1e-5 || seam.x.abs() < 1e-5);
+    }
+
+    #[test]
+    fn distant_checker_tiles_fade_to_the_average() {
+        let (width, height) = (64, 48);
+        let camera = Camera {
+            position: Vec3::new(0.0, 0.1, 0.0),
+            look_at: Vec3::new(0.0, 0.05, 1.0),
+            fov: 0.8,
+            ..Camera::default()
+        };
+        let about_pixel = |x, y| primary_ray(&camera, x, y, width, height, None);
+        // A pixel's cone is as wide as the gap to its neighbour's ray.
+        let (ray, next) = (about_pixel(32, 24), about_pixel(33, 24));
+        let forward = camera.basis().2;
+        let at_depth = |ray: &Ray| ray.direction * (10.0 / ray.direction.dot(forward));
+        let gap = (at_depth(&next) - at_depth(&ray)).length();
+        assert!((ray.footprint(10.0) - gap).abs() < 0.01 * gap);
+
+        let checker = Checker {
+            a: Vec3::ONE,
+            b: Vec3::ZERO,
+            scale: 0.02,
+        };
+        let average = (checker.a + checker.b) * 0.5;
+        let scene = SceneBuilder::new()
+            .add_with_material(Plane::new(Vec3::Y, 0.0), Material::Checker(checker))
+            .far_clip(1000.0)
+            .build();
+        // The largest departure from the average along row `y`, or `None`
+        // if some of the row sees the sky.
+        let contrast = |y| {
+            (0..width)
+                .map(|x| {
+                    let ray = about_pixel(x, y);
+                    let mut hit = raycast(&ray, &scene)?;
+                    hit.footprint = ray.footprint(hit.t);
+                    Some((scene.objects[0].material.albedo(&hit) - average).length())
+                })
+                .try_fold(0.0, |worst: FLOAT, error| Some(worst.max(error?)))
+        };
+        // The row just below the horizon sees tiles far smaller than a pixel.
+        let horizon = (0..height).find_map(contrast).unwrap();
+        assert!(horizon < 0.05, "{horizon}");
+        assert!(contrast(height - 1).unwrap() > 0.2);
+    }
+
+    #[test]
+    fn ray_intersects_tells_occluded_rays_from_clear_ones() {
+        let scene = row_of_spheres();
+        let blocked = Ray::new(Vec3::new(2.0, 0.0, 0.0), Vec3::Z);
+        let clear = Ray::new(Vec3::new(0.0, 1.5, 0.0), Vec3::Z);
+        let away = Ray::new(Vec3::ZERO, Vec3::NEG_Z);
+        assert!(scene.ray_intersects(&blocked));
+        assert!(!scene.ray_intersects(&clear));
+        assert!(!scene.ray_intersects(&away));
+        // Both agree with a full raycast.
+        for ray in [blocked, clear, away] {
+            assert_eq!(scene.ray_intersects(&ray), raycast(&ray, &scene).is_some());
+        }
+        assert!(!scene.ray_intersects_within(&blocked, 3.5));
+    }
+
+    #[test]
+    fn object_ids_name_the_object_under_each_pixel() {
+        let scene = row_of_spheres();
+        let camera = Camera::default();
+        let config = RenderConfig {
+            width: 40,
+            height: 40,
+            ..RenderConfig::default()
+        };
+        let mut ids = Vec::new();
+        render_object_ids(&scene, &camera, &config, &mut ids);
+        assert_eq!(ids.len(), 40 * 40);
+        let pixel_of = |p: Vec3| {
+            let (u, v) = camera.project(p, 1.0).unwrap();
+            let x = ((u + 1.0) * 20.0) as usize;
+            let y = ((1.0 - v) * 20.0) as usize;
+            y * 40 + x
+        };
+        for (index, object) in scene.objects.iter().enumerate() {
+            let center = object.bounds().unwrap().center();
+            assert_eq!(ids[pixel_of(center)], index as u32);
+        }
+        assert_eq!(ids[0], NO_OBJECT);
+        assert_eq!(ids[40 * 40 - 1], NO_OBJECT);
+        let object_2 = ids.iter().filter(|&&id| id == 2).count();
+        assert!(object_2 > 0 && object_2 == ids.iter().filter(|&&id| id == 0).count());
+    }
+
+    #[test]
+    fn relaxed_march_finds_the_reference_hits() {
+        let scene = SceneBuilder::new()
+            .add(Sphere::new(Vec3::new(0.2, 0.0, 0.5), 1.0))
+            .add(Plane::new(Vec3::new(0.0, 0.6, -0.8), -3.0))
+            .build();
+        for relaxation in [1.0, 1.3, 1.6] {
+            for ray in fan_of_rays() {
+                let relaxed = march_relaxed(&ray, &scene, 0.0, relaxation).hit.map(|hit| hit.t);
+                let reference = march_reference(&ray, &scene, 0.002);
+                match (relaxed, reference) {
+                    (Some(t), Some(r)) => assert!((t - r).abs() < 0.01, "hit at {t} not {r}"),
+                    (None, None) => {}
+                    _ => panic!("relaxed {relaxed:?}, reference {reference:?}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normal_pass_faces_the_camera_on_a_sphere() {
+        let scene = SceneBuilder::new()
+            .add(Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0))
+            .build();
+        let config = RenderConfig {
+            width: 31,
+            height: 31,
+            ..RenderConfig::default()
+        };
+        let mut normals = Vec::new();
+        render_normals(&scene, &Camera::default(), &config, &mut normals);
+        let centre = normals[15 * 31 + 15];
+        assert!((centre - Vec3::NEG_Z).length() < 1e-3, "{centre}");
+        // Away from the centre the normals tilt outwards, but stay facing
+        // the camera.
+        let right = normals[15 * 31 + 17];
+        assert!(right.is_normalized() && right.z < 0.0 && right.x > 0.1);
+        assert_eq!(normals[0], Vec3::ZERO);
+    }
+
+    #[test]
+    fn shadow_ray_stopping_short_of_an_occluder_is_clear() {
+        let scene = row_of_spheres();
+        // The middle sphere's near surface is 4 units along.
+        let shadow = Ray::new(Vec3::ZERO, Vec3::Z);
+        assert!(scene.ray_intersects(&shadow.with_range(0.0, 4.1)));
+        assert!(!scene.ray_intersects(&shadow.with_range(0.0, 3.9)));
+        assert!(raycast(&shadow.with_range(0.0, 3.9), &scene).is_none());
+        assert!(!scene.ray_intersects_within(&shadow, 3.9));
+    }
+
+    #[test]
+    fn closest_objects_lists_the_nearest_in_order() {
+        let scene = row_of_spheres();
+        let p = Vec3::new(1.5, 0.0, 5.0);
+        let closest = scene.closest_objects(p, 2);
+        assert_eq!(closest.iter().map(|&(index, _)| index).collect::<Vec<_>>(), [2, 1]);
+        assert!((closest[0].1 - -0.5).abs() < 1e-6);
+        assert!((closest[1].1 - 0.5).abs() < 1e-6);
+        assert_eq!(scene.closest_objects(p, 10).len(), 3);
+        assert!(scene.closest_objects(p, 0).is_empty());
+    }
+
+    #[test]
+    fn render_converged_stops_once_the_image_settles() {
+        let config = RenderConfig {
+            width: 16,
+            height: 12,
+            samples: 2,
+            ..RenderConfig::default()
+        };
+        let camera = Camera::default();
+        let mut sky = Vec::new();
+        // The empty sky barely varies, so the first two frames settle it.
+        assert_eq!(render_converged(&Scene::new(), &camera, &config, 1e-4, 64, &mut sky), 4);
+        assert_eq!(sky.len(), 16 * 12);
+        let mut busy = Vec::new();
+        assert_eq!(render_converged(&test_scene(), &camera, &config, 0.0, 6, &mut busy), 6);
+    }
+
+    #[test]
+    fn tiled_floor_fills_only_its_rectangle() {
+        let floor = tiled_floor(
+            Sphere::new(Vec3::ZERO, 0.3),
+            Vec2::new(-2.0, -1.0),
+            Vec2::new(2.0, 1.0),
+            1.0,
+        );
+        // Look for copies at every half-unit across a wider area.
+        let mut centres = Vec::new();
+        for i in -12..=12 {
+            for j in -12..=12 {
+                let p = Vec3::new(i as FLOAT * 0.5, 0.0, j as FLOAT * 0.5);
+                if floor.distance(p) < 0.0 {
+                    centres.push(p);
+                }
+            }
+        }
+        assert_eq!(centres.len(), 8);
+        for p in &centres {
+            assert!((floor.distance(*p) + 0.3).abs() < 1e-6);
+            assert!(p.x.abs() < 2.0 && p.z.abs() < 1.0);
+        }
+        // Nothing reaches past the rectangle.
+        for i in 0..64 {
+            let along = i as FLOAT * 0.25 - 8.0;
+            for p in [Vec3::new(along, 0.0, 1.01), Vec3::new(2.01, 0.0, along * 0.2)] {
+                assert!(floor.distance(p) > 0.0);
+            }
+        }
+        // A strip narrower than the tiles cuts them off at its edges.
+        let strip = tiled_floor(
+            Sphere::new(Vec3::ZERO, 0.3),
+            Vec2::new(-2.0, -0.15),
+            Vec2::new(2.0, 0.15),
+            1.0,
+        );
+        assert!(strip.distance(Vec3::new(0.5, 0.0, 0.1)) < 0.0);
+        assert!(strip.distance(Vec3::new(0.5, 0.0, 0.2)) > 0.0);
+        assert!(strip.distance(Vec3::new(0.5, 0.0, -0.2)) > 0.0);
+    }
+
+    #[test]
+    fn a_zero_budget_still_takes_one_pass() {
+        let scene = test_scene();
+        let camera = Camera::default();
+        let config = RenderConfig {
+            width: 8,
+            height: 6,
+            samples: 8,
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        let samples =
+            render_frame_within(&scene, &camera, &config, std::time::Duration::ZERO, &mut buffer);
+        assert_eq!(samples, 1);
+        assert_eq!(buffer.len(), 8 * 6);
+    }
+
+    #[test]
+    fn a_budgeted_crop_keeps_the_pixels_outside_it() {
+        let scene = test_scene();
+        let camera = Camera::default();
+        let crop = Rect {
+            x: 2,
+            y: 1,
+            width: 3,
+            height: 2,
+        };
+        let config = RenderConfig {
+            width: 8,
+            height: 6,
+            samples: 2,
+            crop: Some(crop),
+            ..Default::default()
+        };
+        let sentinel = Vec3::new(7.0, 8.0, 9.0);
+        let mut buffer = vec![sentinel; 8 * 6];
+        let budget = std::time::Duration::from_secs(60);
+        assert_eq!(render_frame_within(&scene, &camera, &config, budget, &mut buffer), 2);
+        let uncropped = RenderConfig {
+            crop: None,
+            ..config.clone()
+        };
+        let mut full = Vec::new();
+        render_frame_within(&scene, &camera, &uncropped, budget, &mut full);
+        for y in 0..6 {
+            for x in 0..8 {
+                let color = buffer[y * 8 + x];
+                if crop.contains(x, y) {
+                    assert!(color.abs_diff_eq(full[y * 8 + x], 1e-4), "({x}, {y})");
+                } else {
+                    assert_eq!(color, sentinel, "({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_tagged_cut_wall_takes_the_second_material() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        let bitten = |tag_cut| {
+            let shape = Not {
+                tag_cut,
+                ..Not::new(
+                    Sphere::new(Vec3::ZERO, 1.0),
+                    Sphere::new(Vec3::new(1.0, 0.0, 0.0), 0.5),
+                )
+            };
+            Object::new(
+                shape,
+                Material::Blend(
+                    Box::new(Material::Diffuse { albedo: red }),
+                    Box::new(Material::Diffuse { albedo: blue }),
+                ),
+            )
+        };
+        let albedo = |object: &Object, p: Vec3| {
+            let mut hit = HitRecord::new(1.0, p, Vec3::X, 0);
+            hit.blend = object.blend_weight(p);
+            object.material.albedo(&hit)
+        };
+        let cut_wall = Vec3::new(0.5, 0.0, 0.0);
+        let outer_wall = Vec3::new(-1.0, 0.0, 0.0);
+        let tagged = bitten(true);
+        assert!((albedo(&tagged, cut_wall) - blue).length() < 1e-6);
+        assert!((albedo(&tagged, outer_wall) - red).length() < 1e-6);
+        let untagged = bitten(false);
+        assert!((albedo(&untagged, cut_wall) - red).length() < 1e-6);
+    }
+
+    #[test]
+    fn display_frames_keep_the_highlights_the_window_clamps() {
+        let scene = SceneBuilder::new()
+            .add(Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.5))
+            .light(PointLight::new(Vec3::new(0.0, 1.0, 0.0), Vec3::splat(1000.0)))
+            .build();
+        let config = RenderConfig {
+            width: 16,
+            height: 12,
+            ..Default::default()
+        };
+        let frame = render_frame_display(&scene, &Camera::default(), &config);
+        assert_eq!(frame.hdr.len(), 16 * 12);
+        assert_eq!(frame.ldr.len(), 16 * 12);
+        let centre = 6 * 16 + 8;
+        assert!(frame.hdr[centre].min_element() > 1.0);
+        assert_eq!(frame.ldr[centre], 0xffffff);
+        for (color, pixel) in frame.hdr.iter().zip(&frame.ldr) {
+            assert_eq!(*pixel, to_color(*color));
+        }
+    }
+
+    #[test]
+    fn side_by_side_halves_match_their_own_renders() {
+        let left = test_scene();
+        let right = row_of_spheres();
+        let config = RenderConfig {
+            width: 10,
+            height: 6,
+            ..Default::default()
+        };
+        let camera = Camera::default();
+        let mut buffer = Vec::new();
+        render_frame_side_by_side((&left, &config), (&right, &config), &camera, &mut buffer);
+        let (mut left_alone, mut right_alone) = (Vec::new(), Vec::new());
+        render_frame(&left, &camera, &config, &mut left_alone);
+        render_frame(&right, &camera, &config, &mut right_alone);
+        assert_ne!(left_alone, right_alone);
+        for (i, color) in buffer.iter().enumerate() {
+            let alone = if i % 10 < 5 { &left_alone } else { &right_alone };
+            assert!(color.abs_diff_eq(alone[i], 1e-5), "pixel {i}");
+        }
+    }
+
+    #[test]
+    fn masked_displacement_leaves_the_rest_of_the_shape_alone() {
+        let bumps = |p: Vec3| 0.05 * (p.x * 20.0).sin() * (p.z * 20.0).sin();
+        let displaced = DisplaceMasked {
+            inner: Sphere::new(Vec3::ZERO, 1.0),
+            displacement: bumps,
+            mask: Sphere::new(Vec3::Y, 0.3),
+            falloff: 0.2,
+            amplitude: 0.05,
+            slope: 1.5,
+        };
+        let base = Sphere::new(Vec3::ZERO, 1.0);
+        let (mut masked_off, mut masked_in) = (0, 0);
+        // A grid of points through and around the sphere.
+        for i in 0..13 * 13 * 13 {
+            let cell = Vec3::new((i % 13) as FLOAT, (i / 13 % 13) as FLOAT, (i / 169) as FLOAT);
+            let p = cell * 0.25 - Vec3::splat(1.5);
+            let weight = displaced.weight(p);
+            if weight == 0.0 {
+                assert_eq!(displaced.distance(p), base.distance(p));
+                masked_off += 1;
+            } else if weight == 1.0 {
+                let expected = base.distance(p) + bumps(p);
+                assert!((displaced.distance(p) - expected).abs() < 1e-6);
+                masked_in += 1;
+            }
+        }
+        assert!(masked_off > 0);
+        assert!(masked_in > 0);
+        // Straight down, far from the mask, the surface is the base sphere's.
+        assert_eq!(displaced.distance(-Vec3::Y), 0.0);
+    }
+}
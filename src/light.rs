@@ -0,0 +1,280 @@
+use crate::texture::ImageTexture;
+use crate::{FLOAT, MIN_DISTANCE, Ray, Scene, Vec2, Vec3, nearest_step};
+
+/// A light that radiates equally in all directions from `position`, or
+/// only within a cone when it has a [`Spot`]. `color` is the intensity,
+/// which falls off with the square of distance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    /// Casts soft shadows with these settings instead of hard ones.
+    pub soft_shadow: Option<SoftShadow>,
+    /// Turns the light into a spotlight.
+    pub spot: Option<Spot>,
+}
+
+/// Confines a light to a cone around `direction`. Light is at full
+/// strength within `inner_angle` of the axis and fades smoothly to nothing
+/// at `outer_angle`, both half angles in radians.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spot {
+    pub direction: Vec3,
+    pub inner_angle: FLOAT,
+    pub outer_angle: FLOAT,
+    /// An image projected along the cone, like a gobo in front of a stage
+    /// light, that tints the light by the texel it passes through. It is
+    /// stretched over the square enclosing the outer cone, oriented with
+    /// its top towards +Y, so white passes the light unchanged and black
+    /// blocks it.
+    pub cookie: Option<ImageTexture>,
+}
+
+/// Soft shadow settings for a light. The shadow ray is marched towards the
+/// light, and how closely it grazes geometry on the way, relative to the
+/// distance travelled, darkens the penumbra.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftShadow {
+    /// Sharpness of the penumbra; higher values give harder shadows.
+    pub k: FLOAT,
+    /// March steps allowed per shadow ray. Each step covers at most an
+    /// even share of the distance to the light, so fewer steps are faster
+    /// but sample occluders coarsely, giving a blotchier penumbra, and
+    /// very few can miss thin occluders altogether.
+    pub steps: usize,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, color: Vec3) -> PointLight {
+        PointLight {
+            position,
+            color,
+            soft_shadow: None,
+            spot: None,
+        }
+    }
+
+    pub fn with_spot(mut self, spot: Spot) -> PointLight {
+        self.spot = Some(spot);
+        self
+    }
+
+    /// The intensity sent out towards unit `direction`.
+    pub fn emission(&self, direction: Vec3) -> Vec3 {
+        match &self.spot {
+            Some(spot) => self.color * spot.factor(direction),
+            None => self.color,
+        }
+    }
+
+    pub fn with_soft_shadow(mut self, k: FLOAT, steps: usize) -> PointLight {
+        self.soft_shadow = Some(SoftShadow { k, steps });
+        self
+    }
+
+    /// The light arriving at a surface point `p` facing `normal`, or zero if
+    /// the point faces away or something in `scene` blocks the light.
+    pub fn irradiance(&self, scene: &Scene, p: Vec3, normal: Vec3) -> Vec3 {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+        let cos_theta = normal.dot(direction);
+        let emission = self.emission(-direction);
+        if cos_theta <= 0.0 || emission == Vec3::ZERO {
+            return Vec3::ZERO;
+        }
+        let shadow =
+            Ray::new(p + normal * (MIN_DISTANCE * 4.0), direction).with_range(0.0, distance);
+        let visibility = match &self.soft_shadow {
+            Some(soft) => soft.visibility(scene, &shadow),
+            None if scene.ray_intersects(&shadow) => return Vec3::ZERO,
+            None => 1.0,
+        };
+        emission * (visibility * cos_theta / (distance * distance))
+    }
+}
+
+impl Spot {
+    pub fn new(direction: Vec3, inner_angle: FLOAT, outer_angle: FLOAT) -> Spot {
+        Spot {
+            direction: direction.normalize(),
+            inner_angle,
+            outer_angle,
+            cookie: None,
+        }
+    }
+
+    pub fn with_cookie(mut self, cookie: ImageTexture) -> Spot {
+        self.cookie = Some(cookie);
+        self
+    }
+
+    /// How much of the light goes out along unit `direction`, per channel.
+    pub fn factor(&self, direction: Vec3) -> Vec3 {
+        let cos_theta = direction.dot(self.direction);
+        let (cos_inner, cos_outer) = (self.inner_angle.cos(), self.outer_angle.cos());
+        if cos_theta <= cos_outer {
+            return Vec3::ZERO;
+        }
+        let t = ((cos_theta - cos_outer) / (cos_inner - cos_outer).max(FLOAT::EPSILON)).min(1.0);
+        let falloff = t * t * (3.0 - 2.0 * t);
+        match &self.cookie {
+            Some(cookie) => cookie.sample(self.cookie_uv(direction)) * falloff,
+            None => Vec3::splat(falloff),
+        }
+    }
+
+    /// Where `direction` passes through the cookie.
+    fn cookie_uv(&self, direction: Vec3) -> Vec2 {
+        let forward = self.direction;
+        let reference = if forward.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let right = reference.cross(forward).normalize();
+        let up = forward.cross(right);
+        let scale = 0.5 / (self.outer_angle.tan() * direction.dot(forward));
+        Vec2::new(direction.dot(right), -direction.dot(up)) * scale + Vec2::splat(0.5)
+    }
+}
+
+impl SoftShadow {
+    /// The fraction of light that reaches the end of `ray`, from 0 in full
+    /// shadow to 1 when nothing comes near the ray.
+    pub fn visibility(&self, scene: &Scene, ray: &Ray) -> FLOAT {
+        let end = ray.t_max.min(scene.far_clip);
+        let max_step = (end - ray.t_min) / self.steps.max(1) as FLOAT;
+        let mut t = ray.t_min;
+        let mut visibility: FLOAT = 1.0;
+        for _ in 0..self.steps {
+            if t >= end {
+                break;
+            }
+            let (d, _) = nearest_step(scene, ray.position + ray.direction * t);
+            if d < MIN_DISTANCE {
+                return 0.0;
+            }
+            visibility = visibility.min(self.k * d / t.max(MIN_DISTANCE));
+            t += d.clamp(MIN_DISTANCE, max_step.max(MIN_DISTANCE));
+        }
+        visibility.clamp(0.0, 1.0)
+    }
+}
+
+/// The sum of [`PointLight::irradiance`] over every light in `scene`.
+pub fn direct_light(scene: &Scene, p: Vec3, normal: Vec3) -> Vec3 {
+    scene
+        .lights
+        .iter()
+        .map(|light| light.irradiance(scene, p, normal))
+        .fold(Vec3::ZERO, |sum, e| sum + e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::Wrap;
+    use crate::{Cube, SceneBuilder};
+
+    fn lit_from_above(occluded: bool) -> Vec3 {
+        let builder =
+            SceneBuilder::new().light(PointLight::new(Vec3::new(0.0, 4.0, 0.0), Vec3::ONE));
+        let builder = if occluded {
+            builder.add(Cube::new(Vec3::new(0.0, 2.0, 0.0), 0.5))
+        } else {
+            builder
+        };
+        direct_light(&builder.build(), Vec3::ZERO, Vec3::Y)
+    }
+
+    #[test]
+    fn occluded_lights_give_no_direct_light() {
+        assert!((lit_from_above(false) - Vec3::splat(1.0 / 16.0)).length() < 1e-3);
+        assert_eq!(lit_from_above(true), Vec3::ZERO);
+    }
+
+    /// Visibility of a light above a floor, shadowed by a box in between,
+    /// sampled along a line on the floor running out of the shadow.
+    fn penumbra(k: FLOAT, steps: usize) -> Vec<FLOAT> {
+        let scene = SceneBuilder::new()
+            .add(Cube::new(Vec3::new(0.0, 2.0, 0.0), 0.5))
+            .build();
+        let light = Vec3::new(0.0, 4.0, 0.0);
+        let soft = SoftShadow { k, steps };
+        (0..=60)
+            .map(|i| {
+                let p = Vec3::new(i as FLOAT * 0.05, 0.0, 0.0);
+                let to_light = light - p;
+                let shadow = Ray::new(p, to_light.normalize()).with_range(0.0, to_light.length());
+                soft.visibility(&scene, &shadow)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_larger_step_budget_smooths_the_penumbra() {
+        // Wobble on top of the rise from shadow to light.
+        let wobble = |profile: &[FLOAT]| {
+            let rise: FLOAT = profile
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .sum();
+            rise - (profile[profile.len() - 1] - profile[0]).abs()
+        };
+        let fine = penumbra(8.0, 256);
+        assert!(fine.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(wobble(&fine) < 1e-4);
+        assert!(wobble(&penumbra(8.0, 6)) > 0.01);
+    }
+
+    #[test]
+    fn penumbra_widens_as_k_falls() {
+        let width = |k| {
+            penumbra(k, 256)
+                .iter()
+                .filter(|&&v| v > 0.0 && v < 1.0)
+                .count()
+        };
+        assert!(width(2.0) > width(8.0));
+        assert!(width(8.0) > width(32.0));
+        assert!(width(32.0) > 0);
+    }
+
+    /// Light from a spot hanging over the floor, pointing straight down,
+    /// arriving at points running out along the floor past the cone's edge.
+    fn spot_on_floor(spot: Spot) -> Vec<Vec3> {
+        let light = PointLight::new(Vec3::new(0.0, 4.0, 0.0), Vec3::ONE).with_spot(spot);
+        let scene = SceneBuilder::new().light(light).build();
+        (0..=40)
+            .map(|i| direct_light(&scene, Vec3::new(i as FLOAT * 0.1, 0.0, 0.0), Vec3::Y))
+            .collect()
+    }
+
+    #[test]
+    fn spots_give_no_light_outside_their_cone() {
+        let light = spot_on_floor(Spot::new(-Vec3::Y, 0.3, 0.5));
+        assert!((light[0] - Vec3::splat(1.0 / 16.0)).length() < 1e-3);
+        // The outer cone meets the floor 4 tan 0.5 out from under the light.
+        let edge = 4.0 * (0.5 as FLOAT).tan();
+        for (i, color) in light.iter().enumerate() {
+            if i as FLOAT * 0.1 > edge {
+                assert_eq!(*color, Vec3::ZERO, "{i}");
+            }
+        }
+        assert!(light.contains(&Vec3::ZERO));
+    }
+
+    #[test]
+    fn cookies_tint_the_spot_by_their_texels() {
+        let spot = || Spot::new(-Vec3::Y, 0.3, 0.5);
+        let cookie = |color| ImageTexture::new(vec![color; 4], 2, 2, Wrap::Clamp);
+        let plain = spot_on_floor(spot());
+        let white = spot_on_floor(spot().with_cookie(cookie(Vec3::ONE)));
+        for (a, b) in plain.iter().zip(&white) {
+            assert!((*a - *b).length() < 1e-6);
+        }
+        let black = spot_on_floor(spot().with_cookie(cookie(Vec3::ZERO)));
+        assert!(black.iter().all(|color| *color == Vec3::ZERO));
+    }
+}
@@ -1,229 +1,222 @@
-use std::cell::RefCell;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Scale, Window, WindowOptions};
+use sdf::accumulator::{Accumulator, sample_ramp};
+use sdf::camera::{Camera, CameraPath, Keyframe, Turntable};
+use sdf::histogram::{Histogram, draw_histogram};
+use sdf::reproject::HitCache;
+use sdf::watch::SceneWatcher;
+use sdf::*;
 
-use minifb::{Key, Scale, Window, WindowOptions};
-use rand::prelude::*;
-use rayon::prelude::*;
+/// Resolution divisor used while the view is moving.
+const COARSE_FACTOR: usize = 4;
+/// Supersampling factor and samples per pixel for F12 screenshots.
+const SCREENSHOT_FACTOR: usize = 4;
+const SCREENSHOT_SAMPLES: usize = 16;
+/// Samples per pixel while moving, and the cap reached by ramping up once
+/// the view is still.
+const RAMP_MIN_SAMPLES: usize = 2;
+const RAMP_MAX_SAMPLES: usize = 64;
+/// Time a still frame may spend sampling before it is shown, to keep the
+/// window responsive in expensive scenes.
+const FRAME_BUDGET: std::time::Duration = std::time::Duration::from_millis(33);
+/// Bins and exposure range of the H key luminance histogram.
+const HISTOGRAM_BINS: usize = 64;
+const HISTOGRAM_EV: (FLOAT, FLOAT) = (-8.0, 4.0);
+/// Lens radius used while depth of field is switched on.
+const DOF_APERTURE: FLOAT = 0.05;
+/// Convergence target and sample cap for `--still` renders.
+const STILL_TARGET_VARIANCE: FLOAT = 1e-4;
+const STILL_MAX_SAMPLES: usize = 1024;
 
-type Vec3 = glam::Vec3A;
-type FLOAT = f32;
-
-const WIDTH: usize = 400;
-const HEIGHT: usize = 300;
-const IMAGE_SIZE: usize = WIDTH * HEIGHT;
-const MAX_DEPTH: FLOAT = 50.0;
-const INV_WIDTH: FLOAT = 1.0 / WIDTH as FLOAT;
-const INV_HEIGHT: FLOAT = 1.0 / HEIGHT as FLOAT;
-
-const MIN_DISTANCE: FLOAT = 0.001;
-const VEC3_EPSILON_X: Vec3 = Vec3::new(MIN_DISTANCE, 0.0, 0.0);
-const VEC3_EPSILON_Y: Vec3 = Vec3::new(0.0, MIN_DISTANCE, 0.0);
-const VEC3_EPSILON_Z: Vec3 = Vec3::new(0.0, 0.0, MIN_DISTANCE);
-const SAMPLES: usize = 10;
-const RANDOM_SAMPLES_X : FLOAT = WIDTH as FLOAT / (100_000) as FLOAT;
-const RANDOM_SAMPLES_Y : FLOAT = HEIGHT as FLOAT / (100_000) as FLOAT;
-
-thread_local! {
-    static RNG: RefCell<ThreadRng> = RefCell::new(rand::rng());
-}
-
-#[derive(Debug)]
-struct Ray {
-    position: Vec3,
-    direction: Vec3,
-}
-
-impl Ray {
-    fn new(position: Vec3, direction: Vec3) -> Ray {
-        Ray {
-            position,
-            direction,
-        }
-    }
-}
-
-struct HitRecord {
-    t: FLOAT,
-    p: Vec3,
-    normal: Vec3,
-}
-
-impl HitRecord {
-    fn new(t: FLOAT, p: Vec3, normal: Vec3) -> HitRecord {
-        HitRecord { t, p, normal }
-    }
-}
-
-trait Sdf: Sync + Send {
-    fn distance(&self, point: Vec3) -> FLOAT;
-    fn normal(&self, point: Vec3) -> Vec3 {
-        let normal = Vec3::new(
-            self.distance(point + VEC3_EPSILON_X) - self.distance(point - VEC3_EPSILON_X),
-            self.distance(point + VEC3_EPSILON_Y) - self.distance(point - VEC3_EPSILON_Y),
-            self.distance(point + VEC3_EPSILON_Z) - self.distance(point - VEC3_EPSILON_Z),
-        );
-        normal.normalize()
-    }
-}
-
-struct Sphere {
-    center: Vec3,
-    radius: FLOAT,
-}
-
-impl Sdf for Sphere {
-    fn distance(&self, point: Vec3) -> FLOAT {
-        (point - self.center).length() - self.radius
-    }
-}
-
-struct Cube {
-    center: Vec3,
-    size: FLOAT,
-}
-
-impl Sdf for Cube {
-    fn distance(&self, point: Vec3) -> FLOAT {
-        let q = (point - self.center).abs() - Vec3::splat(self.size);
-        q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
-    }
-}
-
-struct And<T: Sdf, U: Sdf> {
-    t: T,
-    u: U,
-}
-
-impl<T: Sdf, U: Sdf> Sdf for And<T, U> {
-    fn distance(&self, point: Vec3) -> FLOAT {
-        self.t.distance(point).max(self.u.distance(point))
-    }
-}
-
-struct Not<T: Sdf, U: Sdf> {
-    t: T,
-    u: U,
-}
-
-impl<T: Sdf, U: Sdf> Sdf for Not<T, U> {
-    fn distance(&self, point: Vec3) -> FLOAT {
-        self.t.distance(point).max(-self.u.distance(point))
-    }
-}
-
-fn to_color(col: Vec3) -> u32 {
-    let ir = (255.99 * col.x) as u32;
-    let ig = (255.99 * col.y) as u32;
-    let ib = (255.99 * col.z) as u32;
+fn main() {
+    let mut scene = (3 .. 6).fold(SceneBuilder::new(), |builder, z| {
+        let sphere = Sphere {
+            center: Vec3::new(0.0, 0.0, z as FLOAT),
+            radius: 1.0,
+        };
 
-    let color = (ir << 16) | (ig << 8) | ib;
-    color
-}
+        let cube = Cube {
+            center: Vec3::new(0.0, 0.0, z as FLOAT),
+            size: 0.75,
+        };
 
-fn random_in_unit_sphere() -> Vec3 {
-    RNG.with_borrow_mut(|rng| loop {
-        let p = Vec3::new(rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0));
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+        builder.add(And { t: cube, u: sphere })
     })
-}
-
+    .build();
 
-fn trace_ray(ray: Ray, shapes: &[Box<dyn Sdf>], depth : usize) -> Vec3 {
-    if depth > 5 {
-        return Vec3::ZERO;
+    // `--still <path>` renders the default view until it converges, saves
+    // it and exits without opening a window.
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, path] = args.as_slice() && flag == "--still" {
+        render_still(&scene, path);
+        return;
     }
-    let mut p = ray.position;
-    loop {
-        let mut min_distance = FLOAT::MAX;
-        for shape in shapes {
-            let d = shape.distance(p);
-            if d < min_distance {
-                min_distance = d;
-            }
-        }
-        if min_distance > MAX_DEPTH {
-            break;
-        }
-        p = p + ray.direction * min_distance;
-        if min_distance < MIN_DISTANCE {
-            let normal = shapes[0].normal(p);
-            let hit = HitRecord::new(min_distance, p, normal);
-            let target = hit.p + hit.normal + random_in_unit_sphere();
-            return 0.5 * trace_ray(Ray::new(hit.p, target - hit.p), shapes, depth + 1);
-        }
+    // `--watch <path>` shows the scene in a CSG file instead, reloading it
+    // whenever the file is saved.
+    let mut watcher = match args.as_slice() {
+        [_, flag, path] if flag == "--watch" => Some(SceneWatcher::new(path)),
+        _ => None,
+    };
 
-    }
-    let unit_direction = ray.direction.normalize();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    (1.0 - t) * Vec3::ONE + t * Vec3::new(0.5, 0.7, 1.0)
-    //Vec3::ZERO
-}
-fn main() {
     let mut window = Window::new(
-        "Test - ESC to exit",
+        "Test - ESC to exit, SPACE to pause, F12 for a screenshot",
         WIDTH,
         HEIGHT,
-        WindowOptions { 
+        WindowOptions {
             scale: Scale::X2,
-            .. WindowOptions::default() 
+            .. WindowOptions::default()
         },
     )
     .unwrap_or_else(|e| {
         panic!("{}", e);
     });
 
-    let mut shapes :Vec<Box<dyn Sdf> >= Vec::new();
-
-
-    for z in 3 .. 6   {
-        let sphere = Sphere {
-            center: Vec3::new(0.0, 0.0, z as FLOAT),
-            radius: 1.0,
-        };
-    
-        let cube = Cube {
-            center: Vec3::new(0.0, 0.0, z as FLOAT),
-            size: 0.75,
-        };
-    
-        let and = And { t: cube, u: sphere };
-        shapes.push(Box::new(and));
-    }
-
-    let aspect_ratio = WIDTH as FLOAT / HEIGHT as FLOAT;    
-    let mut buffer: Vec<u32> = vec![0; IMAGE_SIZE];
     let mut backbuffer: Vec<Vec3> = vec![Vec3::ZERO; IMAGE_SIZE];
-    let d_time = std::time::Instant::now();
+    let mut config = RenderConfig::default();
+    let mut paused = false;
+    let mut animation_time = 0.0;
+    let mut last_frame = std::time::Instant::now();
+    let mut accumulator = Accumulator::new(IMAGE_SIZE);
+    let mut camera = Camera::default();
+    let path = CameraPath {
+        keyframes: vec![
+            Keyframe { position: Vec3::new(0.0, 0.0, 0.0), look_at: Vec3::new(0.0, 0.0, 4.0), time: 0.0 },
+            Keyframe { position: Vec3::new(2.0, 1.0, 0.5), look_at: Vec3::new(0.0, 0.0, 4.0), time: 2.0 },
+            Keyframe { position: Vec3::new(-2.0, 0.5, 1.0), look_at: Vec3::new(0.0, 0.0, 4.0), time: 4.0 },
+            Keyframe { position: Vec3::new(0.0, 0.0, 0.0), look_at: Vec3::new(0.0, 0.0, 4.0), time: 6.0 },
+        ],
+    };
+    // T swaps the fly-through for a turntable orbit of the row of shapes.
+    let turntable = Turntable {
+        center: Vec3::new(0.0, 0.0, 4.0),
+        radius: 5.0,
+        elevation: 0.3,
+        speed: 0.5,
+    };
+    let mut orbit = false;
+    let mut last_camera = None;
+    // R switches moving frames from coarse previews to full resolution
+    // renders seeded by reprojecting the previous frame's hits.
+    let mut reproject = false;
+    let mut hit_cache = HitCache::new();
+    // Q toggles between the fixed sample count and ramping up while still.
+    let mut ramp = true;
+    // F toggles depth of field; clicking focuses on the object under the
+    // mouse.
+    let mut mouse_was_down = false;
+    // H toggles a luminance histogram of the linear frame.
+    let mut show_histogram = false;
     while window.is_open() && !window.is_key_down(Key::Escape) {
+        for key in window.get_keys_pressed(KeyRepeat::No) {
+            if let Some(mode) = render_mode_for_key(key) {
+                config.mode = mode;
+                accumulator.mark_dirty();
+            }
+            if key == Key::Space {
+                paused = !paused;
+            }
+            if let Some(enabled) = object_for_key(key).and_then(|index| scene.toggle(index)) {
+                println!("Object {}", if enabled { "enabled" } else { "disabled" });
+                accumulator.mark_dirty();
+                hit_cache.clear();
+            }
+            if key == Key::Q {
+                ramp = !ramp;
+                accumulator.mark_dirty();
+            }
+            if key == Key::C {
+                println!("{}", camera.to_ron_string());
+            }
+            if key == Key::R {
+                reproject = !reproject;
+                hit_cache.clear();
+            }
+            if key == Key::H {
+                show_histogram = !show_histogram;
+            }
+            if key == Key::T {
+                orbit = !orbit;
+            }
+            if key == Key::F {
+                camera.aperture = if camera.aperture > 0.0 { 0.0 } else { DOF_APERTURE };
+                accumulator.mark_dirty();
+            }
+            if key == Key::F12 {
+                let mut screenshot = render_screenshot(
+                    &scene,
+                    &camera,
+                    &config,
+                    SCREENSHOT_FACTOR,
+                    SCREENSHOT_SAMPLES,
+                );
+                color::tone_map_buffer(&mut screenshot, config.tone_map);
+                match export::save_png("screenshot.png", &screenshot, config.width, config.height) {
+                    Ok(()) => println!("Saved screenshot.png"),
+                    Err(e) => println!("Failed to save screenshot: {}", e),
+                }
+            }
+        }
+        if let Some(watcher) = &mut watcher {
+            match watcher.poll(|reloaded| scene = reloaded) {
+                Ok(true) => {
+                    println!("Reloaded {}", watcher.path().display());
+                    accumulator.mark_dirty();
+                    hit_cache.clear();
+                }
+                Ok(false) => {}
+                Err(e) => println!("Failed to load {}: {}", watcher.path().display(), e),
+            }
+        }
+        let mouse_down = window.get_mouse_down(MouseButton::Left);
+        let click = window
+            .get_mouse_pos(MouseMode::Discard)
+            .filter(|_| mouse_down && !mouse_was_down);
+        let focus = click.and_then(|(x, y)| {
+            focus_on_pixel(&mut camera, &scene, x as usize, y as usize, WIDTH, HEIGHT)
+        });
+        if let Some(distance) = focus {
+            println!("Focus distance {}", distance);
+            accumulator.mark_dirty();
+        }
+        mouse_was_down = mouse_down;
+        if !paused {
+            animation_time += last_frame.elapsed().as_secs_f64() as FLOAT;
+        }
+        last_frame = std::time::Instant::now();
         let start = std::time::Instant::now();
-        let origin = Vec3::new((d_time.elapsed().as_secs_f32() * 10.0).sin() * 2.0, (d_time.elapsed().as_secs_f32() * 10.0).cos(), 0.0);
-        (0..IMAGE_SIZE)
-            .into_par_iter()
-            .map(|pos| {
-                let x = pos % WIDTH;
-                let y = pos / WIDTH;
-                let x = (x as FLOAT) * (INV_WIDTH * 2.0) - 1.0;
-                let y = (y as FLOAT) * (INV_HEIGHT * 2.0) - 1.0;
-                let x = x * aspect_ratio;
-                let color = (0 .. SAMPLES).into_iter().fold(Vec3::ZERO, |c, _| {
-                    let (x, y) = RNG.with_borrow_mut(|rng| {
-                        let u = x + rng.random_range(-RANDOM_SAMPLES_X..RANDOM_SAMPLES_X);
-                        let v = y + rng.random_range(-RANDOM_SAMPLES_Y..RANDOM_SAMPLES_Y);
-                        (u, v)});
-                    let ray = Ray::new(origin, Vec3::new(x, y, 1.0).normalize());
-                    trace_ray(ray, &shapes, 0) + c
-                });
-    
-                color / SAMPLES as FLOAT
-            })
-            .collect_into_vec(&mut backbuffer);
+        camera = if orbit {
+            turntable.sample(animation_time, &camera)
+        } else {
+            path.sample(animation_time % 6.0, &camera)
+        };
+        let moved = last_camera.as_ref() != Some(&camera);
+        if moved {
+            accumulator.mark_dirty();
+        }
+        accumulator.begin_frame();
+        config.samples = match (ramp, moved) {
+            (false, _) => SAMPLES,
+            (true, true) => RAMP_MIN_SAMPLES,
+            (true, false) => sample_ramp(accumulator.frame_count, RAMP_MIN_SAMPLES, RAMP_MAX_SAMPLES),
+        };
+        if moved && reproject {
+            render_frame_reprojected(&scene, &camera, &config, &mut hit_cache, &mut backbuffer);
+        } else if moved {
+            render_frame_coarse(&scene, &camera, &config, COARSE_FACTOR, &mut backbuffer);
+        } else {
+            let samples = render_frame_within(&scene, &camera, &config, FRAME_BUDGET, &mut backbuffer);
+            accumulator.add_weighted(&backbuffer, samples as FLOAT);
+            accumulator.resolve(&mut backbuffer);
+        }
+        last_camera = Some(camera.clone());
+        config.frame = config.frame.wrapping_add(1);
 
         let elapsed = start.elapsed();
         println!("Elapsed: {}ms", elapsed.as_millis());
-        for (idx, i) in buffer.iter_mut().enumerate() {
-            let color = backbuffer[idx];
-            *i = to_color(color);
+        let mut buffer = to_color_buffer(&backbuffer, config.tone_map);
+        if show_histogram {
+            let (min_ev, max_ev) = HISTOGRAM_EV;
+            let histogram = Histogram::new(&backbuffer, HISTOGRAM_BINS, min_ev, max_ev);
+            draw_histogram(&mut buffer, WIDTH, &histogram);
         }
 
         window
@@ -231,3 +224,68 @@ fn main() {
             .unwrap();
     }
 }
+
+fn render_still(scene: &Scene, path: &str) {
+    let config = RenderConfig::default();
+    let mut image = Vec::new();
+    let samples = render_converged(
+        scene,
+        &Camera::default(),
+        &config,
+        STILL_TARGET_VARIANCE,
+        STILL_MAX_SAMPLES,
+        &mut image,
+    );
+    color::tone_map_buffer(&mut image, config.tone_map);
+    match export::save_png(path, &image, config.width, config.height) {
+        Ok(()) => println!("Saved {} after {} samples per pixel", path, samples),
+        Err(e) => println!("Failed to save {}: {}", path, e),
+    }
+}
+
+/// F1-F9 toggle the visibility of the first nine objects.
+fn object_for_key(key: Key) -> Option<usize> {
+    const KEYS: [Key; 9] = [
+        Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9,
+    ];
+    KEYS.iter().position(|&k| k == key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_keys_select_render_modes() {
+        assert_eq!(render_mode_for_key(Key::Key1), Some(RenderMode::Shaded));
+        assert_eq!(render_mode_for_key(Key::Key2), Some(RenderMode::Normals));
+        assert_eq!(render_mode_for_key(Key::Key3), Some(RenderMode::Depth));
+        assert_eq!(render_mode_for_key(Key::Key4), Some(RenderMode::Steps));
+        assert_eq!(render_mode_for_key(Key::Key5), None);
+        assert_eq!(render_mode_for_key(Key::Space), None);
+    }
+
+    #[test]
+    fn function_keys_select_objects() {
+        assert_eq!(object_for_key(Key::F1), Some(0));
+        assert_eq!(object_for_key(Key::F9), Some(8));
+        assert_eq!(object_for_key(Key::F10), None);
+        assert_eq!(object_for_key(Key::Key1), None);
+    }
+
+    #[test]
+    fn still_render_writes_a_single_png() {
+        let dir = std::env::temp_dir().join(format!("sdf-still-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("still.png");
+        render_still(&Scene::new(), path.to_str().unwrap());
+        let files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        let image = image::open(&path).map(|image| image.into_rgba8());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, [path]);
+        assert_eq!(image.unwrap().dimensions(), (WIDTH as u32, HEIGHT as u32));
+    }
+}
@@ -0,0 +1,549 @@
+use crate::optics::{fresnel_schlick, reflect, refract};
+use crate::texture::{Checker, ImageTexture, Triplanar};
+use crate::{
+    FLOAT, HitRecord, MIN_DISTANCE, Ray, Sdf, Vec2, Vec3, random_float, random_in_unit_sphere,
+};
+
+/// Surface response of an [`Object`](crate::Object).
+pub enum Material {
+    /// Lambertian scattering tinted by `albedo`, with a weak Fresnel
+    /// reflection that brightens grazing angles.
+    Diffuse { albedo: Vec3 },
+    /// Lambertian scattering with an anti-aliased checkerboard albedo.
+    Checker(Checker),
+    /// Lambertian scattering with the albedo looked up from `image` at the
+    /// hit's [`HitRecord::uv`].
+    Textured { image: ImageTexture },
+    /// Lambertian scattering with a [`Triplanar`] projected albedo, for
+    /// shapes without useful texture coordinates.
+    Triplanar(Triplanar),
+    /// Glass-like material that reflects or refracts with Fresnel weighting.
+    /// Light travelling through the interior is attenuated per channel by
+    /// Beer-Lambert absorption, `exp(-absorption * distance)`, so a non-zero
+    /// `absorption` gives tinted glass that deepens with thickness.
+    ///
+    /// When `dispersion` holds separate red, green and blue IORs they replace
+    /// `ior`, and each channel is traced on its own path so white light
+    /// splits into a spectrum. This triples the cost of every dispersive hit.
+    Dielectric {
+        ior: FLOAT,
+        absorption: Vec3,
+        dispersion: Option<Vec3>,
+    },
+    /// Cheap subsurface approximation. The object is probed along the
+    /// inward normal to estimate how thick it is at the hit point; thin
+    /// parts let light through from the far side while thick parts scatter
+    /// like a diffuse surface. `thickness` is the depth at which the object
+    /// stops transmitting.
+    Translucent { color: Vec3, thickness: FLOAT },
+    /// Mirror-like reflection tinted by the Fresnel reflectance, with
+    /// `albedo` as the reflectance at normal incidence. `roughness` blurs
+    /// the reflection, from 0 for a perfect mirror upwards.
+    Metal { albedo: Vec3, roughness: FLOAT },
+    /// Brushed metal: like [`Material::Metal`], but the reflection blurs by
+    /// `roughness.x` along `tangent` and by `roughness.y` across it, so
+    /// highlights stretch along the tangent. `tangent` is projected onto
+    /// the surface at each hit and need not be perpendicular to the normal;
+    /// a cylinder's axis, say, brushes it along its length.
+    Anisotropic {
+        albedo: Vec3,
+        tangent: Vec3,
+        roughness: Vec2,
+    },
+    /// Mixes two materials by the hit's [`HitRecord::blend`] weight, from
+    /// all of the first at 0 to all of the second at 1. Pair it with a
+    /// blending combinator such as [`SmoothUnion`](crate::SmoothUnion).
+    Blend(Box<Material>, Box<Material>),
+    /// A checkerboard across the XZ plane with square cells of size `scale`
+    /// alternating between two whole materials, say polished metal and
+    /// matt tiles. Unlike [`Material::Checker`] the cell edges are hard.
+    Checkerboard {
+        a: Box<Material>,
+        b: Box<Material>,
+        scale: FLOAT,
+    },
+}
+
+/// Normal-incidence reflectance of the clear coat of diffuse surfaces, a
+/// typical value for non-metals.
+const DIELECTRIC_F0: Vec3 = Vec3::splat(0.04);
+
+/// A ray leaving a surface and the factor applied to the light it gathers.
+pub struct Scatter {
+    pub attenuation: Vec3,
+    pub ray: Ray,
+    /// Whether the ray samples the Lambertian lobe of a diffuse material.
+    /// Light from an environment map is gathered directly at such surfaces,
+    /// so the renderer ignores it when these rays escape.
+    pub diffuse: bool,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material::Diffuse {
+            albedo: Vec3::splat(0.5),
+        }
+    }
+}
+
+impl Material {
+    /// The reflectance at `hit`, multiplied into the light scattered from it.
+    pub fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        match self {
+            Material::Diffuse { albedo } => *albedo,
+            Material::Checker(checker) => checker.color(hit.p, hit.footprint),
+            Material::Textured { image } => image.sample(hit.uv),
+            Material::Triplanar(triplanar) => triplanar.color(hit.p, hit.normal),
+            Material::Dielectric { .. } => Vec3::ONE,
+            Material::Translucent { color, .. } => *color,
+            Material::Metal { albedo, .. } | Material::Anisotropic { albedo, .. } => *albedo,
+            Material::Blend(a, b) => a.albedo(hit).lerp(b.albedo(hit), hit.blend),
+            Material::Checkerboard { .. } => self.resolve(hit).albedo(hit),
+        }
+    }
+
+    /// The material that applies at `hit`: for a [`Material::Checkerboard`],
+    /// that of the cell the hit lies in, and otherwise the material itself.
+    pub fn resolve(&self, hit: &HitRecord) -> &Material {
+        match self {
+            Material::Checkerboard { a, b, scale } => {
+                let cell = (hit.p.x / scale).floor() + (hit.p.z / scale).floor();
+                if cell.rem_euclid(2.0) == 0.0 { a } else { b }.resolve(hit)
+            }
+            _ => self,
+        }
+    }
+
+    /// Picks the continuation of `ray` after it hits the surface of `sdf` at
+    /// `hit`, or `None` if the light is absorbed.
+    pub fn scatter(&self, ray: &Ray, hit: &HitRecord, sdf: &dyn Sdf) -> Option<Scatter> {
+        match self {
+            Material::Dielectric {
+                ior,
+                absorption,
+                dispersion,
+            } => {
+                let ior = dispersion.map_or(*ior, |iors| iors.x);
+                Some(dielectric_scatter(ray, hit, ior, *absorption))
+            }
+            Material::Translucent { color, thickness } => {
+                let depth = internal_distance(sdf, hit.p, -hit.normal, *thickness);
+                if random_float() < translucency(depth, *thickness) {
+                    // Continue diffusely from the far side of the object.
+                    let exit = hit.p - hit.normal * (depth + MIN_DISTANCE * 4.0);
+                    Some(Scatter {
+                        attenuation: *color,
+                        ray: Ray::new(
+                            exit,
+                            (random_in_unit_sphere() - hit.normal).normalize_or(-hit.normal),
+                        ),
+                        diffuse: false,
+                    })
+                } else {
+                    Some(Scatter {
+                        attenuation: *color,
+                        ray: diffuse_ray(hit),
+                        diffuse: false,
+                    })
+                }
+            }
+            Material::Checkerboard { .. } => self.resolve(hit).scatter(ray, hit, sdf),
+            // Picking one material in proportion to the weight blends them on
+            // average, whatever kind of scattering each does.
+            Material::Blend(a, b) => {
+                if random_float() < hit.blend {
+                    b.scatter(ray, hit, sdf)
+                } else {
+                    a.scatter(ray, hit, sdf)
+                }
+            }
+            Material::Metal { albedo, roughness } => {
+                let direction = ray.direction.normalize();
+                let cos_theta = (-direction).dot(hit.normal);
+                let reflected =
+                    reflect(direction, hit.normal) + *roughness * random_in_unit_sphere();
+                // Blurred reflections that end up below the surface are absorbed.
+                (reflected.dot(hit.normal) > 0.0).then(|| Scatter {
+                    attenuation: fresnel_schlick(cos_theta, *albedo),
+                    ray: Ray::new(hit.p + hit.normal * (MIN_DISTANCE * 4.0), reflected),
+                    diffuse: false,
+                })
+            }
+            Material::Anisotropic {
+                albedo,
+                tangent,
+                roughness,
+            } => {
+                let direction = ray.direction.normalize();
+                let cos_theta = (-direction).dot(hit.normal);
+                let (tangent, bitangent) = tangent_frame(hit.normal, *tangent);
+                let offset = random_in_unit_sphere();
+                let reflected = reflect(direction, hit.normal)
+                    + tangent * (roughness.x * offset.x)
+                    + bitangent * (roughness.y * offset.y);
+                (reflected.dot(hit.normal) > 0.0).then(|| Scatter {
+                    attenuation: fresnel_schlick(cos_theta, *albedo),
+                    ray: Ray::new(hit.p + hit.normal * (MIN_DISTANCE * 4.0), reflected),
+                    diffuse: false,
+                })
+            }
+            _ => {
+                if random_float() < coat_reflectance(ray.direction, hit.normal) {
+                    return Some(Scatter {
+                        attenuation: Vec3::ONE,
+                        ray: Ray::new(
+                            hit.p + hit.normal * (MIN_DISTANCE * 4.0),
+                            reflect(ray.direction.normalize(), hit.normal),
+                        ),
+                        diffuse: false,
+                    });
+                }
+                Some(Scatter {
+                    attenuation: self.albedo(hit),
+                    ray: diffuse_ray(hit),
+                    diffuse: true,
+                })
+            }
+        }
+    }
+
+    /// Whether the surface scatters light diffusely, so that lights in the
+    /// scene are sampled directly at each hit rather than left to be found
+    /// by random bounces.
+    pub fn is_diffuse(&self) -> bool {
+        match self {
+            Material::Diffuse { .. }
+            | Material::Checker(_)
+            | Material::Textured { .. }
+            | Material::Triplanar(_) => true,
+            Material::Blend(a, b) | Material::Checkerboard { a, b, .. } => {
+                a.is_diffuse() && b.is_diffuse()
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the surface only reflects or refracts, sending light on in a
+    /// few well-defined directions.
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Dielectric { .. } | Material::Metal { .. } | Material::Anisotropic { .. } => {
+                true
+            }
+            Material::Blend(a, b) | Material::Checkerboard { a, b, .. } => {
+                a.is_specular() && b.is_specular()
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether this material refracts each color channel differently, in which
+    /// case the renderer traces channels separately via [`Material::scatter_channel`].
+    pub fn is_dispersive(&self) -> bool {
+        match self {
+            Material::Dielectric {
+                dispersion: Some(iors),
+                ..
+            } => iors.x != iors.y || iors.y != iors.z,
+            _ => false,
+        }
+    }
+
+    /// Like [`Material::scatter`], but for light of a single color channel
+    /// (0 = red, 1 = green, 2 = blue). Only dispersive dielectrics differ.
+    pub fn scatter_channel(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sdf: &dyn Sdf,
+        channel: usize,
+    ) -> Option<Scatter> {
+        match self {
+            Material::Dielectric {
+                absorption,
+                dispersion: Some(iors),
+                ..
+            } => Some(dielectric_scatter(ray, hit, iors[channel], *absorption)),
+            _ => self.scatter(ray, hit, sdf),
+        }
+    }
+}
+
+/// The share of the light leaving along the reverse of `direction` that the
+/// clear coat of a diffuse material reflects, for a surface facing `normal`;
+/// the rest comes from the diffuse base beneath the coat.
+pub fn coat_reflectance(direction: Vec3, normal: Vec3) -> FLOAT {
+    let cos_theta = (-direction.normalize()).dot(normal);
+    fresnel_schlick(cos_theta, DIELECTRIC_F0).x
+}
+
+/// A diffuse bounce off the surface at `hit`. The ray starts just above
+/// the surface, since a march from the hit point itself would find the
+/// surface again straight away and leave the point wrongly in shadow.
+fn diffuse_ray(hit: &HitRecord) -> Ray {
+    let origin = hit.p + hit.normal * (MIN_DISTANCE * 4.0);
+    let direction = (hit.normal + random_in_unit_sphere()).normalize_or(hit.normal);
+    Ray::new(origin, direction)
+}
+
+/// Unit tangent and bitangent at a surface facing `normal`, with the tangent
+/// as close to `reference` as the surface allows.
+pub fn tangent_frame(normal: Vec3, reference: Vec3) -> (Vec3, Vec3) {
+    let tangent = (reference - normal * reference.dot(normal))
+        .try_normalize()
+        .unwrap_or_else(|| normal.any_orthonormal_vector());
+    (tangent, normal.cross(tangent))
+}
+
+/// How much light a translucent object lets through where it is `depth`
+/// thick, from 1 at zero depth falling to 0 at `thickness`.
+pub fn translucency(depth: FLOAT, thickness: FLOAT) -> FLOAT {
+    (1.0 - depth / thickness).clamp(0.0, 1.0)
+}
+
+/// Marches from `p` along `direction` through the inside of `sdf` and
+/// returns how far it is to the far surface, capped at `max_distance`.
+pub fn internal_distance(sdf: &dyn Sdf, p: Vec3, direction: Vec3, max_distance: FLOAT) -> FLOAT {
+    let mut t = MIN_DISTANCE * 2.0;
+    while t < max_distance {
+        let d = sdf.distance(p + direction * t);
+        if d >= 0.0 {
+            return t;
+        }
+        t += (-d).max(MIN_DISTANCE);
+    }
+    max_distance
+}
+
+fn dielectric_scatter(ray: &Ray, hit: &HitRecord, ior: FLOAT, absorption: Vec3) -> Scatter {
+    let direction = ray.direction.normalize();
+    let entering = direction.dot(hit.normal) < 0.0;
+    let (normal, eta, attenuation) = if entering {
+        (hit.normal, 1.0 / ior, Vec3::ONE)
+    } else {
+        // The ray reaching an exit point has just crossed the interior, and
+        // refracted rays are unit length, so hit.t is the path length inside.
+        (-hit.normal, ior, (-absorption * hit.t).exp())
+    };
+    let cos_theta = (-direction).dot(normal).min(1.0);
+    let refracted =
+        refract(direction, normal, eta).filter(|_| random_float() >= schlick(cos_theta, eta));
+    let (direction, side) = match refracted {
+        Some(refracted) => (refracted, -normal),
+        None => (reflect(direction, normal), normal),
+    };
+    // Start just off the surface on the side the ray leaves towards, so the
+    // march does not immediately find the surface it started on.
+    let origin = hit.p + side * (MIN_DISTANCE * 4.0);
+    Scatter {
+        attenuation,
+        ray: Ray::new(origin, direction),
+        diffuse: false,
+    }
+}
+
+fn schlick(cos_theta: FLOAT, eta: FLOAT) -> FLOAT {
+    let r0 = ((1.0 - eta) / (1.0 + eta)).powi(2);
+    fresnel_schlick(cos_theta, Vec3::splat(r0)).x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coat_takes_its_share_from_the_diffuse_base() {
+        let normal = Vec3::Y;
+        let head_on = coat_reflectance(-Vec3::Y, normal);
+        assert!((head_on - DIELECTRIC_F0.x).abs() < 1e-6);
+        let grazing = coat_reflectance(Vec3::new(1.0, -0.01, 0.0), normal);
+        assert!(grazing > 0.9);
+        assert!(coat_reflectance(Vec3::new(1.0, -1.0, 0.0), normal) < grazing);
+    }
+
+    /// A hit at the origin on a surface facing +Z, `t` along a ray that
+    /// travelled in `direction`.
+    fn hit_at(t: FLOAT, direction: Vec3) -> (Ray, HitRecord) {
+        let ray = Ray::new(-direction * t, direction);
+        (ray, HitRecord::new(t, Vec3::ZERO, Vec3::Z, 0))
+    }
+
+    #[test]
+    fn longer_paths_through_red_glass_are_redder() {
+        let glass = Material::Dielectric {
+            ior: 1.5,
+            absorption: Vec3::new(0.0, 1.0, 1.0),
+            dispersion: None,
+        };
+        let sphere = crate::Sphere::new(Vec3::ZERO, 1.0);
+        let exit = |t: FLOAT| {
+            let (ray, hit) = hit_at(t, Vec3::Z);
+            glass.scatter(&ray, &hit, &sphere).unwrap().attenuation
+        };
+        let (short, long) = (exit(0.2), exit(2.0));
+        assert_eq!(short.x, 1.0);
+        assert!((short.y - (-0.2 as FLOAT).exp()).abs() < 1e-6);
+        assert!(long.y / long.x < short.y / short.x);
+        assert!((long.z - (-2.0 as FLOAT).exp()).abs() < 1e-6);
+
+        // Entering the glass attenuates nothing yet.
+        let (ray, hit) = hit_at(2.0, -Vec3::Z);
+        assert_eq!(
+            glass.scatter(&ray, &hit, &sphere).unwrap().attenuation,
+            Vec3::ONE
+        );
+    }
+
+    #[test]
+    fn thin_parts_are_more_translucent_than_thick_ones() {
+        assert_eq!(translucency(0.0, 0.5), 1.0);
+        assert_eq!(translucency(0.25, 0.5), 0.5);
+        assert_eq!(translucency(0.5, 0.5), 0.0);
+        assert_eq!(translucency(2.0, 0.5), 0.0);
+
+        // A slab 0.2 thick in X standing on a block 2 thick.
+        let shape = crate::Or {
+            t: crate::AaBox::new(Vec3::new(-0.1, 0.0, -1.0), Vec3::new(0.1, 2.0, 1.0)),
+            u: crate::AaBox::new(Vec3::new(-1.0, -2.0, -1.0), Vec3::new(1.0, 0.0, 1.0)),
+        };
+        let thin = internal_distance(&shape, Vec3::new(0.1, 1.0, 0.0), -Vec3::X, 1.0);
+        let thick = internal_distance(&shape, Vec3::new(1.0, -1.0, 0.0), -Vec3::X, 1.0);
+        assert!((thin - 0.2).abs() < 2.0 * MIN_DISTANCE);
+        assert_eq!(thick, 1.0);
+        assert!(translucency(thin, 1.0) > 0.75 && translucency(thick, 1.0) == 0.0);
+    }
+
+    #[test]
+    fn dispersion_bends_each_channel_by_its_own_ior() {
+        let sphere = crate::Sphere::new(Vec3::ZERO, 1.0);
+        let incoming = Vec3::new(0.6, 0.0, -0.8);
+        let (ray, hit) = hit_at(1.0, incoming);
+        let refracted = |material: &Material, channel: Option<usize>| {
+            // Reseeding gives every call the same Fresnel draw; seeds that
+            // reflect instead of refracting are skipped.
+            (0..64)
+                .find_map(|seed| {
+                    crate::seed_rng(seed);
+                    let scatter = match channel {
+                        Some(c) => material.scatter_channel(&ray, &hit, &sphere, c),
+                        None => material.scatter(&ray, &hit, &sphere),
+                    }?;
+                    (scatter.ray.direction.z < 0.0).then_some(scatter.ray.direction)
+                })
+                .unwrap()
+        };
+
+        let glass = |dispersion| Material::Dielectric {
+            ior: 1.5,
+            absorption: Vec3::ZERO,
+            dispersion,
+        };
+        let equal = glass(Some(Vec3::splat(1.5)));
+        assert!(!equal.is_dispersive());
+        for c in 0..3 {
+            assert_eq!(refracted(&equal, Some(c)), refracted(&glass(None), None));
+        }
+
+        // Blue light has the highest IOR, so it bends furthest towards the
+        // normal and keeps the least of its sideways motion.
+        let prism = glass(Some(Vec3::new(1.45, 1.5, 1.55)));
+        assert!(prism.is_dispersive());
+        let sideways: Vec<FLOAT> = (0..3).map(|c| refracted(&prism, Some(c)).x).collect();
+        assert!(sideways[0] > sideways[1] && sideways[1] > sideways[2]);
+        assert!((sideways[2] - 0.6 / 1.55).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diffuse_bounces_leave_the_surface_they_start_on() {
+        let scene = crate::SceneBuilder::new()
+            .add(crate::Sphere::new(Vec3::new(0.0, 0.0, 4.0), 1.0))
+            .build();
+        crate::seed_rng(3);
+        for i in 0..16 {
+            let target = Vec3::new(
+                (i % 4) as FLOAT * 0.4 - 0.6,
+                (i / 4) as FLOAT * 0.4 - 0.6,
+                4.0,
+            );
+            let hit = crate::raycast(&Ray::new(Vec3::ZERO, target.normalize()), &scene).unwrap();
+            for _ in 0..32 {
+                let bounce = diffuse_ray(&hit);
+                assert!(bounce.direction.dot(hit.normal) >= 0.0);
+                // A lone sphere is convex, so nothing lies above its surface.
+                assert!(crate::raycast(&bounce, &scene).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn checkerboard_tiles_scatter_by_their_own_materials() {
+        let board = Material::Checkerboard {
+            a: Box::new(Material::Metal {
+                albedo: Vec3::splat(0.9),
+                roughness: 0.0,
+            }),
+            b: Box::new(Material::Diffuse {
+                albedo: Vec3::new(0.2, 0.4, 0.6),
+            }),
+            scale: 1.0,
+        };
+        let plane = crate::Plane::new(Vec3::Y, 0.0);
+        let direction = Vec3::new(1.0, -1.0, 0.0).normalize();
+        let tile = |x: FLOAT, z: FLOAT| {
+            let p = Vec3::new(x, 0.0, z);
+            (
+                Ray::new(p - direction, direction),
+                HitRecord::new(1.0, p, Vec3::Y, 0),
+            )
+        };
+
+        let (ray, metal) = tile(0.5, 0.5);
+        assert!(matches!(board.resolve(&metal), Material::Metal { .. }));
+        assert_eq!(board.albedo(&metal), Vec3::splat(0.9));
+        let mirrored = board.scatter(&ray, &metal, &plane).unwrap();
+        assert!(!mirrored.diffuse);
+        assert!((mirrored.ray.direction - Vec3::new(1.0, 1.0, 0.0).normalize()).length() < 1e-5);
+
+        // Diagonal neighbours share a colour; edge neighbours alternate.
+        for (x, z) in [(1.5, 0.5), (-0.5, 0.5), (0.5, -0.5)] {
+            let (ray, diffuse) = tile(x, z);
+            assert!(matches!(board.resolve(&diffuse), Material::Diffuse { .. }));
+            assert_eq!(board.albedo(&diffuse), Vec3::new(0.2, 0.4, 0.6));
+            // Bar the occasional reflection off the clear coat.
+            let scattered =
+                (0..32).filter(|_| board.scatter(&ray, &diffuse, &plane).unwrap().diffuse);
+            assert!(scattered.count() > 24);
+        }
+        assert!(matches!(
+            board.resolve(&tile(1.5, 1.5).1),
+            Material::Metal { .. }
+        ));
+    }
+
+    #[test]
+    fn brushed_highlights_stretch_along_the_tangent() {
+        let brushed = Material::Anisotropic {
+            albedo: Vec3::ONE,
+            tangent: Vec3::X,
+            roughness: Vec2::new(0.4, 0.1),
+        };
+        let plane = crate::Plane::new(Vec3::Z, 0.0);
+        // Grazing across the brushing, where the mirror direction is
+        // nearly in the surface.
+        let direction = Vec3::new(0.0, 0.9, -0.3).normalize();
+        let (ray, hit) = hit_at(2.0, direction);
+        let mirror = reflect(direction, Vec3::Z);
+        crate::seed_rng(11);
+        let (mut along, mut across, mut count) = (0.0, 0.0, 0);
+        for _ in 0..512 {
+            if let Some(scatter) = brushed.scatter(&ray, &hit, &plane) {
+                let spread = scatter.ray.direction.normalize() - mirror;
+                along += spread.dot(Vec3::X).abs();
+                across += spread.dot(Vec3::Y).abs() + spread.dot(Vec3::Z).abs();
+                count += 1;
+            }
+        }
+        assert!(count > 256);
+        assert!(along > across * 2.0, "{along} {across}");
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rayon::prelude::*;
+
+use crate::{Aabb, FLOAT, Scene, Vec3};
+
+/// The six tetrahedra sharing the cube diagonal from corner 0 to corner 7.
+/// Corner `i` sits at offset `(i & 1, (i >> 1) & 1, (i >> 2) & 1)`.
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 7, 1, 3],
+    [0, 7, 3, 2],
+    [0, 7, 2, 6],
+    [0, 7, 6, 4],
+    [0, 7, 4, 5],
+    [0, 7, 5, 1],
+];
+
+struct Mesher {
+    points: Vec<Vec3>,
+    values: Vec<FLOAT>,
+    vertices: Vec<Vec3>,
+    triangles: Vec<[u32; 3]>,
+    edges: HashMap<(usize, usize), u32>,
+}
+
+impl Mesher {
+    fn edge_vertex(&mut self, a: usize, b: usize) -> u32 {
+        let key = (a.min(b), a.max(b));
+        if let Some(&index) = self.edges.get(&key) {
+            return index;
+        }
+        let (va, vb) = (self.values[a], self.values[b]);
+        let t = va / (va - vb);
+        let p = self.points[a] + (self.points[b] - self.points[a]) * t;
+        let index = self.vertices.len() as u32;
+        self.vertices.push(p);
+        self.edges.insert(key, index);
+        index
+    }
+
+    fn centroid(&self, corners: &[usize]) -> Vec3 {
+        corners.iter().map(|&c| self.points[c]).sum::<Vec3>() / corners.len() as FLOAT
+    }
+
+    /// Emits a triangle wound so its normal points along `outward`.
+    fn triangle(&mut self, a: u32, b: u32, c: u32, outward: Vec3) {
+        let (pa, pb, pc) = (
+            self.vertices[a as usize],
+            self.vertices[b as usize],
+            self.vertices[c as usize],
+        );
+        if (pb - pa).cross(pc - pa).dot(outward) >= 0.0 {
+            self.triangles.push([a, b, c]);
+        } else {
+            self.triangles.push([a, c, b]);
+        }
+    }
+
+    fn tetrahedron(&mut self, corners: [usize; 4]) {
+        let (inside, outside): (Vec<usize>, Vec<usize>) =
+            corners.iter().partition(|&&c| self.values[c] < 0.0);
+        let outward = self.centroid(&outside) - self.centroid(&inside);
+        match (inside.as_slice(), outside.as_slice()) {
+            ([i], [o0, o1, o2]) => {
+                let a = self.edge_vertex(*i, *o0);
+                let b = self.edge_vertex(*i, *o1);
+                let c = self.edge_vertex(*i, *o2);
+                self.triangle(a, b, c, outward);
+            }
+            ([i0, i1, i2], [o]) => {
+                let a = self.edge_vertex(*o, *i0);
+                let b = self.edge_vertex(*o, *i1);
+                let c = self.edge_vertex(*o, *i2);
+                self.triangle(a, b, c, outward);
+            }
+            ([i0, i1], [o0, o1]) => {
+                let a = self.edge_vertex(*i0, *o0);
+                let b = self.edge_vertex(*i0, *o1);
+                let c = self.edge_vertex(*i1, *o1);
+                let d = self.edge_vertex(*i1, *o0);
+                self.triangle(a, b, c, outward);
+                self.triangle(a, c, d, outward);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Extracts the zero isosurface of `scene` within `bounds` as an indexed
+/// triangle mesh, sampling the distance field on a grid of `resolution` cells
+/// per axis. Each cube is split into six tetrahedra, which avoids the
+/// ambiguous cases of classic marching cubes and its large case table while
+/// producing a watertight mesh.
+pub fn to_mesh(scene: &Scene, bounds: Aabb, resolution: usize) -> (Vec<Vec3>, Vec<[u32; 3]>) {
+    let Aabb { min, max } = bounds;
+    let n = resolution + 1;
+    let cell = (max - min) / resolution as FLOAT;
+    let index = |x: usize, y: usize, z: usize| x + y * n + z * n * n;
+
+    let points: Vec<Vec3> = (0..n * n * n)
+        .map(|i| {
+            min + cell
+                * Vec3::new(
+                    (i % n) as FLOAT,
+                    ((i / n) % n) as FLOAT,
+                    (i / (n * n)) as FLOAT,
+                )
+        })
+        .collect();
+    let values = points.par_iter().map(|&p| scene.distance(p)).collect();
+
+    let mut mesher = Mesher {
+        points,
+        values,
+        vertices: Vec::new(),
+        triangles: Vec::new(),
+        edges: HashMap::new(),
+    };
+    for z in 0..resolution {
+        for y in 0..resolution {
+            for x in 0..resolution {
+                let cube: [usize; 8] = std::array::from_fn(|c| {
+                    index(x + (c & 1), y + ((c >> 1) & 1), z + ((c >> 2) & 1))
+                });
+                for tetrahedron in TETRAHEDRA {
+                    mesher.tetrahedron(tetrahedron.map(|c| cube[c]));
+                }
+            }
+        }
+    }
+    (mesher.vertices, mesher.triangles)
+}
+
+/// Writes an indexed triangle mesh as a Wavefront OBJ.
+pub fn write_obj<W: Write>(
+    mut writer: W,
+    vertices: &[Vec3],
+    triangles: &[[u32; 3]],
+) -> io::Result<()> {
+    for v in vertices {
+        writeln!(writer, "v {} {} {}", v.x, v.y, v.z)?;
+    }
+    for [a, b, c] in triangles {
+        writeln!(writer, "f {} {} {}", a + 1, b + 1, c + 1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SceneBuilder, Sphere};
+
+    #[test]
+    fn sphere_mesh_is_closed_and_lies_on_the_surface() {
+        let scene = SceneBuilder::new()
+            .add(Sphere::new(Vec3::ZERO, 1.0))
+            .build();
+        let bounds = Aabb::from_center(Vec3::ZERO, Vec3::splat(1.5));
+        let resolution = 16;
+        let (vertices, triangles) = to_mesh(&scene, bounds, resolution);
+        assert!(!triangles.is_empty());
+
+        let cell = 3.0 / resolution as FLOAT;
+        for v in &vertices {
+            assert!((v.length() - 1.0).abs() < 0.1 * cell);
+        }
+
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for &[a, b, c] in &triangles {
+            let (pa, pb, pc) = (
+                vertices[a as usize],
+                vertices[b as usize],
+                vertices[c as usize],
+            );
+            assert!((pb - pa).cross(pc - pa).dot(pa + pb + pc) >= 0.0);
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((u.min(v), u.max(v))).or_default() += 1;
+            }
+        }
+        assert!(edges.values().all(|&count| count == 2));
+    }
+}
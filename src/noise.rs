@@ -0,0 +1,123 @@
+use crate::{FLOAT, Vec3};
+
+/// Gradient noise with a fixed seed, so the same point and seed always give
+/// the same value. Different seeds give unrelated patterns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Noise {
+    pub seed: u32,
+}
+
+/// [`Noise::perlin3`] with the default seed.
+pub fn perlin3(p: Vec3) -> FLOAT {
+    Noise::default().perlin3(p)
+}
+
+/// [`Noise::fbm3`] with the default seed.
+pub fn fbm3(p: Vec3, octaves: usize) -> FLOAT {
+    Noise::default().fbm3(p, octaves)
+}
+
+/// Ken Perlin's improved-noise gradients: the midpoints of a cube's edges.
+const GRADIENTS: [Vec3; 12] = [
+    Vec3::new(1.0, 1.0, 0.0),
+    Vec3::new(-1.0, 1.0, 0.0),
+    Vec3::new(1.0, -1.0, 0.0),
+    Vec3::new(-1.0, -1.0, 0.0),
+    Vec3::new(1.0, 0.0, 1.0),
+    Vec3::new(-1.0, 0.0, 1.0),
+    Vec3::new(1.0, 0.0, -1.0),
+    Vec3::new(-1.0, 0.0, -1.0),
+    Vec3::new(0.0, 1.0, 1.0),
+    Vec3::new(0.0, -1.0, 1.0),
+    Vec3::new(0.0, 1.0, -1.0),
+    Vec3::new(0.0, -1.0, -1.0),
+];
+
+impl Noise {
+    pub fn new(seed: u32) -> Noise {
+        Noise { seed }
+    }
+
+    /// Perlin gradient noise at `p`, in `[-1, 1]`. It is zero at every
+    /// integer lattice point and varies smoothly, with continuous first and
+    /// second derivatives, over features about one unit across.
+    pub fn perlin3(&self, p: Vec3) -> FLOAT {
+        let cell = p.floor();
+        let f = p - cell;
+        let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let gradient = GRADIENTS[self.hash(x + dx, y + dy, z + dz) as usize % 12];
+            gradient.dot(f - Vec3::new(dx as FLOAT, dy as FLOAT, dz as FLOAT))
+        };
+        let u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+        let lerp = |a: FLOAT, b: FLOAT, t: FLOAT| a + (b - a) * t;
+        let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u.x);
+        let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u.x);
+        let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u.x);
+        let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u.x);
+        let y0 = lerp(x00, x10, u.y);
+        let y1 = lerp(x01, x11, u.y);
+        lerp(y0, y1, u.z).clamp(-1.0, 1.0)
+    }
+
+    /// Fractal Brownian motion: `octaves` layers of [`Noise::perlin3`], each
+    /// at twice the frequency and half the amplitude of the one before,
+    /// normalised back into `[-1, 1]`. More octaves add finer detail.
+    pub fn fbm3(&self, p: Vec3, octaves: usize) -> FLOAT {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for octave in 0..octaves {
+            // Each octave is offset and reseeded so the lattice zeros of the
+            // layers do not line up.
+            let layer = Noise::new(self.seed.wrapping_add(octave as u32));
+            sum += amplitude * layer.perlin3(p * frequency + Vec3::splat(octave as FLOAT * 0.37));
+            total += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+
+    fn hash(&self, x: i32, y: i32, z: i32) -> u32 {
+        let mut h = self.seed ^ 0x9e37_79b9;
+        for v in [x, y, z] {
+            h = (h ^ v as u32).wrapping_mul(0x85eb_ca6b);
+            h ^= h >> 13;
+            h = h.wrapping_mul(0xc2b2_ae35);
+            h ^= h >> 16;
+        }
+        h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> impl Iterator<Item = Vec3> {
+        (0..512).map(|i| {
+            let i = i as FLOAT;
+            Vec3::new(i * 0.173 - 40.0, (i * 0.619).sin() * 9.0, i * -0.0917 + 3.3)
+        })
+    }
+
+    #[test]
+    fn noise_is_deterministic_and_bounded() {
+        let (noise, other) = (Noise::new(7), Noise::new(8));
+        let mut differs = false;
+        for p in points() {
+            let value = noise.perlin3(p);
+            assert_eq!(value, Noise::new(7).perlin3(p));
+            assert!((-1.0..=1.0).contains(&value));
+            let fractal = noise.fbm3(p, 5);
+            assert_eq!(fractal, noise.fbm3(p, 5));
+            assert!((-1.0..=1.0).contains(&fractal));
+            differs |= value != other.perlin3(p);
+        }
+        assert!(differs);
+        assert_eq!(perlin3(Vec3::new(3.0, -2.0, 5.0)), 0.0);
+        assert_eq!(fbm3(Vec3::ONE, 0), 0.0);
+    }
+}
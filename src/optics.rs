@@ -0,0 +1,73 @@
+use crate::{Vec3, FLOAT};
+
+/// Mirrors `v` about the surface with unit normal `n`.
+pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
+    v - 2.0 * v.dot(n) * n
+}
+
+/// Refracts the unit direction `v` through a surface with unit normal `n`
+/// facing against `v`, where `eta` is the ratio of the refractive index on
+/// the incoming side to the one on the far side. Returns `None` on total
+/// internal reflection.
+pub fn refract(v: Vec3, n: Vec3, eta: FLOAT) -> Option<Vec3> {
+    let cos_theta = (-v).dot(n).min(1.0);
+    let k = 1.0 - eta * eta * (1.0 - cos_theta * cos_theta);
+    (k >= 0.0).then(|| eta * v + (eta * cos_theta - k.sqrt()) * n)
+}
+
+/// Flips `n` if needed so it faces against `direction`.
+pub fn face_forward(n: Vec3, direction: Vec3) -> Vec3 {
+    if n.dot(direction) > 0.0 { -n } else { n }
+}
+
+/// Schlick's approximation of the Fresnel reflectance of a surface with
+/// reflectance `f0` at normal incidence, for light arriving at an angle whose
+/// cosine to the normal is `cos_theta`. Rises to 1 at grazing angles.
+pub fn fresnel_schlick(cos_theta: FLOAT, f0: Vec3) -> Vec3 {
+    f0 + (Vec3::ONE - f0) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresnel_rises_to_one_at_grazing_angles() {
+        let f0 = Vec3::splat(0.04);
+        assert_eq!(fresnel_schlick(1.0, f0), f0);
+        let mut last = 0.0;
+        for i in (0..=10).rev() {
+            let r = fresnel_schlick(i as FLOAT / 10.0, f0).x;
+            assert!(r >= last);
+            last = r;
+        }
+        assert!((fresnel_schlick(0.0, f0).x - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reflection_mirrors_about_the_normal() {
+        let v = Vec3::new(1.0, -1.0, 0.0);
+        assert_eq!(reflect(v, Vec3::Y), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(reflect(-Vec3::Z, Vec3::Z), Vec3::Z);
+    }
+
+    #[test]
+    fn refraction_follows_snells_law() {
+        let eta = 1.0 / 1.5;
+        let v = Vec3::new(0.6, -0.8, 0.0);
+        let r = refract(v, Vec3::Y, eta).unwrap();
+        assert!((r.length() - 1.0).abs() < 1e-6);
+        // sin of the incident and refracted angles are 0.6 and 0.6 * eta.
+        assert!((r.x - 0.6 * eta).abs() < 1e-6);
+        assert!(r.y < 0.0 && r.z == 0.0);
+        assert!((refract(-Vec3::Y, Vec3::Y, eta).unwrap() - -Vec3::Y).length() < 1e-6);
+    }
+
+    #[test]
+    fn steep_exits_reflect_totally() {
+        // Leaving glass at 45 degrees exceeds the critical angle of about 42.
+        let v = Vec3::new(1.0, -1.0, 0.0).normalize();
+        assert_eq!(refract(v, Vec3::Y, 1.5), None);
+        assert!(refract(Vec3::new(0.6, -0.8, 0.0), Vec3::Y, 1.5).is_some());
+    }
+}
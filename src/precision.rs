@@ -0,0 +1,47 @@
+//! The scalar and vector types the renderer is built on. They are single
+//! precision by default; the `f64` feature switches them all to double
+//! precision, trading speed for accuracy in large scenes and deep fractals
+//! where `f32` rounding shows up as banding.
+
+#[cfg(not(feature = "f64"))]
+mod types {
+    pub use std::f32::consts;
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub type FLOAT = f32;
+    pub type Vec2 = glam::Vec2;
+    pub type Vec3 = glam::Vec3A;
+    pub type BVec3 = glam::BVec3A;
+    pub type Mat3 = glam::Mat3A;
+    pub type Quat = glam::Quat;
+    pub type Affine3 = glam::Affine3A;
+
+    pub fn quat_from_mat3(m: &Mat3) -> Quat {
+        Quat::from_mat3a(m)
+    }
+}
+
+#[cfg(feature = "f64")]
+mod types {
+    pub use std::f64::consts;
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub type FLOAT = f64;
+    pub type Vec2 = glam::DVec2;
+    pub type Vec3 = glam::DVec3;
+    pub type BVec3 = glam::BVec3;
+    pub type Mat3 = glam::DMat3;
+    pub type Quat = glam::DQuat;
+    pub type Affine3 = glam::DAffine3;
+
+    pub fn quat_from_mat3(m: &Mat3) -> Quat {
+        Quat::from_mat3(m)
+    }
+}
+
+pub use types::*;
+
+/// Applies `affine` to `point`.
+pub fn transform_point(affine: &Affine3, point: Vec3) -> Vec3 {
+    affine.matrix3 * point + affine.translation
+}
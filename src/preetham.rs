@@ -0,0 +1,147 @@
+use crate::consts::{FRAC_PI_2, PI};
+use crate::{FLOAT, Vec3};
+
+/// The Preetham, Shirley and Smits analytic daylight model. Sky color
+/// depends on the angle to the zenith and to the sun, and on the
+/// atmosphere's turbidity: about 2 for a very clear day, 3 for a typical
+/// clear sky and 6 or more for hazy conditions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preetham {
+    pub sun_direction: Vec3,
+    pub turbidity: FLOAT,
+    /// Multiplies the model's luminance, which is in kcd/m², to bring it
+    /// into the range of the rest of the scene.
+    pub exposure: FLOAT,
+    /// Perez coefficients A to E for luminance Y and chromaticity x and y.
+    coefficients: [[FLOAT; 5]; 3],
+    /// Zenith Y, x and y divided by the Perez function at the zenith, so
+    /// scaling the Perez function by them gives the sky at any direction.
+    zenith: [FLOAT; 3],
+}
+
+/// Typical scale from the model's kcd/m² to this renderer's radiance.
+const DEFAULT_EXPOSURE: FLOAT = 0.04;
+
+impl Preetham {
+    pub fn new(sun_direction: Vec3, turbidity: FLOAT) -> Preetham {
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity;
+        let coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        // Angle of the sun from the zenith, kept just above the horizon.
+        let theta_s = sun_direction.y.clamp(0.0, 1.0).acos().min(FRAC_PI_2 - 0.01);
+        let (t1, t2, t3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let zenith_x = t * t * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * t1)
+            + t * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * t1 + 0.00394)
+            + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * t1 + 0.25886);
+        let zenith_xy = t * t * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * t1)
+            + t * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * t1 + 0.00516)
+            + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * t1 + 0.26688);
+        let zenith = [zenith_y, zenith_x, zenith_xy];
+        let zenith = std::array::from_fn(|i| zenith[i] / perez(&coefficients[i], 1.0, theta_s));
+        Preetham {
+            sun_direction,
+            turbidity,
+            exposure: DEFAULT_EXPOSURE,
+            coefficients,
+            zenith,
+        }
+    }
+
+    /// A sky with the sun `elevation` radians above the horizon, in the
+    /// direction `azimuth` radians clockwise from -Z when seen from above.
+    pub fn from_elevation(elevation: FLOAT, azimuth: FLOAT, turbidity: FLOAT) -> Preetham {
+        let direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        Preetham::new(direction, turbidity)
+    }
+
+    /// Linear sRGB radiance of the sky seen along `direction`. Directions
+    /// below the horizon see the sky just above it.
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+        let cos_theta = direction.y.max(0.01);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [y, x, yy] = std::array::from_fn(|i| {
+            self.zenith[i] * perez(&self.coefficients[i], cos_theta, gamma)
+        });
+        xyy_to_rgb(y, x, yy) * self.exposure
+    }
+}
+
+/// The Perez sky distribution for a direction `theta` from the zenith, given
+/// by its cosine, and `gamma` from the sun.
+fn perez(c: &[FLOAT; 5], cos_theta: FLOAT, gamma: FLOAT) -> FLOAT {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+/// Converts luminance `luminance` and chromaticity (`x`, `y`) to linear sRGB.
+fn xyy_to_rgb(luminance: FLOAT, x: FLOAT, y: FLOAT) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::ZERO;
+    }
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    Vec3::new(
+        3.2406 * cx - 1.5372 * luminance - 0.4986 * cz,
+        -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz,
+        0.0557 * cx - 0.2040 * luminance + 1.0570 * cz,
+    )
+    .max(Vec3::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::luminance;
+
+    #[test]
+    fn sky_brightens_toward_the_sun() {
+        let elevation = 0.5;
+        let sky = Preetham::from_elevation(elevation, 0.0, 3.0);
+        let at = |azimuth: FLOAT| {
+            let direction = Vec3::new(
+                elevation.cos() * azimuth.sin(),
+                elevation.sin(),
+                -elevation.cos() * azimuth.cos(),
+            );
+            luminance(sky.radiance(direction))
+        };
+        // Brightness falls away from the sun until about a right angle from
+        // it, past which the Perez model brightens slightly again.
+        let toward_sun: Vec<FLOAT> = [0.1, 0.4, 0.8, 1.2].into_iter().map(at).collect();
+        assert!(
+            toward_sun.windows(2).all(|pair| pair[0] > pair[1]),
+            "{toward_sun:?}"
+        );
+        assert!(at(0.1) > 2.0 * at(PI));
+        assert!(sky.radiance(Vec3::Y).min_element() > 0.0);
+    }
+}
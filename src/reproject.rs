@@ -0,0 +1,137 @@
+use crate::camera::Camera;
+use crate::{FLOAT, Vec3, primary_ray};
+
+/// Seeds are pulled back towards the camera by this fraction of their
+/// distance, so a slightly nearer surface in the new view is still found.
+const SEED_MARGIN: FLOAT = 0.05;
+
+/// The world-space primary hit of every pixel of the last reprojected
+/// frame, and the camera it was seen from. Reprojecting the points into the
+/// next view gives each pixel a distance to start marching from, which
+/// skips most of the empty space in front of the surface. Surfaces that
+/// move or appear in front of a cached point are missed until the pixel's
+/// seed fails, so fast motion can ghost.
+#[derive(Default)]
+pub struct HitCache {
+    camera: Option<Camera>,
+    width: usize,
+    height: usize,
+    points: Vec<Option<Vec3>>,
+}
+
+impl HitCache {
+    pub fn new() -> HitCache {
+        HitCache::default()
+    }
+
+    /// Forgets the cached frame, e.g. after the scene changes.
+    pub fn clear(&mut self) {
+        self.camera = None;
+        self.points.clear();
+    }
+
+    /// Replaces the cache with the hits of a `width` x `height` frame seen
+    /// from `camera`, in row-major order.
+    pub fn store(
+        &mut self,
+        camera: &Camera,
+        width: usize,
+        height: usize,
+        points: Vec<Option<Vec3>>,
+    ) {
+        self.camera = Some(camera.clone());
+        self.width = width;
+        self.height = height;
+        self.points = points;
+    }
+
+    /// Per-pixel distances along the primary rays of `camera` at which to
+    /// start marching, or `None` where no cached point lands. Nothing is
+    /// seeded if the cache is empty or was recorded at another resolution.
+    pub fn seeds(&self, camera: &Camera, width: usize, height: usize) -> Vec<Option<FLOAT>> {
+        let mut seeds = vec![None; width * height];
+        if self.camera.is_none() || self.width != width || self.height != height {
+            return seeds;
+        }
+        for point in self.points.iter().flatten() {
+            let Some((x, y)) = reproject(*point, camera, width, height) else {
+                continue;
+            };
+            let ray = primary_ray(camera, x, y, width, height, None);
+            let t = (*point - ray.position).dot(ray.direction) * (1.0 - SEED_MARGIN);
+            let seed = &mut seeds[y * width + x];
+            // Keep the nearest point when several land on one pixel.
+            if t > 0.0 && seed.is_none_or(|s| t < s) {
+                *seed = Some(t);
+            }
+        }
+        seeds
+    }
+}
+
+/// The pixel of a `width` x `height` view from `camera` that `point` falls
+/// in, or `None` if it is off screen or behind the camera.
+pub fn reproject(
+    point: Vec3,
+    camera: &Camera,
+    width: usize,
+    height: usize,
+) -> Option<(usize, usize)> {
+    let (u, v) = camera.project(point, width as FLOAT / height as FLOAT)?;
+    let x = (u + 1.0) * 0.5 * width as FLOAT;
+    let y = (1.0 - v) * 0.5 * height as FLOAT;
+    if x < 0.0 || y < 0.0 || x >= width as FLOAT || y >= height as FLOAT {
+        return None;
+    }
+    Some((x as usize, y as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SceneBuilder, Sphere, raycast};
+
+    #[test]
+    fn static_camera_reprojects_hits_to_their_own_pixels() {
+        let scene = SceneBuilder::new()
+            .add(Sphere::new(Vec3::new(0.3, -0.2, 4.0), 1.5))
+            .build();
+        let camera = Camera::default();
+        let (width, height) = (16, 12);
+        let hits: Vec<Option<(FLOAT, Vec3)>> = (0..width * height)
+            .map(|i| {
+                let ray = primary_ray(&camera, i % width, i / width, width, height, None);
+                raycast(&ray, &scene).map(|hit| (hit.t, hit.p))
+            })
+            .collect();
+        assert!(hits.iter().any(Option::is_some) && hits.iter().any(Option::is_none));
+
+        let mut cache = HitCache::new();
+        cache.store(
+            &camera,
+            width,
+            height,
+            hits.iter().map(|hit| hit.map(|(_, p)| p)).collect(),
+        );
+        let seeds = cache.seeds(&camera, width, height);
+        for (i, (hit, seed)) in hits.iter().zip(&seeds).enumerate() {
+            match (hit, seed) {
+                (Some((t, p)), Some(seed)) => {
+                    assert_eq!(
+                        reproject(*p, &camera, width, height),
+                        Some((i % width, i / width))
+                    );
+                    assert!((seed - t * (1.0 - SEED_MARGIN)).abs() < 1e-3);
+                }
+                (None, None) => {}
+                _ => panic!("pixel {} was seeded differently from its hit", i),
+            }
+        }
+        assert!(
+            cache
+                .seeds(&camera, width * 2, height)
+                .iter()
+                .all(Option::is_none)
+        );
+    }
+}
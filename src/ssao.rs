@@ -0,0 +1,111 @@
+use crate::{FLOAT, Vec3};
+
+/// Screen-space ambient occlusion settings. A cheap stand-in for tracing
+/// occlusion rays: creases and contact points are darkened by comparing
+/// each pixel's depth with its neighbours in the depth buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ssao {
+    /// How far in pixels to look for occluding neighbours.
+    pub radius: usize,
+    /// Occlusion of a fully enclosed pixel, from 0 (off) to 1 (black).
+    pub strength: FLOAT,
+    /// Depth differences below this, in world units, are ignored to avoid
+    /// self-occlusion from noise and curvature.
+    pub bias: FLOAT,
+    /// Depth differences above this are treated as separate surfaces that
+    /// are too far apart to occlude each other.
+    pub range: FLOAT,
+}
+
+impl Default for Ssao {
+    fn default() -> Ssao {
+        Ssao {
+            radius: 4,
+            strength: 1.0,
+            bias: 0.02,
+            range: 0.5,
+        }
+    }
+}
+
+/// Pixel offsets of half the sample pairs; each is mirrored for the other.
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+/// The fraction of light reaching each pixel, from 1 (unoccluded) down to
+/// `1 - strength`, for a `width` x `height` buffer rendered in
+/// [`RenderMode::Depth`](crate::RenderMode::Depth) with the given far clip.
+///
+/// Each pair of opposite neighbours is compared with the center pixel. On a
+/// flat surface, even a slanted one, the center lies midway between them;
+/// a center further away than their midpoint sits in a crease or behind a
+/// nearby object and counts as occluded.
+pub fn occlusion(
+    depth: &[Vec3],
+    width: usize,
+    height: usize,
+    far_clip: FLOAT,
+    settings: &Ssao,
+) -> Vec<FLOAT> {
+    let distance = |x: usize, y: usize| (1.0 - depth[y * width + x].x) * far_clip;
+    let sample = |x: usize, y: usize, dx: isize, dy: isize| {
+        let sx = x.checked_add_signed(dx).filter(|&sx| sx < width)?;
+        let sy = y.checked_add_signed(dy).filter(|&sy| sy < height)?;
+        Some(distance(sx, sy))
+    };
+    (0..width * height)
+        .map(|pos| {
+            let (x, y) = (pos % width, pos / width);
+            let center = distance(x, y);
+            let mut pairs = 0;
+            let mut occluded = 0;
+            for step in [settings.radius.div_ceil(2), settings.radius] {
+                let step = step as isize;
+                for (dx, dy) in DIRECTIONS {
+                    let a = sample(x, y, dx * step, dy * step);
+                    let b = sample(x, y, -dx * step, -dy * step);
+                    if let (Some(a), Some(b)) = (a, b) {
+                        pairs += 1;
+                        let gap = center - 0.5 * (a + b);
+                        if gap > settings.bias && gap < settings.range {
+                            occluded += 1;
+                        }
+                    }
+                }
+            }
+            if pairs == 0 {
+                return 1.0;
+            }
+            1.0 - settings.strength * occluded as FLOAT / pairs as FLOAT
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_depth_is_unoccluded() {
+        let (width, height) = (16, 12);
+        let flat = vec![Vec3::splat(0.4); width * height];
+        let slanted: Vec<Vec3> = (0..width * height)
+            .map(|pos| {
+                Vec3::splat(0.2 + 0.01 * (pos % width) as FLOAT + 0.02 * (pos / width) as FLOAT)
+            })
+            .collect();
+        for depth in [flat, slanted] {
+            let ao = occlusion(&depth, width, height, 10.0, &Ssao::default());
+            assert!(ao.iter().all(|&a| (a - 1.0).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn a_pit_is_occluded() {
+        let (width, height) = (9, 9);
+        let mut depth = vec![Vec3::splat(0.5); width * height];
+        depth[4 * width + 4] = Vec3::splat(0.48);
+        let ao = occlusion(&depth, width, height, 10.0, &Ssao::default());
+        assert!(ao[4 * width + 4] < 0.5);
+        assert_eq!(ao[0], 1.0);
+    }
+}
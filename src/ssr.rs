@@ -0,0 +1,171 @@
+use crate::camera::Camera;
+use crate::optics::reflect;
+use crate::{FLOAT, Rect, Vec3};
+
+/// Screen-space reflection settings. Reflections on metal surfaces are
+/// looked up in the finished frame by marching the reflected ray through
+/// the depth of the primary hits, which is much cheaper than tracing them,
+/// but can only show what is already on screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ssr {
+    /// Steps taken along the reflected ray before giving up.
+    pub steps: usize,
+    /// Length of each step in world units.
+    pub step_size: FLOAT,
+    /// How far behind the surface in a pixel the ray may pass and still
+    /// count as hitting it, in world units.
+    pub thickness: FLOAT,
+    /// How much of the screen-space reflection replaces the traced color,
+    /// from 0 (off) to 1.
+    pub strength: FLOAT,
+}
+
+impl Default for Ssr {
+    fn default() -> Ssr {
+        Ssr {
+            steps: 64,
+            step_size: 0.1,
+            thickness: 0.2,
+            strength: 0.5,
+        }
+    }
+}
+
+/// The primary hit seen through a pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surface {
+    pub position: Vec3,
+    pub normal: Vec3,
+    /// Whether the surface takes screen-space reflections.
+    pub reflective: bool,
+}
+
+/// Marches from `origin` along the unit `direction` and returns the index
+/// of the first pixel whose surface the ray passes just behind, or `None`
+/// if the ray leaves the screen or runs out of steps. `surfaces` holds the
+/// primary hit of each pixel of a `width` x `height` frame seen by `camera`.
+pub fn reflected_pixel(
+    origin: Vec3,
+    direction: Vec3,
+    camera: &Camera,
+    surfaces: &[Option<Surface>],
+    width: usize,
+    height: usize,
+    settings: &Ssr,
+) -> Option<usize> {
+    let forward = camera.basis().2;
+    let depth = |p: Vec3| (p - camera.position).dot(forward);
+    let aspect_ratio = width as FLOAT / height as FLOAT;
+    (1..=settings.steps).find_map(|step| {
+        let p = origin + direction * (step as FLOAT * settings.step_size);
+        let (u, v) = camera.project(p, aspect_ratio)?;
+        let x = ((u + 1.0) * 0.5 * width as FLOAT).floor();
+        let y = ((1.0 - v) * 0.5 * height as FLOAT).floor();
+        if x < 0.0 || y < 0.0 || x >= width as FLOAT || y >= height as FLOAT {
+            return None;
+        }
+        let index = y as usize * width + x as usize;
+        let behind = depth(p) - depth(surfaces[index]?.position);
+        (behind > 0.0 && behind < settings.thickness).then_some(index)
+    })
+}
+
+/// Mixes screen-space reflections into `color` at every reflective pixel
+/// inside `region` whose reflected ray finds a surface on screen within
+/// `region`, which is all of the frame that holds rendered colors. The rest
+/// of the frame is left as it is.
+pub fn apply(
+    color: &mut [Vec3],
+    surfaces: &[Option<Surface>],
+    camera: &Camera,
+    width: usize,
+    height: usize,
+    region: Rect,
+    settings: &Ssr,
+) {
+    let source = color.to_vec();
+    let inside = |index: usize| region.contains(index % width, index / width);
+    for (i, (pixel, surface)) in color.iter_mut().zip(surfaces).enumerate() {
+        let Some(surface) = surface.filter(|surface| surface.reflective && inside(i)) else {
+            continue;
+        };
+        let view = (surface.position - camera.position).normalize();
+        let direction = reflect(view, surface.normal);
+        let origin = surface.position + surface.normal * (settings.step_size * 0.5);
+        if let Some(index) =
+            reflected_pixel(origin, direction, camera, surfaces, width, height, settings)
+            && inside(index)
+        {
+            *pixel = pixel.lerp(source[index], settings.strength);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5 x 5 frame seeing only a surface at depth 5 through its center.
+    fn center_surface() -> Vec<Option<Surface>> {
+        let mut surfaces = vec![None; 25];
+        surfaces[12] = Some(Surface {
+            position: Vec3::new(0.0, 0.0, 5.0),
+            normal: -Vec3::Z,
+            reflective: false,
+        });
+        surfaces
+    }
+
+    #[test]
+    fn reflected_ray_reads_the_pixel_it_passes_behind() {
+        let camera = Camera::default();
+        let settings = Ssr::default();
+        let surfaces = center_surface();
+        let origin = Vec3::new(0.0, 0.0, 2.0);
+        let hit = reflected_pixel(origin, Vec3::Z, &camera, &surfaces, 5, 5, &settings);
+        assert_eq!(hit, Some(12));
+        let away = reflected_pixel(origin, Vec3::X, &camera, &surfaces, 5, 5, &settings);
+        assert_eq!(away, None);
+    }
+
+    #[test]
+    fn reflections_stay_inside_the_region() {
+        let camera = Camera::default();
+        let settings = Ssr {
+            strength: 1.0,
+            ..Ssr::default()
+        };
+        let mut surfaces = center_surface();
+        // A mirror one pixel to the left of the center, further away and
+        // turned to reflect the view back across the center surface.
+        surfaces[12].as_mut().unwrap().position.z = 3.0;
+        let position = Vec3::new(-2.0, 0.0, 5.0);
+        let view = position.normalize();
+        let reflected = (Vec3::new(0.0, 0.0, 3.1) - position).normalize();
+        surfaces[11] = Some(Surface {
+            position,
+            normal: (reflected - view).normalize(),
+            reflective: true,
+        });
+        let mut color: Vec<Vec3> = (0..25).map(|i| Vec3::splat(i as FLOAT)).collect();
+        let full = Rect {
+            x: 0,
+            y: 0,
+            width: 5,
+            height: 5,
+        };
+        let mut reflected = color.clone();
+        apply(&mut reflected, &surfaces, &camera, 5, 5, full, &settings);
+        let changed: Vec<usize> = (0..25).filter(|&i| reflected[i] != color[i]).collect();
+        assert_eq!(changed, [11]);
+        assert_eq!(reflected[11], color[12]);
+        let left = Rect {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 5,
+        };
+        apply(&mut color, &surfaces, &camera, 5, 5, left, &settings);
+        assert!((0..25).all(|i| color[i] == Vec3::splat(i as FLOAT)));
+    }
+}
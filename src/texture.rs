@@ -0,0 +1,344 @@
+use std::path::Path;
+
+use crate::{FLOAT, HitRecord, Vec2, Vec3};
+
+/// A checkerboard across the XZ plane with square cells of size `scale`.
+pub struct Checker {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub scale: FLOAT,
+}
+
+impl Checker {
+    /// Returns the checker color at `p`, box-filtered over a square of side
+    /// `footprint` (in world units). The box integral of the square wave has
+    /// a closed form, so distant tiles fade to the average of `a` and `b`
+    /// instead of aliasing; a zero footprint gives hard edges.
+    pub fn color(&self, p: Vec3, footprint: FLOAT) -> Vec3 {
+        let x = p.x / self.scale;
+        let z = p.z / self.scale;
+        let w = footprint / self.scale + 0.001;
+        let fx = filtered_square_wave(x, w);
+        let fz = filtered_square_wave(z, w);
+        let mix = 0.5 - 0.5 * fx * fz;
+        self.a + (self.b - self.a) * mix
+    }
+}
+
+/// Box filter of width `w` applied to a square wave of period 2 that is +1
+/// on even cells and -1 on odd ones.
+fn filtered_square_wave(x: FLOAT, w: FLOAT) -> FLOAT {
+    let tri = |x: FLOAT| ((x * 0.5).rem_euclid(1.0) - 0.5).abs();
+    2.0 * (tri(x - 0.5 * w) - tri(x + 0.5 * w)) / w
+}
+
+/// A procedural bump map: a height field of product-of-sines bumps used to
+/// perturb shading normals without changing the geometry.
+pub struct Bump {
+    pub amplitude: FLOAT,
+    pub frequency: FLOAT,
+}
+
+impl Bump {
+    pub fn height(&self, p: Vec3) -> FLOAT {
+        let q = p * self.frequency;
+        self.amplitude * q.x.sin() * q.y.sin() * q.z.sin()
+    }
+
+    fn gradient(&self, p: Vec3) -> Vec3 {
+        let q = p * self.frequency;
+        let sin = Vec3::new(q.x.sin(), q.y.sin(), q.z.sin());
+        let cos = Vec3::new(q.x.cos(), q.y.cos(), q.z.cos());
+        self.amplitude
+            * self.frequency
+            * Vec3::new(
+                cos.x * sin.y * sin.z,
+                sin.x * cos.y * sin.z,
+                sin.x * sin.y * cos.z,
+            )
+    }
+
+    /// Tilts `normal` against the part of the height gradient that lies in
+    /// the surface's tangent plane.
+    pub fn perturb(&self, normal: Vec3, p: Vec3) -> Vec3 {
+        if self.amplitude == 0.0 {
+            return normal;
+        }
+        let gradient = self.gradient(p);
+        let tangential = gradient - normal * gradient.dot(normal);
+        (normal - tangential).normalize()
+    }
+
+    pub fn apply(&self, hit: &mut HitRecord) {
+        hit.normal = self.perturb(hit.normal, hit.p);
+    }
+}
+
+/// Restricts shading normals to a few directions, so smooth surfaces shade
+/// as flat facets for a low-poly look. The geometry is unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlatShading {
+    /// Snaps the normal to the nearest of the six axis directions.
+    Axis,
+    /// Rounds each component of the normal to a multiple of `1 / levels`
+    /// before renormalizing; more levels give smaller facets.
+    Quantize(usize),
+}
+
+impl FlatShading {
+    pub fn flatten(&self, normal: Vec3) -> Vec3 {
+        match *self {
+            FlatShading::Axis => {
+                let a = normal.abs();
+                let axis = if a.x >= a.y && a.x >= a.z {
+                    Vec3::X
+                } else if a.y >= a.z {
+                    Vec3::Y
+                } else {
+                    Vec3::Z
+                };
+                axis * axis.dot(normal).signum()
+            }
+            FlatShading::Quantize(levels) => {
+                let levels = levels.max(1) as FLOAT;
+                ((normal * levels).round() / levels).normalize_or(normal)
+            }
+        }
+    }
+
+    pub fn apply(&self, hit: &mut HitRecord) {
+        hit.normal = self.flatten(hit.normal);
+    }
+}
+
+/// How texture coordinates outside `[0, 1]` are mapped back onto an
+/// [`ImageTexture`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Wrap {
+    /// Tiles the image, so planes and boxes repeat it in every cell.
+    #[default]
+    Repeat,
+    /// Extends the edge texels outwards.
+    Clamp,
+}
+
+/// A color image addressed by [`HitRecord::uv`], with `(0, 0)` at the top
+/// left corner and `(1, 1)` at the bottom right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageTexture {
+    pixels: Vec<Vec3>,
+    width: usize,
+    height: usize,
+    pub wrap: Wrap,
+}
+
+impl ImageTexture {
+    /// `pixels` holds `width` x `height` colors in row-major order.
+    pub fn new(pixels: Vec<Vec3>, width: usize, height: usize, wrap: Wrap) -> ImageTexture {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height);
+        ImageTexture {
+            pixels,
+            width,
+            height,
+            wrap,
+        }
+    }
+
+    /// Loads an image with [`load_image`](crate::export::load_image).
+    pub fn load<P: AsRef<Path>>(path: P, wrap: Wrap) -> image::ImageResult<ImageTexture> {
+        let (pixels, width, height) = crate::export::load_image(path)?;
+        Ok(ImageTexture::new(pixels, width, height, wrap))
+    }
+
+    /// The color at `uv`, bilinearly interpolated between the four nearest
+    /// texel centers. Sampling exactly at a texel center returns that texel.
+    pub fn sample(&self, uv: Vec2) -> Vec3 {
+        let x = uv.x * self.width as FLOAT - 0.5;
+        let y = uv.y * self.height as FLOAT - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |dx: FLOAT, dy: FLOAT| {
+            let column = self.wrap.index(x0 + dx, self.width);
+            let row = self.wrap.index(y0 + dy, self.height);
+            self.pixels[row * self.width + column]
+        };
+        let top = texel(0.0, 0.0).lerp(texel(1.0, 0.0), fx);
+        let bottom = texel(0.0, 1.0).lerp(texel(1.0, 1.0), fx);
+        top.lerp(bottom, fy)
+    }
+}
+
+impl Wrap {
+    fn index(self, i: FLOAT, size: usize) -> usize {
+        match self {
+            Wrap::Repeat => (i as i64).rem_euclid(size as i64) as usize,
+            Wrap::Clamp => i.clamp(0.0, (size - 1) as FLOAT) as usize,
+        }
+    }
+}
+
+/// Projects an image onto a surface from the three axis directions and
+/// blends the projections by how squarely the surface faces each axis, so
+/// any shape can be textured without UV coordinates. The image repeats
+/// every `scale` world units.
+pub struct Triplanar {
+    pub image: ImageTexture,
+    pub scale: FLOAT,
+    /// How quickly the blend switches between projections as the normal
+    /// turns; 1 blends broadly, higher values give narrower seams.
+    pub sharpness: FLOAT,
+}
+
+impl Triplanar {
+    /// The weights of the projections along X, Y and Z for a surface with
+    /// unit normal `normal`, summing to 1.
+    pub fn weights(&self, normal: Vec3) -> Vec3 {
+        let a = normal.abs();
+        let w = Vec3::new(
+            a.x.powf(self.sharpness),
+            a.y.powf(self.sharpness),
+            a.z.powf(self.sharpness),
+        );
+        w / w.element_sum().max(FLOAT::MIN_POSITIVE)
+    }
+
+    /// The blended color at `p` on a surface with unit normal `normal`.
+    pub fn color(&self, p: Vec3, normal: Vec3) -> Vec3 {
+        let q = p / self.scale;
+        let w = self.weights(normal);
+        self.image.sample(Vec2::new(q.z, q.y)) * w.x
+            + self.image.sample(Vec2::new(q.x, q.z)) * w.y
+            + self.image.sample(Vec2::new(q.x, q.y)) * w.z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_fades_to_the_average_with_a_wide_footprint() {
+        let checker = Checker {
+            a: Vec3::ONE,
+            b: Vec3::ZERO,
+            scale: 1.0,
+        };
+        assert!((checker.color(Vec3::new(0.5, 0.0, 0.5), 0.0) - checker.a).length() < 0.01);
+        assert!((checker.color(Vec3::new(1.5, 0.0, 0.5), 0.0) - checker.b).length() < 0.01);
+        let average = (checker.a + checker.b) * 0.5;
+        let error = |footprint: FLOAT| {
+            (checker.color(Vec3::new(0.5, 0.0, 0.5), footprint) - average).length()
+        };
+        assert!(error(4.0) < error(1.5));
+        assert!(error(40.0) < 0.05);
+    }
+
+    #[test]
+    fn zero_amplitude_bump_leaves_the_normal_unchanged() {
+        let flat = Bump {
+            amplitude: 0.0,
+            frequency: 8.0,
+        };
+        let bumpy = Bump {
+            amplitude: 0.2,
+            frequency: 8.0,
+        };
+        let normal = Vec3::new(0.3, 0.8, -0.5).normalize();
+        let p = Vec3::new(0.1, 0.7, 0.3);
+        assert_eq!(flat.perturb(normal, p), normal);
+        let tilted = bumpy.perturb(normal, p);
+        assert!(tilted.is_normalized() && (tilted - normal).length() > 0.01);
+    }
+
+    #[test]
+    fn image_texture_returns_texels_at_their_centers() {
+        let pixels: Vec<Vec3> = (0..6).map(|i| Vec3::splat(i as FLOAT)).collect();
+        let image = ImageTexture::new(pixels.clone(), 3, 2, Wrap::Repeat);
+        for (i, pixel) in pixels.iter().enumerate() {
+            let uv = Vec2::new(
+                ((i % 3) as FLOAT + 0.5) / 3.0,
+                ((i / 3) as FLOAT + 0.5) / 2.0,
+            );
+            assert!((image.sample(uv) - *pixel).length() < 1e-5);
+        }
+        // Halfway between two texel centers gives their average.
+        let between = image.sample(Vec2::new(1.0 / 3.0, 0.25));
+        assert!((between - Vec3::splat(0.5)).length() < 1e-5);
+    }
+
+    #[test]
+    fn wrap_modes_handle_out_of_range_uvs() {
+        let pixels: Vec<Vec3> = (0..4).map(|i| Vec3::splat(i as FLOAT)).collect();
+        let repeat = ImageTexture::new(pixels.clone(), 2, 2, Wrap::Repeat);
+        let clamp = ImageTexture::new(pixels, 2, 2, Wrap::Clamp);
+        let inside = Vec2::new(0.25, 0.75);
+        for offset in [Vec2::new(1.0, 0.0), Vec2::new(-2.0, 3.0)] {
+            assert!((repeat.sample(inside + offset) - repeat.sample(inside)).length() < 1e-5);
+        }
+        // Far outside the image, clamping holds on to the nearest corner.
+        assert_eq!(clamp.sample(Vec2::new(-5.0, -5.0)), Vec3::splat(0.0));
+        assert_eq!(clamp.sample(Vec2::new(5.0, -5.0)), Vec3::splat(1.0));
+        assert_eq!(clamp.sample(Vec2::new(-5.0, 5.0)), Vec3::splat(2.0));
+        assert_eq!(clamp.sample(Vec2::new(5.0, 5.0)), Vec3::splat(3.0));
+        // Across the right edge, repeating blends back into the first column.
+        let seam = repeat.sample(Vec2::new(1.0, 0.25));
+        assert!((seam - Vec3::splat(0.5)).length() < 1e-5);
+    }
+
+    #[test]
+    fn triplanar_weights_favour_the_facing_projection() {
+        let pixels: Vec<Vec3> = (0..16).map(|i| Vec3::splat(i as FLOAT / 15.0)).collect();
+        let triplanar = Triplanar {
+            image: ImageTexture::new(pixels, 4, 4, Wrap::Repeat),
+            scale: 2.0,
+            sharpness: 4.0,
+        };
+        for normal in [
+            Vec3::X,
+            Vec3::new(0.3, -0.8, 0.5).normalize(),
+            Vec3::new(-1.0, 1.0, 1.0).normalize(),
+        ] {
+            assert!((triplanar.weights(normal).element_sum() - 1.0).abs() < 1e-6);
+        }
+        let tilted = triplanar.weights(Vec3::new(0.9, 0.3, 0.3).normalize());
+        assert!(tilted.x > 0.9 && tilted.x > 10.0 * tilted.y.max(tilted.z));
+        assert_eq!(triplanar.weights(Vec3::X), Vec3::X);
+
+        // Facing +X, the color is the image projected along X, onto (z, y).
+        let p = Vec3::new(0.3, 0.5, 1.1);
+        let expected = triplanar
+            .image
+            .sample(Vec2::new(p.z, p.y) / triplanar.scale);
+        assert!((triplanar.color(p, Vec3::X) - expected).length() < 1e-6);
+        let hit = HitRecord::new(1.0, p, Vec3::X, 0);
+        let material = crate::Material::Triplanar(triplanar);
+        assert!((material.albedo(&hit) - expected).length() < 1e-6);
+    }
+
+    #[test]
+    fn flat_shading_snaps_normals_to_a_few_directions() {
+        let normals: Vec<Vec3> = (0..200)
+            .map(|i| {
+                let i = i as FLOAT;
+                Vec3::new((i * 0.71).sin(), (i * 1.37).cos(), (i * 0.29).sin() - 0.2).normalize()
+            })
+            .collect();
+        for &normal in &normals {
+            let axis = FlatShading::Axis.flatten(normal);
+            assert_eq!(axis.abs().element_sum(), 1.0);
+            assert_eq!(axis.abs().max_element(), 1.0);
+            // The axis it snaps to is the one the normal leans along most.
+            assert_eq!(axis.dot(normal), normal.abs().max_element());
+        }
+        let mut facets = Vec::new();
+        for &normal in &normals {
+            let facet = FlatShading::Quantize(2).flatten(normal);
+            assert!(facet.is_normalized() && facet.dot(normal) > 0.7);
+            if !facets.iter().any(|&f: &Vec3| (f - facet).length() < 1e-6) {
+                facets.push(facet);
+            }
+        }
+        // Components in steps of a half give at most 5^3 - 1 directions.
+        assert!(facets.len() > 6 && facets.len() < 124, "{}", facets.len());
+    }
+}
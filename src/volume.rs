@@ -0,0 +1,131 @@
+use crate::consts::PI;
+use crate::{Aabb, FLOAT, Ray, Scene, Vec3, random_float};
+
+/// Samples taken along each span of a ray through a volume.
+const STEPS: usize = 32;
+
+/// Isotropic phase function: scattered light leaves evenly in all directions.
+const PHASE: FLOAT = 1.0 / (4.0 * PI);
+
+/// A box filled with an even participating medium such as fog or smoke.
+/// Light crossing it is dimmed by `exp(-density * distance)`, and light from
+/// the scene's point lights is scattered towards the viewer along the way.
+/// Occluders between a light and the medium cast visible shafts through it.
+pub struct Volume {
+    pub bounds: Aabb,
+    /// Extinction per world unit; higher values give thicker fog.
+    pub density: FLOAT,
+    /// The fraction of extinguished light that is scattered rather than
+    /// absorbed, per channel.
+    pub albedo: Vec3,
+}
+
+impl Volume {
+    pub fn new(bounds: Aabb, density: FLOAT, albedo: Vec3) -> Volume {
+        Volume {
+            bounds,
+            density,
+            albedo,
+        }
+    }
+
+    /// Single scattering along `ray` through the medium, up to `t_max` along
+    /// the ray: the light scattered towards the ray's origin, and the
+    /// fraction of light from beyond that makes it through. Light reaching
+    /// each sample is dimmed by the medium between it and the light.
+    pub fn integrate(&self, scene: &Scene, ray: &Ray, t_max: FLOAT) -> (Vec3, FLOAT) {
+        let Some((enter, exit)) = self.bounds.intersect_ray(ray) else {
+            return (Vec3::ZERO, 1.0);
+        };
+        let (start, end) = (enter.max(ray.t_min), exit.min(t_max));
+        if end <= start || self.density <= 0.0 {
+            return (Vec3::ZERO, 1.0);
+        }
+        let dt = (end - start) / STEPS as FLOAT;
+        let step_transmittance = (-self.density * dt * ray.direction.length()).exp();
+        let jitter = random_float();
+        let mut radiance = Vec3::ZERO;
+        let mut transmittance = 1.0;
+        for i in 0..STEPS {
+            let p = ray.position + ray.direction * (start + (i as FLOAT + jitter) * dt);
+            let light = self.in_scattering(scene, p);
+            radiance += self.albedo * light * (transmittance * (1.0 - step_transmittance));
+            transmittance *= step_transmittance;
+        }
+        (radiance, transmittance)
+    }
+
+    /// The light from the scene's point lights scattered towards any one
+    /// direction at `p`.
+    fn in_scattering(&self, scene: &Scene, p: Vec3) -> Vec3 {
+        scene
+            .lights
+            .iter()
+            .map(|light| {
+                let to_light = light.position - p;
+                let distance = to_light.length();
+                let shadow = Ray::new(p, to_light / distance).with_range(0.0, distance);
+                if scene.ray_intersects(&shadow) {
+                    return Vec3::ZERO;
+                }
+                let inside = self
+                    .bounds
+                    .intersect_ray(&shadow)
+                    .map_or(0.0, |(enter, exit)| exit.min(distance) - enter.max(0.0));
+                light.emission(-shadow.direction)
+                    * ((-self.density * inside.max(0.0)).exp() * PHASE)
+                    / (distance * distance)
+            })
+            .fold(Vec3::ZERO, |sum, e| sum + e)
+    }
+}
+
+/// The light arriving at the origin of `ray` when `color` is seen at
+/// distance `t` through every volume in the scene. Volumes are composited
+/// from the furthest in, so the ones in front dim those behind them.
+pub(crate) fn composite(scene: &Scene, ray: &Ray, t: FLOAT, color: Vec3) -> Vec3 {
+    let mut spans: Vec<(FLOAT, &Volume)> = scene
+        .volumes
+        .iter()
+        .filter_map(|volume| {
+            let (enter, _) = volume.bounds.intersect_ray(ray)?;
+            (enter < t).then_some((enter, volume))
+        })
+        .collect();
+    spans.sort_by(|a, b| b.0.total_cmp(&a.0));
+    spans.into_iter().fold(color, |color, (_, volume)| {
+        let (radiance, transmittance) = volume.integrate(scene, ray, t);
+        radiance + color * transmittance
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transmittance_falls_with_density_and_distance() {
+        let scene = Scene::new();
+        let fog = |density: FLOAT| {
+            Volume::new(
+                Aabb::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 10.0)),
+                density,
+                Vec3::ONE,
+            )
+        };
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::Z);
+        let through = |density: FLOAT, t_max: FLOAT| fog(density).integrate(&scene, &ray, t_max).1;
+        for (density, t_max) in [(0.1, 4.0), (0.5, 4.0), (0.5, 8.0)] {
+            // The ray enters the box one unit along.
+            let measured = through(density, t_max);
+            assert!((measured - (-density * (t_max - 1.0)).exp()).abs() < 1e-4);
+        }
+        assert!(through(0.5, 4.0) < through(0.1, 4.0));
+        assert!(through(0.5, 8.0) < through(0.5, 4.0));
+        // Past the far side of the box there is nothing more to cross.
+        assert!((through(0.5, 30.0) - through(0.5, 11.0)).abs() < 1e-6);
+        assert_eq!(through(0.0, 8.0), 1.0);
+        // With no lights, the fog only dims.
+        assert_eq!(fog(0.5).integrate(&scene, &ray, 8.0).0, Vec3::ZERO);
+    }
+}
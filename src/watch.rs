@@ -0,0 +1,77 @@
+//! Live reloading of a scene file written in the [`csg`](crate::csg)
+//! language, so a scene can be edited while the renderer shows it.
+
+use std::path::{Path, PathBuf};
+
+use crate::Scene;
+use crate::csg::{ParseError, parse_scene};
+
+/// Watches a scene file by polling it, which needs no platform support and
+/// is cheap for files this small; call [`SceneWatcher::poll`] once a frame.
+pub struct SceneWatcher {
+    path: PathBuf,
+    /// The text last seen, whether or not it parsed.
+    text: Option<String>,
+}
+
+impl SceneWatcher {
+    /// Watches `path`. The first poll loads whatever the file holds.
+    pub fn new<P: AsRef<Path>>(path: P) -> SceneWatcher {
+        SceneWatcher {
+            path: path.as_ref().to_path_buf(),
+            text: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rereads the file and, if its text changed since the last poll, parses
+    /// it and passes the new scene to `on_reload`. Returns whether the scene
+    /// was reloaded, or the parse error of a changed file; each change is
+    /// reported once, so a broken file is only complained about until it is
+    /// saved again. A file that cannot be read, say while an editor is
+    /// replacing it, counts as unchanged.
+    pub fn poll<F: FnOnce(Scene)>(&mut self, on_reload: F) -> Result<bool, ParseError> {
+        let Ok(text) = std::fs::read_to_string(&self.path) else {
+            return Ok(false);
+        };
+        if self.text.as_deref() == Some(text.as_str()) {
+            return Ok(false);
+        }
+        let scene = parse_scene(&text);
+        self.text = Some(text);
+        on_reload(scene?);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+
+    #[test]
+    fn modifying_the_file_reloads_the_scene() {
+        let path = std::env::temp_dir().join(format!("sdf-watch-{}.csg", std::process::id()));
+        std::fs::write(&path, "sphere(1)").unwrap();
+        let mut watcher = SceneWatcher::new(&path);
+        let mut surface = None;
+        let mut reload = |watcher: &mut SceneWatcher| {
+            watcher.poll(|scene| surface = Some(scene.distance(Vec3::new(0.5, 0.0, 0.0))))
+        };
+        assert_eq!(reload(&mut watcher), Ok(true));
+        assert_eq!(reload(&mut watcher), Ok(false));
+        std::fs::write(&path, "box(0.25)").unwrap();
+        assert_eq!(reload(&mut watcher), Ok(true));
+        // A broken edit is reported once, then left alone until fixed.
+        std::fs::write(&path, "sphere(").unwrap();
+        assert!(reload(&mut watcher).is_err());
+        assert_eq!(reload(&mut watcher), Ok(false));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reload(&mut watcher), Ok(false));
+        let surface = surface.unwrap();
+        assert!((surface - 0.25).abs() < 1e-5);
+    }
+}